@@ -1,10 +1,39 @@
 #![no_std]
 
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
 use common::{
     config::{CLOCK_FREQ, TICKS_PER_SEC},
     constant::{MICRO_PER_SEC, MILLI_PER_SEC, NANO_PER_SEC},
 };
-use riscv::register::time;
+use riscv::register::{scounteren, sstatus, stvec, time};
+
+/// 是否支持 Sstc 扩展，即可以在 S 态直接写 `stimecmp` 设置定时器，而不需要 SBI 调用
+static HAS_SSTC: AtomicBool = AtomicBool::new(false);
+
+// 探测 Sstc 时使用的临时 trap 入口和探测函数。
+// 读 `stimecmp` 时如果触发了非法指令异常，trap 入口会跳过该指令并将返回值置为 0
+core::arch::global_asm!(
+    "
+    .section .text
+    .align 2
+__sstc_probe_trap:
+    csrr t0, sepc
+    addi t0, t0, 4
+    csrw sepc, t0
+    li a0, 0
+    sret
+
+    .align 2
+__sstc_probe:
+    li a0, 1
+    csrr t1, 0x14d
+    ret
+"
+);
 
 /// 应当是返回时钟次数。应该是从开机或者复位算起。
 #[inline]
@@ -14,22 +43,74 @@ pub fn get_time() -> usize {
     time::read()
 }
 
+// 先乘后除的话，中间结果在开机约 1844 秒后就会溢出，因此直接按每个时钟次数的纳秒数
+// 或者每微秒、毫秒的时钟次数换算，这要求能够整除。这也和 vDSO 中的计算方式一致
+const _: () = assert!(NANO_PER_SEC % CLOCK_FREQ == 0);
+const _: () = assert!(CLOCK_FREQ % MICRO_PER_SEC == 0);
+
 #[inline]
 pub fn get_time_ns() -> usize {
-    time::read() * NANO_PER_SEC / CLOCK_FREQ
+    time::read() * (NANO_PER_SEC / CLOCK_FREQ)
 }
 
 #[inline]
 pub fn get_time_us() -> usize {
-    time::read() * MICRO_PER_SEC / CLOCK_FREQ
+    time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
 }
 
 #[inline]
 pub fn get_time_ms() -> usize {
-    time::read() * MILLI_PER_SEC / CLOCK_FREQ
+    time::read() / (CLOCK_FREQ / MILLI_PER_SEC)
+}
+
+/// 纳秒转换为时钟次数，向上取整
+#[inline]
+pub fn ns_to_ticks(ns: usize) -> usize {
+    ns.div_ceil(NANO_PER_SEC / CLOCK_FREQ)
+}
+
+/// 忙碌的 hart 的调度时间片长度，以时钟次数计
+#[inline]
+pub fn quantum_ticks() -> usize {
+    CLOCK_FREQ / TICKS_PER_SEC
+}
+
+/// 探测是否支持 Sstc 扩展，并允许用户态读取 `time` CSR（vDSO 需要）。应当在关中断时由每个 hart 调用一次
+pub fn init() {
+    unsafe { scounteren::set_tm() };
+    extern "C" {
+        fn __sstc_probe_trap();
+        fn __sstc_probe() -> usize;
+    }
+    // SAFETY: 探测期间关中断，只有读 `stimecmp` 可能触发异常，由临时 trap 入口处理
+    let has_sstc = unsafe {
+        let sie = sstatus::read().sie();
+        sstatus::clear_sie();
+        let old_stvec = stvec::read();
+        stvec::write(__sstc_probe_trap as usize, stvec::TrapMode::Direct);
+        let has_sstc = __sstc_probe() != 0;
+        stvec::write(old_stvec.address(), old_stvec.trap_mode().unwrap());
+        if sie {
+            sstatus::set_sie();
+        }
+        has_sstc
+    };
+    HAS_SSTC.store(has_sstc, Ordering::Relaxed);
+}
+
+/// 是否支持 Sstc 扩展
+pub fn has_sstc() -> bool {
+    HAS_SSTC.load(Ordering::Relaxed)
 }
 
-/// 设置下一次定时器中断
-pub fn set_next_trigger() {
-    sbi_rt::set_timer((get_time() + CLOCK_FREQ / TICKS_PER_SEC) as u64);
+/// 设置定时器在时钟次数达到 `deadline` 时触发中断。已经过去的时刻会立即触发，`u64::MAX` 则不会触发
+pub fn set_timer(deadline: u64) {
+    if has_sstc() {
+        unsafe {
+            // 0x14d 即 `stimecmp`
+            asm!("csrw 0x14d, {}", in(reg) deadline);
+        }
+    } else {
+        sbi_rt::set_timer(deadline);
+    }
 }
@@ -0,0 +1,21 @@
+//! QEMU virt 平台上的 Goldfish RTC，提供自 Epoch 以来的纳秒数
+
+use core::ptr;
+
+use common::config::{PA_TO_VA, QEMU_RTC_ADDR};
+
+/// 时间的低 32 位。读取它时高 32 位会被锁存，因此要先读低位再读高位
+const TIME_LOW: usize = 0x00;
+/// 时间的高 32 位
+const TIME_HIGH: usize = 0x04;
+
+/// 读取自 Epoch 以来的纳秒数
+pub fn read_ns() -> u64 {
+    let base = PA_TO_VA + QEMU_RTC_ADDR;
+    // SAFETY: RTC 的寄存器已经映射在内核地址空间中，读取没有副作用（除了锁存高位）
+    unsafe {
+        let low = ptr::read_volatile((base + TIME_LOW) as *const u32);
+        let high = ptr::read_volatile((base + TIME_HIGH) as *const u32);
+        (u64::from(high) << 32) | u64::from(low)
+    }
+}
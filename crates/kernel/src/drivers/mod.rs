@@ -1,3 +1,4 @@
+pub mod goldfish_rtc;
 pub mod qemu_block;
 pub mod qemu_plic;
 pub mod qemu_uart;
@@ -1,62 +1,383 @@
+mod sched;
 mod yield_now;
 
-use core::{future::Future, task::Poll};
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{cmp::Reverse, future::Future, sync::atomic::AtomicUsize, task::Poll};
 
-use async_task::{Runnable, Task};
+use async_task::Task;
 use atomic::Ordering;
-use common::config::TASK_LIMIT;
-use crossbeam_queue::ArrayQueue;
-use klocks::Lazy;
+use common::config::{LOCAL_TASK_QUEUE_CAPACITY, MAX_HART_NUM};
+use crossbeam_queue::SegQueue;
+use crossbeam_utils::CachePadded;
+use klocks::{Lazy, SpinNoIrqMutex};
+use triomphe::Arc;
 pub use yield_now::yield_now;
 
-use crate::SHUTDOWN;
+use self::sched::MIN_GRANULARITY_NS;
+pub use self::sched::{SchedEntity, SchedPolicy, ALL_HARTS, RR_TIMESLICE_NS};
+use crate::{
+    hart::{self, local_hart},
+    time, SHUTDOWN,
+};
+
+/// 带有调度信息的任务
+pub type Runnable = async_task::Runnable<Arc<SchedEntity>>;
 
 static TASK_QUEUE: Lazy<TaskQueue> = Lazy::new(TaskQueue::new);
 
-/// NOTE: 目前的实现中，并发的任务量是有硬上限 (`TASK_LIMIT`) 的，超过会直接 panic
+/// 每取这么多次任务，就优先从全局队列中取一次，避免全局队列中的任务饿死
+const GLOBAL_QUEUE_INTERVAL: usize = 61;
+
+/// 因为没有任务而挂起的 hart 的掩码，第 i 位为 1 表示 hart i 空闲
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// 任务队列。每个 hart 有自己的本地队列，另有一个无上限的全局队列。
+///
+/// 任务被唤醒时放入当前 hart 的本地队列，放不下则放入全局队列。
+/// hart 优先从本地队列取任务，其次是全局队列，都为空时从其他 hart 的本地队列中窃取。
+/// 任何时候都只会取出亲和性允许在本 hart 上运行的任务
 struct TaskQueue {
-    queue: ArrayQueue<Runnable>,
+    locals: [CachePadded<LocalQueue>; MAX_HART_NUM],
+    global: SegQueue<Runnable>,
+}
+
+struct LocalQueue {
+    /// 唤醒任务可能发生在中断中，所以需要关中断
+    queue: SpinNoIrqMutex<RunQueue>,
+    /// 本 hart 取任务的次数
+    fetch_count: AtomicUsize,
+}
+
+/// hart 的本地运行队列。实时任务总是先于普通任务运行
+#[derive(Default)]
+struct RunQueue {
+    /// 实时任务，按优先级从高到低排列，同优先级的先进先出
+    realtime: BTreeMap<(Reverse<u8>, u64), Runnable>,
+    /// 普通任务，按虚拟运行时间从小到大排列，相同的先进先出
+    fair: BTreeMap<(u64, u64), Runnable>,
+    /// 递增的序号，用于区分键值相同的任务
+    seq: u64,
+    /// 出队的普通任务中最大的虚拟运行时间，单调不减。新加入的普通任务以它为基准
+    min_vruntime: u64,
+}
+
+impl RunQueue {
+    fn len(&self) -> usize {
+        self.realtime.len() + self.fair.len()
+    }
+
+    fn push(&mut self, runnable: Runnable) {
+        let entity = runnable.metadata();
+        self.seq += 1;
+        if entity.policy().is_realtime() {
+            self.realtime
+                .insert((Reverse(entity.rt_priority()), self.seq), runnable);
+        } else {
+            entity.place(self.min_vruntime);
+            self.fair.insert((entity.vruntime(), self.seq), runnable);
+        }
+    }
+
+    /// 取出允许在 hart `hart_id` 上运行的任务中最应该运行的一个
+    fn pop(&mut self, hart_id: usize) -> Option<Runnable> {
+        if let Some(runnable) = remove_first_allowed(&mut self.realtime, hart_id, false) {
+            return Some(runnable);
+        }
+        let runnable = remove_first_allowed(&mut self.fair, hart_id, false)?;
+        self.min_vruntime = self.min_vruntime.max(runnable.metadata().vruntime());
+        Some(runnable)
+    }
+
+    /// 取出允许在 hart `hart_id` 上运行的任务中最不急于运行的一个，用于被其他 hart 窃取
+    fn pop_last(&mut self, hart_id: usize) -> Option<Runnable> {
+        remove_first_allowed(&mut self.fair, hart_id, true)
+            .or_else(|| remove_first_allowed(&mut self.realtime, hart_id, true))
+    }
+
+    /// 是否有允许在 hart `hart_id` 上运行的任务
+    fn has_allowed(&self, hart_id: usize) -> bool {
+        self.realtime
+            .values()
+            .chain(self.fair.values())
+            .any(|runnable| runnable.metadata().allows(hart_id))
+    }
+
+    /// 移出调度实体为 `entity` 的任务
+    fn remove_entity(&mut self, entity: &Arc<SchedEntity>) -> Option<Runnable> {
+        remove_by_entity(&mut self.realtime, entity)
+            .or_else(|| remove_by_entity(&mut self.fair, entity))
+    }
+
+    /// 正在运行的任务 `curr` 是否应该让给队列中的任务
+    fn should_preempt(&self, curr: &SchedEntity) -> bool {
+        let highest_rt = self
+            .realtime
+            .first_key_value()
+            .map(|(&(Reverse(prio), _), _)| prio);
+        match curr.policy() {
+            SchedPolicy::Fifo => highest_rt.is_some_and(|prio| prio > curr.rt_priority()),
+            SchedPolicy::RoundRobin => highest_rt.is_some_and(|prio| {
+                prio > curr.rt_priority()
+                    || (prio == curr.rt_priority() && curr.slice_used() >= RR_TIMESLICE_NS)
+            }),
+            SchedPolicy::Normal => {
+                highest_rt.is_some()
+                    || (curr.slice_used() >= MIN_GRANULARITY_NS
+                        && self
+                            .fair
+                            .first_key_value()
+                            .is_some_and(|(&(vruntime, _), _)| vruntime < curr.vruntime()))
+            }
+        }
+    }
+}
+
+/// 按顺序（`rev` 为真时逆序）找到第一个允许在 hart `hart_id` 上运行的任务并将其移出
+fn remove_first_allowed<K: Ord + Copy>(
+    map: &mut BTreeMap<K, Runnable>,
+    hart_id: usize,
+    rev: bool,
+) -> Option<Runnable> {
+    let allowed =
+        |(&key, runnable): (&K, &Runnable)| runnable.metadata().allows(hart_id).then_some(key);
+    let key = if rev {
+        map.iter().rev().find_map(allowed)
+    } else {
+        map.iter().find_map(allowed)
+    }?;
+    map.remove(&key)
+}
+
+/// 找到调度实体为 `entity` 的任务并将其移出
+fn remove_by_entity<K: Ord + Copy>(
+    map: &mut BTreeMap<K, Runnable>,
+    entity: &Arc<SchedEntity>,
+) -> Option<Runnable> {
+    let key = map
+        .iter()
+        .find_map(|(&key, runnable)| Arc::ptr_eq(runnable.metadata(), entity).then_some(key))?;
+    map.remove(&key)
 }
 
 impl TaskQueue {
     fn new() -> Self {
         Self {
-            queue: ArrayQueue::new(TASK_LIMIT),
+            locals: core::array::from_fn(|_| {
+                CachePadded::new(LocalQueue {
+                    queue: SpinNoIrqMutex::new(RunQueue::default()),
+                    fetch_count: AtomicUsize::new(0),
+                })
+            }),
+            global: SegQueue::new(),
         }
     }
 
+    /// 将任务放入队列。如果任务不允许在当前 hart 上运行，则放入它允许的 hart 中任务最少的那个。
+    ///
+    /// 全局队列中的任务可能被任何 hart 取走，所以只有允许在所有已启动的 hart 上运行的任务
+    /// 才会因为本地队列已满而进入全局队列。全局队列先进先出，不考虑优先级，所以实时任务也总是放入本地队列
     fn push_task(&self, runnable: Runnable) {
-        self.queue.push(runnable).expect("Out of task limit");
+        let entity = runnable.metadata();
+        let hart_id = local_hart().hart_id();
+        let target = if entity.allows(hart_id) {
+            hart_id
+        } else {
+            (0..MAX_HART_NUM)
+                .filter(|&i| entity.allows(i) && hart::online_harts() & (1 << i) != 0)
+                .min_by_key(|&i| self.locals[i].queue.lock().len())
+                .unwrap_or(hart_id)
+        };
+        let mut local = self.locals[target].queue.lock();
+        let surplus = if local.len() < LOCAL_TASK_QUEUE_CAPACITY
+            || entity.policy().is_realtime()
+            || entity.affinity() & hart::online_harts() != hart::online_harts()
+        {
+            local.push(runnable);
+            local.len() > 1
+        } else {
+            drop(local);
+            self.global.push(runnable);
+            true
+        };
+        if target != hart_id {
+            // 目标 hart 可能正在挂起，也可能需要被新任务抢占
+            hart::send_ipi(1 << target);
+        } else if surplus {
+            // 还有其他任务在等待，唤醒一个空闲的 hart 来窃取
+            wake_one_idle_hart(hart_id);
+        }
+    }
+
+    /// 是否有可以在 hart `hart_id` 上直接运行的任务，不考虑窃取。
+    ///
+    /// 本地队列中的任务在入队后亲和性可能被修改，只计入仍允许在本 hart 上运行的，以免 hart 空转
+    fn has_task(&self, hart_id: usize) -> bool {
+        self.locals[hart_id].queue.lock().has_allowed(hart_id) || !self.global.is_empty()
+    }
+
+    /// 如果 `entity` 的任务正在不允许的 hart 的本地队列中，则将其重新放入队列
+    fn migrate(&self, entity: &Arc<SchedEntity>) {
+        for hart_id in (0..MAX_HART_NUM).filter(|&i| !entity.allows(i)) {
+            let runnable = self.locals[hart_id].queue.lock().remove_entity(entity);
+            if let Some(runnable) = runnable {
+                self.push_task(runnable);
+                return;
+            }
+        }
     }
 
     fn fetch_task(&self) -> Option<Runnable> {
-        self.queue.pop()
+        let hart_id = local_hart().hart_id();
+        let local = &self.locals[hart_id];
+        if local.fetch_count.fetch_add(1, Ordering::Relaxed) % GLOBAL_QUEUE_INTERVAL == 0 {
+            if let Some(runnable) = self.pop_global(hart_id) {
+                return Some(runnable);
+            }
+        }
+        // 先释放本地队列的锁，窃取时还要再获取
+        let runnable = local.queue.lock().pop(hart_id);
+        runnable
+            .or_else(|| self.pop_global(hart_id))
+            .or_else(|| self.steal(hart_id))
+    }
+
+    /// 从全局队列中取出任务。任务入队后亲和性可能被修改，不允许在当前 hart 上运行的任务会被重新放入队列
+    fn pop_global(&self, hart_id: usize) -> Option<Runnable> {
+        while let Some(runnable) = self.global.pop() {
+            if runnable.metadata().allows(hart_id) {
+                return Some(runnable);
+            }
+            self.push_task(runnable);
+        }
+        None
+    }
+
+    /// 从其他 hart 的本地队列中窃取允许在本 hart 上运行的任务。会取走目标队列中约一半的任务，
+    /// 其中最应该运行的一个返回，其余放入本地队列
+    fn steal(&self, hart_id: usize) -> Option<Runnable> {
+        // 从下一个 hart 开始依次尝试，以免所有 hart 都从同一个 hart 窃取
+        for victim in (1..MAX_HART_NUM).map(|i| (hart_id + i) % MAX_HART_NUM) {
+            // 不同时持有两个队列的锁，以免两个 hart 互相窃取时死锁
+            let (runnable, stolen) = {
+                let mut victim = self.locals[victim].queue.lock();
+                let Some(runnable) = victim.pop(hart_id) else {
+                    continue;
+                };
+                let n_steal = victim.len() / 2;
+                let stolen: Vec<_> = (0..n_steal)
+                    .map_while(|_| victim.pop_last(hart_id))
+                    .collect();
+                (runnable, stolen)
+            };
+            let mut local = self.locals[hart_id].queue.lock();
+            for stolen in stolen {
+                local.push(stolen);
+            }
+            return Some(runnable);
+        }
+        None
+    }
+
+    fn should_preempt(&self, curr: &SchedEntity) -> bool {
+        self.locals[local_hart().hart_id()]
+            .queue
+            .lock()
+            .should_preempt(curr)
+            || (curr.policy() == SchedPolicy::Normal
+                && curr.slice_used() >= MIN_GRANULARITY_NS
+                && !self.global.is_empty())
     }
 }
 
-pub fn spawn_with<F, A>(future: F, action: A) -> (Runnable, Task<F::Output>)
+pub fn spawn_with<F, A>(
+    future: F,
+    sched: Arc<SchedEntity>,
+    action: A,
+) -> (Runnable, Task<F::Output, Arc<SchedEntity>>)
 where
     F: Future + Send + 'static,
     F::Output: Send + 'static,
     A: Fn() + Send + Sync + 'static,
 {
     // TODO: 现在这么操作用于让用户线程被调度时状态设为 `Ready`，其实可能可以有更好的方式
-    async_task::spawn(future, move |runnable| {
-        action();
-        TASK_QUEUE.push_task(runnable);
-    })
+    async_task::Builder::new().metadata(sched).spawn(
+        move |_| future,
+        move |runnable| {
+            action();
+            TASK_QUEUE.push_task(runnable);
+        },
+    )
+}
+
+/// 内核任务的句柄，drop 时会取消该任务
+pub type KernelTask<T> = Task<T, Arc<SchedEntity>>;
+
+/// 创建一个内核任务并加入调度队列。
+///
+/// 内核任务不属于任何用户线程，运行时不能访问 `local_hart().curr_thread()`
+pub fn spawn_kernel_task<F>(future: F) -> KernelTask<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (runnable, task) = spawn_with(future, Arc::new(SchedEntity::default()), || {});
+    runnable.schedule();
+    task
+}
+
+/// 任务 `entity` 的亲和性被修改后调用。它若正在不再允许的 hart 上排队，则被移到允许的 hart 上，
+/// 否则那个 hart 取不到它，而允许的 hart 可能正挂起着
+pub fn migrate(entity: &Arc<SchedEntity>) {
+    TASK_QUEUE.migrate(entity);
+}
+
+/// 正在运行的任务 `curr` 是否应该让出 hart。在时钟中断时调用，同时会结算 `curr` 的运行时间
+pub fn should_preempt(curr: &SchedEntity) -> bool {
+    curr.update();
+    TASK_QUEUE.should_preempt(curr)
+}
+
+/// 唤醒除 `hart_id` 以外的一个空闲 hart
+fn wake_one_idle_hart(hart_id: usize) {
+    let idle = IDLE_HARTS.load(Ordering::Acquire) & !(1 << hart_id);
+    if idle != 0 {
+        hart::send_ipi(1 << idle.trailing_zeros());
+    }
+}
+
+/// 当前 hart 是否因为没有任务而挂起
+pub fn is_idle() -> bool {
+    IDLE_HARTS.load(Ordering::Relaxed) & (1 << local_hart().hart_id()) != 0
+}
+
+/// 唤醒所有空闲的 hart，如在系统关闭时让它们退出调度循环
+pub fn wake_idle_harts() {
+    hart::send_ipi(IDLE_HARTS.load(Ordering::Acquire));
 }
 
 pub fn run_utils_idle() {
+    let hart_id = local_hart().hart_id();
     loop {
         while let Some(task) = TASK_QUEUE.fetch_task() {
             trace!("Schedule new task");
+            let entity = Arc::clone(task.metadata());
+            entity.start_running();
             task.run();
+            entity.update();
         }
         if SHUTDOWN.load(Ordering::SeqCst) {
             break;
         }
-        sbi_rt::hart_suspend(sbi_rt::Retentive, 0, 0);
+        // 先标记为空闲再检查一次队列，这样在两者之间加入的任务要么被检查到，要么会发送 IPI。
+        // IPI 在挂起前到达也没关系，待处理的中断会让 hart 立即从挂起中恢复
+        IDLE_HARTS.fetch_or(1 << hart_id, Ordering::SeqCst);
+        if !TASK_QUEUE.has_task(hart_id) && !SHUTDOWN.load(Ordering::SeqCst) {
+            // 空闲时不需要调度时钟，只在有定时器到期时唤醒
+            time::program_timer(false);
+            sbi_rt::hart_suspend(sbi_rt::Retentive, 0, 0);
+        }
+        IDLE_HARTS.fetch_and(!(1 << hart_id), Ordering::SeqCst);
+        time::program_timer(true);
     }
 }
 
@@ -0,0 +1,209 @@
+use core::sync::atomic::{AtomicBool, AtomicI8, AtomicU64, AtomicU8, AtomicUsize};
+
+use atomic::{Atomic, Ordering};
+use common::config::MAX_HART_NUM;
+use defines::sched::{MAX_NICE, MIN_NICE, SCHED_FIFO, SCHED_OTHER, SCHED_RR};
+
+/// nice 值为 0 的任务的权重
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// nice 值 -20..=19 对应的权重，取自 Linux 的 `sched_prio_to_weight`。
+///
+/// nice 值每相差 1，获得的 CPU 时间大约相差 10%
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, // -20..=-16
+    29154, 23254, 18705, 14949, 11916, // -15..=-11
+    9548, 7620, 6100, 4904, 3906, // -10..=-6
+    3121, 2501, 1991, 1586, 1277, // -5..=-1
+    1024, 820, 655, 526, 423, // 0..=4
+    335, 272, 215, 172, 137, // 5..=9
+    110, 87, 70, 56, 45, // 10..=14
+    36, 29, 23, 18, 15, // 15..=19
+];
+
+/// 新唤醒的任务的虚拟运行时间最多比队列中的最小值少这么多（纳秒），
+/// 让睡眠的任务醒来后能较快运行，但又不至于长期霸占 CPU
+const SLEEPER_CREDIT_NS: u64 = 3_000_000;
+
+/// `SCHED_RR` 的时间片长度（纳秒）
+pub const RR_TIMESLICE_NS: u64 = 100_000_000;
+
+/// `SCHED_OTHER` 的任务至少连续运行这么久（纳秒）才会被其他普通任务抢占，以免同优先级的任务频繁切换。
+/// 取自 Linux 的 `sysctl_sched_min_granularity`
+pub(super) const MIN_GRANULARITY_NS: u64 = 750_000;
+
+/// 允许在所有 hart 上运行的亲和性掩码
+pub const ALL_HARTS: usize = usize::MAX >> (usize::BITS as usize - MAX_HART_NUM);
+
+/// 调度策略
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SchedPolicy {
+    /// `SCHED_OTHER`，按照以 nice 值加权的虚拟运行时间公平调度
+    Normal,
+    /// `SCHED_FIFO`，实时任务，不会因为时间片用完而让出
+    Fifo,
+    /// `SCHED_RR`，实时任务，时间片用完后让给同优先级的其他任务
+    RoundRobin,
+}
+
+unsafe impl bytemuck::NoUninit for SchedPolicy {}
+
+impl SchedPolicy {
+    pub fn from_user(policy: u32) -> Option<Self> {
+        match policy {
+            SCHED_OTHER => Some(Self::Normal),
+            SCHED_FIFO => Some(Self::Fifo),
+            SCHED_RR => Some(Self::RoundRobin),
+            _ => None,
+        }
+    }
+
+    pub fn to_user(self) -> u32 {
+        match self {
+            Self::Normal => SCHED_OTHER,
+            Self::Fifo => SCHED_FIFO,
+            Self::RoundRobin => SCHED_RR,
+        }
+    }
+
+    pub fn is_realtime(self) -> bool {
+        self != Self::Normal
+    }
+}
+
+/// 任务的调度信息，作为任务的元数据由执行器使用。
+///
+/// 各字段可能被不同的 hart 同时访问，所以都用原子变量。策略和优先级分开存储，
+/// 修改时可能短暂地读到不一致的组合，但这只会影响一次调度决策
+pub struct SchedEntity {
+    policy: Atomic<SchedPolicy>,
+    /// 实时优先级，实时策略下为 1..=99，否则为 0
+    rt_priority: AtomicU8,
+    /// nice 值，-20..=19，只对 `SCHED_OTHER` 有意义
+    nice: AtomicI8,
+    /// fork 时是否重置为 `SCHED_OTHER`，并将负的 nice 值重置为 0
+    reset_on_fork: AtomicBool,
+    /// 亲和性掩码，第 i 位为 1 表示允许在 hart i 上运行
+    affinity: AtomicUsize,
+    /// 虚拟运行时间（纳秒），即以 nice 值加权后的运行时间
+    vruntime: AtomicU64,
+    /// 上一次结算运行时间的时刻
+    exec_start: AtomicU64,
+    /// 自本次被调度以来连续运行的时间（纳秒），用于 `SCHED_RR` 的时间片
+    slice_used: AtomicU64,
+}
+
+impl Default for SchedEntity {
+    fn default() -> Self {
+        Self {
+            policy: Atomic::new(SchedPolicy::Normal),
+            rt_priority: AtomicU8::new(0),
+            nice: AtomicI8::new(0),
+            reset_on_fork: AtomicBool::new(false),
+            affinity: AtomicUsize::new(ALL_HARTS),
+            vruntime: AtomicU64::new(0),
+            exec_start: AtomicU64::new(0),
+            slice_used: AtomicU64::new(0),
+        }
+    }
+}
+
+impl SchedEntity {
+    /// 为 fork 或 clone 出的新任务复制调度信息。虚拟运行时间也会继承，以免新任务抢占过多 CPU
+    pub fn fork(&self) -> Self {
+        let entity = Self::default();
+        entity
+            .vruntime
+            .store(self.vruntime.load(Ordering::Relaxed), Ordering::Relaxed);
+        entity.set_affinity(self.affinity());
+        if self.reset_on_fork() {
+            entity.set_nice(self.nice().max(0));
+        } else {
+            entity.set_policy(self.policy(), self.rt_priority(), false);
+            entity.set_nice(self.nice());
+        }
+        entity
+    }
+
+    pub fn policy(&self) -> SchedPolicy {
+        self.policy.load(Ordering::Relaxed)
+    }
+
+    pub fn rt_priority(&self) -> u8 {
+        self.rt_priority.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_on_fork(&self) -> bool {
+        self.reset_on_fork.load(Ordering::Relaxed)
+    }
+
+    /// 设置调度策略和实时优先级。调用者需保证优先级与策略匹配
+    pub fn set_policy(&self, policy: SchedPolicy, rt_priority: u8, reset_on_fork: bool) {
+        self.policy.store(policy, Ordering::Relaxed);
+        self.rt_priority.store(rt_priority, Ordering::Relaxed);
+        self.reset_on_fork.store(reset_on_fork, Ordering::Relaxed);
+    }
+
+    pub fn nice(&self) -> i8 {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    /// 设置 nice 值，超出范围的会被截断到 -20..=19
+    pub fn set_nice(&self, nice: i8) {
+        self.nice
+            .store(nice.clamp(MIN_NICE, MAX_NICE), Ordering::Relaxed);
+    }
+
+    pub fn affinity(&self) -> usize {
+        self.affinity.load(Ordering::Relaxed)
+    }
+
+    /// 设置亲和性掩码。调用者需保证其中至少有一个已启动的 hart
+    pub fn set_affinity(&self, affinity: usize) {
+        self.affinity.store(affinity, Ordering::Relaxed);
+    }
+
+    /// 是否允许在 hart `hart_id` 上运行
+    pub fn allows(&self, hart_id: usize) -> bool {
+        self.affinity() & (1 << hart_id) != 0
+    }
+
+    fn weight(&self) -> u64 {
+        NICE_TO_WEIGHT[(self.nice() - MIN_NICE) as usize]
+    }
+
+    pub(super) fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    /// 任务加入队列时，根据队列的最小虚拟运行时间调整自己的虚拟运行时间
+    pub(super) fn place(&self, min_vruntime: u64) {
+        self.vruntime.fetch_max(
+            min_vruntime.saturating_sub(SLEEPER_CREDIT_NS),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// 任务被 hart 选中，开始运行
+    pub(super) fn start_running(&self) {
+        self.exec_start
+            .store(riscv_time::get_time_ns() as u64, Ordering::Relaxed);
+        self.slice_used.store(0, Ordering::Relaxed);
+    }
+
+    /// 结算自上次结算以来的运行时间
+    pub(super) fn update(&self) {
+        let now = riscv_time::get_time_ns() as u64;
+        let delta = now.saturating_sub(self.exec_start.swap(now, Ordering::Relaxed));
+        self.slice_used.fetch_add(delta, Ordering::Relaxed);
+        if !self.policy().is_realtime() {
+            self.vruntime
+                .fetch_add(delta * NICE_0_WEIGHT / self.weight(), Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn slice_used(&self) -> u64 {
+        self.slice_used.load(Ordering::Relaxed)
+    }
+}
@@ -8,13 +8,13 @@ use core::{
 use defines::{
     error::{errno, AKResult, KResult},
     ioctl::{
-        Termios, WinSize, TCGETA, TCGETS, TCSBRK, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCGWINSZ,
-        TIOCSPGRP, TIOCSWINSZ,
+        Termios, WinSize, TCGETA, TCGETS, TCSBRK, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCGSID,
+        TIOCGWINSZ, TIOCSCTTY, TIOCSPGRP, TIOCSWINSZ,
     },
 };
 use futures::future::BoxFuture;
 use kernel_tracer::Instrument;
-use klocks::{Lazy, SpinMutex};
+use klocks::{Lazy, SpinMutex, SpinMutexGuard};
 use triomphe::Arc;
 
 use crate::{
@@ -23,7 +23,10 @@ use crate::{
         inode::{BytesInodeBackend, InodeMeta},
         DynBytesInode, InodeMode,
     },
+    hart::local_hart,
     memory::{ReadBuffer, UserCheck},
+    process,
+    signal::{self, KSignalSet, Signal},
     time,
     uart_console::print,
 };
@@ -34,6 +37,9 @@ pub struct TtyInode {
 }
 
 struct TtyInodeInner {
+    /// 以该终端为控制终端的会话
+    session: Option<usize>,
+    /// 前台进程组
     fg_pgid: usize,
     win_size: WinSize,
     termios: Termios,
@@ -44,6 +50,9 @@ impl TtyInode {
         TtyInode {
             meta: InodeMeta::new(InodeMode::CharDevice),
             inner: SpinMutex::new(TtyInodeInner {
+                // 目前只有这一个终端，因此直接作为 INITPROC 所在会话的控制终端，
+                // 这样由 INITPROC 启动的 shell 无需 `setsid` 也能使用作业控制
+                session: Some(1),
                 fg_pgid: 1,
                 win_size: WinSize {
                     ws_row: 67,
@@ -93,13 +102,19 @@ impl BytesInodeBackend for TtyInode {
     }
 
     fn read_inode_at<'a>(&'a self, buf: ReadBuffer<'a>, _offset: u64) -> AKResult<'a, usize> {
+        if let Err(e) = self.check_background_read() {
+            return Box::pin(async move { Err(e) });
+        }
         let curr_time = time::curr_time_spec();
         self.meta
             .lock_inner_with(|inner| inner.access_time = curr_time);
         let ReadBuffer::User(buf) = buf else {
             unreachable!("why kernel read tty?");
         };
-        Box::pin(TtyFuture::new(buf).instrument(trace_span!("read_tty")))
+        Box::pin(
+            async move { signal::interruptible(TtyFuture::new(buf)).await? }
+                .instrument(trace_span!("read_tty")),
+        )
     }
 
     fn write_inode_at(&self, buf: UserCheck<[u8]>, _offset: u64) -> AKResult<'_, usize> {
@@ -144,20 +159,63 @@ impl BytesInodeBackend for TtyInode {
             TIOCGPGRP => {
                 debug!("Get foreground pgid");
                 let fg_pgid_ptr = unsafe {
-                    UserCheck::new(value as _)
+                    UserCheck::<i32>::new(value as _)
                         .ok_or(errno::EINVAL)?
                         .check_ptr_mut()?
                 };
-                fg_pgid_ptr.write(self.inner.lock().fg_pgid);
+                let (_, _, sid) = curr_ids();
+                let fg_pgid = self.lock_as_ctty(sid)?.fg_pgid;
+                fg_pgid_ptr.write(fg_pgid as i32);
                 Ok(0)
             }
             TIOCSPGRP => {
-                debug!("Set foreground pgid");
-                let fg_pgid = UserCheck::<usize>::new(value as _)
+                let fg_pgid = UserCheck::<i32>::new(value as _)
                     .ok_or(errno::EINVAL)?
                     .check_ptr()?
                     .read();
-                self.inner.lock().fg_pgid = fg_pgid;
+                debug!("Set foreground pgid to {fg_pgid}");
+                let fg_pgid = usize::try_from(fg_pgid).map_err(|_| errno::EINVAL)?;
+                let (_, _, sid) = curr_ids();
+                // 要求目标进程组存在，且与调用进程处于同一会话中
+                let in_session = process::processes_in_group(fg_pgid)
+                    .iter()
+                    .any(|process| process.lock_inner_with(|inner| inner.sid) == sid);
+                let mut inner = self.lock_as_ctty(sid)?;
+                if !in_session {
+                    return Err(errno::EPERM);
+                }
+                inner.fg_pgid = fg_pgid;
+                Ok(0)
+            }
+            TIOCSCTTY => {
+                let (pid, pgid, sid) = curr_ids();
+                debug!("Set controlling terminal of session {sid}");
+                // 只有会话首进程才能设置控制终端
+                if pid != sid {
+                    return Err(errno::EPERM);
+                }
+                let mut inner = self.inner.lock();
+                match inner.session {
+                    Some(session) if session == sid => return Ok(0),
+                    // 终端已经是其他会话的控制终端了，只有 `value` 为 1 时才能抢过来
+                    // TODO: [blocked] 抢夺控制终端还需要特权
+                    Some(_) if value != 1 => return Err(errno::EPERM),
+                    _ => {}
+                }
+                inner.session = Some(sid);
+                inner.fg_pgid = pgid;
+                Ok(0)
+            }
+            TIOCGSID => {
+                debug!("Get session id");
+                let sid_ptr = unsafe {
+                    UserCheck::<i32>::new(value as _)
+                        .ok_or(errno::EINVAL)?
+                        .check_ptr_mut()?
+                };
+                let (_, _, sid) = curr_ids();
+                self.lock_as_ctty(sid)?;
+                sid_ptr.write(sid as i32);
                 Ok(0)
             }
             TIOCGWINSZ => {
@@ -187,6 +245,51 @@ impl BytesInodeBackend for TtyInode {
         }
     }
 }
+impl TtyInode {
+    /// 锁住终端，并要求它是会话 `sid` 的控制终端，否则返回 `ENOTTY`
+    fn lock_as_ctty(&self, sid: usize) -> KResult<SpinMutexGuard<'_, TtyInodeInner>> {
+        let inner = self.inner.lock();
+        if inner.session != Some(sid) {
+            return Err(errno::ENOTTY);
+        }
+        Ok(inner)
+    }
+
+    /// 后台进程组读控制终端时，向该进程组发送 `SIGTTIN`。
+    ///
+    /// 错误：
+    /// - `ERESTARTSYS` 已发送 `SIGTTIN`，等待信号处理后重试
+    /// - `EIO` `SIGTTIN` 被忽略或屏蔽了
+    fn check_background_read(&self) -> KResult<()> {
+        let (_, pgid, sid) = curr_ids();
+        {
+            let inner = self.inner.lock();
+            if inner.session != Some(sid) || inner.fg_pgid == pgid {
+                return Ok(());
+            }
+        }
+        debug!("background process group {pgid} reads tty");
+        let ignored = local_hart()
+            .curr_process()
+            .lock_inner_with(|inner| inner.signal_handlers.is_ignored(Signal::SIGTTIN));
+        let blocked = local_hart()
+            .curr_thread()
+            .lock_inner_with(|inner| inner.signal_mask.contains(KSignalSet::SIGTTIN));
+        if ignored || blocked {
+            return Err(errno::EIO);
+        }
+        process::signal_group(pgid, Signal::SIGTTIN);
+        Err(errno::ERESTARTSYS)
+    }
+}
+
+/// 获取当前进程的 pid、pgid 和 sid
+fn curr_ids() -> (usize, usize, usize) {
+    let process = local_hart().curr_process();
+    let (pgid, sid) = process.lock_inner_with(|inner| (inner.pgid, inner.sid));
+    (process.pid(), pgid, sid)
+}
+
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct TtyFuture {
     user_buf: UserCheck<[u8]>,
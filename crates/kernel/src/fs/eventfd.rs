@@ -0,0 +1,144 @@
+use defines::{
+    error::{errno, KResult},
+    fs::PollEvents,
+};
+use event_listener::{listener, Event};
+use klocks::SpinMutex;
+
+use super::{inode::InodeMeta, InodeMode};
+use crate::{memory::UserCheck, signal, time};
+
+/// 计数器的最大值。写入后超过它的话就要等待
+const MAX_COUNT: u64 = u64::MAX - 1;
+
+/// 通过 `eventfd2` 创建的文件，内部是一个 64 位的计数器。写入会累加计数器，读取会取出计数器的值
+pub struct EventFd {
+    meta: InodeMeta,
+    count: SpinMutex<u64>,
+    /// 是否以信号量的方式读取，即每次读取只取出 1
+    semaphore: bool,
+    /// 计数器变化时通知等待的读者和写者
+    event: Event,
+}
+
+impl EventFd {
+    pub fn new(init: u64, semaphore: bool) -> Self {
+        // TODO: [low] linux 上 eventfd 是匿名 inode，这里暂且当作普通文件
+        let meta = InodeMeta::new(InodeMode::Regular);
+        let curr_time = time::curr_time_spec();
+        meta.lock_inner_with(|inner| inner.change_time = curr_time);
+        Self {
+            meta,
+            count: SpinMutex::new(init),
+            semaphore,
+            event: Event::new(),
+        }
+    }
+
+    /// 读取计数器，返回读取的字节数即 8。信号量模式下读出 1 并将计数器减 1，否则读出计数器的值并将其清零。
+    ///
+    /// 若计数器为 0，则在 `nonblock` 时返回 `EAGAIN`，否则等待直到计数器非 0
+    pub async fn read(&self, buf: UserCheck<[u8]>, nonblock: bool) -> KResult<usize> {
+        if buf.len() < 8 {
+            return Err(errno::EINVAL);
+        }
+        let ptr = UserCheck::new(buf.addr().get() as *mut u64).ok_or(errno::EFAULT)?;
+        loop {
+            // 先注册监听再检查，以免错过通知
+            listener!(self.event => listener);
+            // 先检查地址再修改计数器，以免读出的值无法写入而丢失
+            let value_ptr = unsafe { ptr.check_ptr_mut()? };
+            let value = {
+                let mut count = self.count.lock();
+                let value = if self.semaphore {
+                    (*count).min(1)
+                } else {
+                    *count
+                };
+                *count -= value;
+                value
+            };
+            if value > 0 {
+                value_ptr.write(value);
+                self.event.notify(usize::MAX);
+                break;
+            }
+            drop(value_ptr);
+            if nonblock {
+                return Err(errno::EAGAIN);
+            }
+            signal::interruptible(listener).await?;
+        }
+
+        let curr_time = time::curr_time_spec();
+        self.meta
+            .lock_inner_with(|inner| inner.access_time = curr_time);
+        Ok(8)
+    }
+
+    /// 将一个 8 字节的整数累加到计数器上，返回写入的字节数即 8。
+    ///
+    /// 若累加后会超过 [`MAX_COUNT`]，则在 `nonblock` 时返回 `EAGAIN`，否则等待直到计数器被读取
+    pub async fn write(&self, buf: UserCheck<[u8]>, nonblock: bool) -> KResult<usize> {
+        if buf.len() < 8 {
+            return Err(errno::EINVAL);
+        }
+        let value = UserCheck::new(buf.addr().get() as *mut u64)
+            .ok_or(errno::EFAULT)?
+            .check_ptr()?
+            .read();
+        if value == u64::MAX {
+            return Err(errno::EINVAL);
+        }
+        loop {
+            listener!(self.event => listener);
+            let written = {
+                let mut count = self.count.lock();
+                let written = MAX_COUNT - *count >= value;
+                if written {
+                    *count += value;
+                }
+                written
+            };
+            if written {
+                if value > 0 {
+                    self.event.notify(usize::MAX);
+                }
+                break;
+            }
+            if nonblock {
+                return Err(errno::EAGAIN);
+            }
+            signal::interruptible(listener).await?;
+        }
+
+        let curr_time = time::curr_time_spec();
+        self.meta.lock_inner_with(|inner| {
+            inner.modify_time = curr_time;
+            inner.change_time = curr_time;
+        });
+        Ok(8)
+    }
+
+    /// 计数器非 0 时可读，计数器还能再加 1 时可写
+    pub fn poll(&self) -> PollEvents {
+        let count = *self.count.lock();
+        let mut events = PollEvents::empty();
+        if count > 0 {
+            events |= PollEvents::POLLIN;
+        }
+        if count < MAX_COUNT {
+            events |= PollEvents::POLLOUT;
+        }
+        events
+    }
+
+    /// 就绪状态可能变化时通知的事件
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    pub fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+}
@@ -11,8 +11,11 @@ use klocks::SpinMutex;
 use triomphe::Arc;
 
 use super::{
+    eventfd::EventFd,
     inode::{DynDirInode, InodeMeta, InodeMode},
     pipe::Pipe,
+    signalfd::SignalFd,
+    timerfd::TimerFd,
     DEntry, DEntryBytes, DEntryDir, DynBytesInode,
 };
 use crate::memory::{ReadBuffer, UserCheck};
@@ -23,6 +26,9 @@ pub enum File {
     Dir(Arc<DirFile>),
     Seekable(Arc<SeekableFile>),
     Stream(Arc<DEntryBytes>),
+    SignalFd(Arc<SignalFd>),
+    TimerFd(Arc<TimerFd>),
+    EventFd(Arc<EventFd>),
 }
 
 pub struct DirFile {
@@ -141,49 +147,42 @@ impl FdTable {
         self.add_from(desc, 0)
     }
 
+    /// 找到 `N` 个最小可用的 fd，依次插入描述符，并返回这些 fd
+    ///
+    /// 如果超过进程文件描述符软上限则返回 None，此时不会插入任何描述符
     pub fn add_many<const N: usize>(&mut self, descs: [FileDescriptor; N]) -> Option<[usize; N]> {
-        if self.files.len() + N > self.rlimit.rlim_curr {
-            return None;
-        }
-
+        let mut ret = [0; N];
         let mut new_fd = 0;
-        let mut n_ok = 0;
-        let mut ret = [usize::MAX; N];
-        let mut descs = descs.into_iter();
-        for &existed_fd in self.files.keys() {
-            if new_fd != existed_fd {
-                n_ok += 1;
-                if n_ok >= N {
-                    break;
-                }
-                self.files.insert(new_fd, descs.next().unwrap());
-                ret[n_ok] = new_fd;
-                break;
+        for fd in &mut ret {
+            while self.files.contains_key(&new_fd) {
+                new_fd += 1;
+            }
+            if new_fd >= self.rlimit.rlim_curr {
+                return None;
             }
+            *fd = new_fd;
             new_fd += 1;
         }
-        while n_ok < N {
-            self.files.insert(new_fd, descs.next().unwrap());
-            n_ok += 1;
-            new_fd += 1;
+        for (fd, desc) in ret.into_iter().zip(descs) {
+            self.files.insert(fd, desc);
         }
         Some(ret)
     }
 
     /// 找到自 `from` 最小可用的 fd，插入一个描述符，并返回该 fd
     ///
-    /// 如果超过进程文件描述符软上限则返回 None
+    /// 如果 fd 达到了进程文件描述符软上限（`RLIMIT_NOFILE`）则返回 None
     pub fn add_from(&mut self, desc: FileDescriptor, from: usize) -> Option<usize> {
-        if self.files.len() >= self.rlimit.rlim_curr {
-            return None;
-        }
-        let mut new_fd = 0;
-        for (&existed_fd, _) in self.files.range(from..) {
+        let mut new_fd = from;
+        for &existed_fd in self.files.range(from..).map(|(fd, _)| fd) {
             if new_fd != existed_fd {
                 break;
             }
             new_fd += 1;
         }
+        if new_fd >= self.rlimit.rlim_curr {
+            return None;
+        }
         self.files.insert(new_fd, desc);
         Some(new_fd)
     }
@@ -212,6 +211,16 @@ impl FdTable {
     pub fn limit(&self) -> usize {
         self.rlimit.rlim_curr
     }
+
+    /// 文件描述符数量的限制，即 `RLIMIT_NOFILE`
+    pub fn rlimit(&self) -> RLimit {
+        self.rlimit
+    }
+
+    /// 设置 `RLIMIT_NOFILE`。已经打开的超过软上限的 fd 不受影响
+    pub fn set_rlimit(&mut self, rlimit: RLimit) {
+        self.rlimit = rlimit;
+    }
 }
 
 #[derive(Clone)]
@@ -246,6 +255,21 @@ impl FileDescriptor {
                 Ok(nread)
             }
             File::Stream(stream) => stream.inode().read_at(ReadBuffer::User(buf), 0).await,
+            File::SignalFd(signalfd) => {
+                signalfd
+                    .read(buf, self.flags.contains(OpenFlags::NONBLOCK))
+                    .await
+            }
+            File::TimerFd(timerfd) => {
+                timerfd
+                    .read(buf, self.flags.contains(OpenFlags::NONBLOCK))
+                    .await
+            }
+            File::EventFd(eventfd) => {
+                eventfd
+                    .read(buf, self.flags.contains(OpenFlags::NONBLOCK))
+                    .await
+            }
         }
     }
 
@@ -264,12 +288,22 @@ impl FileDescriptor {
                 Ok(nwrite)
             }
             File::Stream(stream) => stream.inode().write_at(buf, 0).await,
+            File::SignalFd(_) | File::TimerFd(_) => Err(errno::EINVAL),
+            File::EventFd(eventfd) => {
+                eventfd
+                    .write(buf, self.flags.contains(OpenFlags::NONBLOCK))
+                    .await
+            }
         }
     }
 
     pub async fn seek(&self, pos: SeekFrom) -> KResult<usize> {
         match &self.file {
-            File::Stream(_) | File::Pipe(_) => Err(errno::ESPIPE),
+            File::Stream(_)
+            | File::Pipe(_)
+            | File::SignalFd(_)
+            | File::TimerFd(_)
+            | File::EventFd(_) => Err(errno::ESPIPE),
             File::Dir(_) => todo!("[low] what does dir seek mean?"),
             File::Seekable(seekable) => {
                 let ret = match pos {
@@ -305,6 +339,9 @@ impl FileDescriptor {
             File::Seekable(seekable) => seekable.inode().meta(),
             File::Pipe(pipe) => pipe.meta(),
             File::Stream(stream) => stream.inode().meta(),
+            File::SignalFd(signalfd) => signalfd.meta(),
+            File::TimerFd(timerfd) => timerfd.meta(),
+            File::EventFd(eventfd) => eventfd.meta(),
         }
     }
 
@@ -333,6 +370,9 @@ impl FileDescriptor {
             File::Dir(dir) => dir.dentry.name(),
             File::Seekable(seekable) => seekable.dentry.name(),
             File::Stream(stream) => stream.name(),
+            File::SignalFd(_) => "<signalfd>",
+            File::TimerFd(_) => "<timerfd>",
+            File::EventFd(_) => "<eventfd>",
         }
     }
 }
@@ -4,7 +4,7 @@ use atomic::Ordering;
 use common::config::{PAGE_OFFSET_MASK, PAGE_SIZE, PAGE_SIZE_BITS};
 use defines::{
     error::{errno, AKResult, KResult},
-    fs::StatMode,
+    fs::{AccessFlags, StatMode},
     misc::TimeSpec,
 };
 use kernel_tracer::Instrument;
@@ -16,6 +16,7 @@ use crate::{
     executor::block_on,
     fs::page_cache::PageState,
     memory::{ReadBuffer, UserCheck},
+    process::Credentials,
     time,
 };
 
@@ -60,12 +61,23 @@ pub struct InodeMeta {
 }
 
 impl InodeMeta {
+    /// 新的 inode 属于 root。文件系统本身不记录权限时，其权限也就是这里的默认值
     pub fn new(mode: InodeMode) -> Self {
+        let perm = match mode {
+            InodeMode::Regular | InodeMode::Dir | InodeMode::Socket => 0o755,
+            InodeMode::SymbolLink => 0o777,
+            InodeMode::Fifo => 0o600,
+            // 像 `/dev/tty`、`/dev/null` 这样的设备，所有用户都要能读写
+            InodeMode::BlockDevice | InodeMode::CharDevice => 0o666,
+        };
         Self {
             ino: INODE_NUMBER.fetch_add(1, Ordering::SeqCst),
             mode,
             page_cache: PageCache::new(),
             inner: SpinMutex::new(InodeMetaInner {
+                uid: 0,
+                gid: 0,
+                perm: StatMode::permission(perm),
                 data_len: 0,
                 access_time: TimeSpec::default(),
                 modify_time: TimeSpec::default(),
@@ -93,9 +105,49 @@ impl InodeMeta {
     pub fn get_inner_mut(&mut self) -> &mut InodeMetaInner {
         self.inner.get_mut()
     }
+
+    /// 新建的 inode 属于创建者，即 `cred` 的有效用户 ID 和有效组 ID，权限设为 `perm`
+    pub fn init_owner(&self, cred: &Credentials, perm: StatMode) {
+        self.lock_inner_with(|inner| {
+            inner.uid = cred.euid;
+            inner.gid = cred.egid;
+            inner.perm = perm;
+        });
+    }
+
+    /// 检查凭证为 `cred` 的进程是否可以以 `access` 访问该 inode
+    ///
+    /// 特权进程可以读写任何文件，也可以搜索任何目录，但只能执行至少有一个执行位的文件
+    ///
+    /// 错误：
+    /// - `EACCES` 没有权限
+    pub fn check_access(&self, cred: &Credentials, access: AccessFlags) -> KResult<()> {
+        let (uid, gid, perm) = self.lock_inner_with(|inner| (inner.uid, inner.gid, inner.perm));
+        let allowed = if cred.is_privileged() {
+            !access.contains(AccessFlags::EXECUTE)
+                || self.mode == InodeMode::Dir
+                || perm.intersects(StatMode::S_IXUSR | StatMode::S_IXGRP | StatMode::S_IXOTH)
+        } else {
+            let bits = if cred.euid == uid {
+                perm.bits() >> 6
+            } else if cred.in_group(gid) {
+                perm.bits() >> 3
+            } else {
+                perm.bits()
+            };
+            AccessFlags::from_bits_truncate(bits).contains(access)
+        };
+        if allowed { Ok(()) } else { Err(errno::EACCES) }
+    }
 }
 
 pub struct InodeMetaInner {
+    /// 所有者的用户 ID
+    pub uid: u32,
+    /// 所有者的组 ID
+    pub gid: u32,
+    /// 权限位，只包含 [`StatMode`] 中的 `S_ISUID` 到 `S_IXOTH` 的部分
+    pub perm: StatMode,
     /// 对常规文件来说，是其文件内容大小；对目录来说，是它目录项列表占据的总共块空间；其他情况是 0
     pub data_len: u64,
     /// 上一次访问时间
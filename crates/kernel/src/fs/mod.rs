@@ -3,11 +3,14 @@
 
 mod dentry;
 mod devfs;
+mod eventfd;
 mod fat32;
 mod file;
 mod inode;
 mod page_cache;
 mod pipe;
+mod signalfd;
+mod timerfd;
 mod tmpfs;
 
 use alloc::{string::String, vec::Vec};
@@ -18,7 +21,7 @@ use cervine::Cow;
 use compact_str::{CompactString, ToCompactString};
 use defines::{
     error::{errno, KResult},
-    fs::{MountFlags, Stat, StatMode, UnmountFlags, AT_FDCWD},
+    fs::{AccessFlags, MountFlags, Stat, StatMode, UnmountFlags, AT_FDCWD},
 };
 use hashbrown::HashMap;
 use klocks::{Lazy, SpinMutex};
@@ -27,23 +30,51 @@ use triomphe::Arc;
 use self::inode::InodeMeta;
 pub use self::{
     dentry::{DEntry, DEntryBytes, DEntryDir},
+    eventfd::EventFd,
     file::{DirFile, FdTable, File, FileDescriptor, SeekFrom, SeekableFile},
     inode::{DynBytesInode, InodeMode},
     pipe::make_pipe,
+    signalfd::SignalFd,
+    timerfd::TimerFd,
 };
 use crate::{
     drivers::qemu_block::{BLOCK_DEVICE, BLOCK_SIZE},
     hart::local_hart,
     memory::ReadBuffer,
+    process::Credentials,
     uart_console::println,
 };
 
 pub fn init() {
     Lazy::force(&VFS);
-    VFS.mount("/dev", "udev", FileSystemType::Devfs, MountFlags::empty());
+    let dev = resolve_kernel_path("/dev").expect("resolve /dev failed");
+    VFS.mount(dev, "udev", FileSystemType::Devfs, MountFlags::empty());
+    init_tmp();
     VFS.list_root_dir();
 }
 
+/// 确保 `/tmp` 存在。和 Linux 一样，它对所有用户可写并带有 sticky 位，这样非特权进程也能在其中创建文件
+fn init_tmp() {
+    let root = VFS.root_dir();
+    let tmp = match root.lookup(Cow::Borrowed("tmp")) {
+        Some(DEntry::Dir(tmp)) => tmp,
+        Some(DEntry::Bytes(_)) => {
+            warn!("/tmp is not a directory");
+            return;
+        }
+        None => match root.mkdir(CompactString::from_static_str("tmp")) {
+            Ok(tmp) => tmp,
+            Err(e) => {
+                warn!("failed to create /tmp: {e:?}");
+                return;
+            }
+        },
+    };
+    tmp.inode()
+        .meta()
+        .lock_inner_with(|inner| inner.perm = StatMode::permission(0o1777));
+}
+
 pub struct VirtFileSystem {
     root_dir: Arc<DEntryDir>,
     mount_table: SpinMutex<HashMap<DEntry, FileSystem>>,
@@ -56,13 +87,15 @@ impl VirtFileSystem {
 
     pub fn mount(
         &self,
-        mount_point: &str,
+        p2i: PathToInode,
         device_path: &str,
         fs_type: FileSystemType,
         flags: MountFlags,
     ) -> KResult<()> {
-        debug!("mount {device_path} under {mount_point}, fs_type: {fs_type:?}, flags: {flags:?}",);
-        let p2i = resolve_path_with_dir_fd(AT_FDCWD, mount_point)?;
+        debug!(
+            "mount {device_path} under {}, fs_type: {fs_type:?}, flags: {flags:?}",
+            p2i.last_component
+        );
         let mut mount_table = self.mount_table.lock();
         // NOTE: linux 要求挂载必须发生在一个存在的目录上，但是我们的实现似乎不需要
         let mounted_dentry;
@@ -113,9 +146,8 @@ impl VirtFileSystem {
         Ok(())
     }
 
-    pub fn unmount(&self, mount_point: &str, flags: UnmountFlags) -> KResult<()> {
-        debug!("mount {mount_point}, flags: {flags:?}");
-        let p2i = resolve_path_with_dir_fd(AT_FDCWD, mount_point)?;
+    pub fn unmount(&self, p2i: PathToInode, flags: UnmountFlags) -> KResult<()> {
+        debug!("unmount {}, flags: {flags:?}", p2i.last_component);
         let dentry = p2i
             .dir
             .lookup(Cow::Borrowed(&p2i.last_component))
@@ -232,31 +264,48 @@ pub struct PathToInode {
     pub last_component: CompactString,
 }
 
+/// 以当前进程的身份解析路径，会检查路径上各目录的搜索权限
 pub fn resolve_path_with_dir_fd(dir_fd: usize, path: &str) -> KResult<PathToInode> {
-    let start_dir;
+    let process = local_hart().curr_process();
+    let inner = process.lock_inner();
     // 绝对路径则忽视 fd
-    if path.starts_with('/') {
-        start_dir = Arc::clone(VFS.root_dir());
+    let start_dir = if path.starts_with('/') {
+        Arc::clone(VFS.root_dir())
+    } else if dir_fd == AT_FDCWD {
+        Arc::clone(&inner.cwd)
+    } else if let Some(base) = inner.fd_table.get(dir_fd) {
+        // 相对路径名，需要从一个目录开始
+        let File::Dir(dir) = &**base else {
+            return Err(errno::ENOTDIR);
+        };
+        Arc::clone(dir.dentry())
     } else {
-        let process = local_hart().curr_process();
-        let inner = process.lock_inner();
-        if dir_fd == AT_FDCWD {
-            start_dir = Arc::clone(&inner.cwd);
-        } else if let Some(base) = inner.fd_table.get(dir_fd) {
-            // 相对路径名，需要从一个目录开始
-            let File::Dir(dir) = &**base else {
-                return Err(errno::ENOTDIR);
-            };
-            start_dir = Arc::clone(dir.dentry());
-        } else {
-            return Err(errno::EBADF);
-        }
-    }
+        return Err(errno::EBADF);
+    };
+    let cred = inner.cred.clone();
+    drop(inner);
 
-    path_walk(start_dir, path)
+    path_walk(start_dir, path, &cred)
 }
 
-pub fn path_walk(start_dir: Arc<DEntryDir>, path: &str) -> KResult<PathToInode> {
+/// 内核自身以 root 身份解析绝对路径 `path`
+fn resolve_kernel_path(path: &str) -> KResult<PathToInode> {
+    debug_assert!(path.starts_with('/'));
+    path_walk(Arc::clone(VFS.root_dir()), path, &Credentials::ROOT)
+}
+
+/// 从 `start_dir` 开始解析路径。在某个目录下查找下一个 component 之前，会检查 `cred` 对该目录的搜索权限，
+/// 包括返回的 [`PathToInode::dir`]
+///
+/// 错误：
+/// - `EACCES` 没有路径上某个目录的搜索权限
+/// - `ENOENT` 路径上某个目录不存在
+/// - `ENOTDIR` 路径上某个 component 不是目录
+pub fn path_walk(
+    start_dir: Arc<DEntryDir>,
+    path: &str,
+    cred: &Credentials,
+) -> KResult<PathToInode> {
     debug!("walk path: {path}, from {}", start_dir.name());
     let mut split = path
         .trim_start_matches('/')
@@ -274,6 +323,10 @@ pub fn path_walk(start_dir: Arc<DEntryDir>, path: &str) -> KResult<PathToInode>
     };
 
     for next_component in split {
+        ret.dir
+            .inode()
+            .meta()
+            .check_access(cred, AccessFlags::EXECUTE)?;
         match ret.dir.lookup(Cow::Borrowed(curr_component)) {
             Some(DEntry::Dir(next_dir)) => ret.dir = next_dir,
             Some(_) => return Err(errno::ENOTDIR),
@@ -281,12 +334,17 @@ pub fn path_walk(start_dir: Arc<DEntryDir>, path: &str) -> KResult<PathToInode>
         }
         curr_component = next_component;
     }
+    ret.dir
+        .inode()
+        .meta()
+        .check_access(cred, AccessFlags::EXECUTE)?;
     ret.last_component = curr_component.to_compact_string();
     Ok(ret)
 }
 
+/// 内核自身以 root 身份查找文件，`path` 需要是绝对路径
 pub fn find_file(path: &str) -> KResult<DEntry> {
-    let p2i = resolve_path_with_dir_fd(AT_FDCWD, path)?;
+    let p2i = resolve_kernel_path(path)?;
     p2i.dir
         .lookup(Cow::Owned(p2i.last_component))
         .ok_or(errno::ENOENT)
@@ -308,15 +366,15 @@ pub fn stat_from_meta(meta: &InodeMeta) -> Stat {
     // TODO: fstat 的 device id 暂时是一个随意的数字
     stat.st_dev = 114514;
     stat.st_ino = meta.ino() as u64;
-    stat.st_mode = StatMode::from(meta.mode());
     stat.st_nlink = 1;
-    stat.st_uid = 0;
-    stat.st_gid = 0;
     stat.st_rdev = 0;
     // TODO: 特殊文件也先填成 BLOCK_SIZE 吧
     stat.st_blksize = BLOCK_SIZE as u32;
     // TODO: 文件有空洞时，可能小于 st_size/512。而且可能实际占用的块数量会更多
     meta.lock_inner_with(|meta_inner| {
+        stat.st_mode = StatMode::from(meta.mode()) | meta_inner.perm;
+        stat.st_uid = meta_inner.uid;
+        stat.st_gid = meta_inner.gid;
         stat.st_size = meta_inner.data_len;
         stat.st_atime = meta_inner.access_time;
         stat.st_mtime = meta_inner.modify_time;
@@ -1,9 +1,13 @@
 use async_channel::{Receiver, Sender};
-use defines::error::{errno, KResult};
+use defines::{
+    error::{errno, KResult},
+    fs::PollEvents,
+};
+use event_listener::Event;
 use triomphe::Arc;
 
 use super::{inode::InodeMeta, InodeMode};
-use crate::{memory::UserCheck, time};
+use crate::{memory::UserCheck, signal, time};
 
 // TODO: [low] pipe 的实现可以优化
 
@@ -13,6 +17,9 @@ const PIPE_CAPACITY: usize = 16384;
 pub struct Pipe {
     meta: Arc<InodeMeta>,
     inner: PipeInner,
+    /// 由两端共享，读写或者关闭一端时通知，用于 ppoll 等待就绪。
+    /// 注意它需要在 `inner` 之后 drop，这样通知时一端已经关闭了
+    event: PipeEvent,
 }
 
 impl Pipe {
@@ -24,12 +31,18 @@ impl Pipe {
         let mut n_read = 0;
 
         while n_read < len {
-            let Ok(byte) = receiver.recv().await else {
-                break;
+            // 被信号打断时，若已经读到了数据则返回已读的部分
+            let byte = match signal::interruptible(receiver.recv()).await {
+                Ok(Ok(byte)) => byte,
+                Ok(Err(_)) => break,
+                Err(_) if n_read > 0 => break,
+                Err(e) => return Err(e),
             };
             unsafe { curr.check_ptr_mut()? }.write(byte);
             curr = curr.add(1).ok_or(errno::EINVAL)?;
             n_read += 1;
+            // 读取可能会因为管道为空而阻塞，因此每读一个字节就要通知等待可写的一方
+            self.event.0.notify(usize::MAX);
         }
         let curr_time = time::curr_time_spec();
         self.meta
@@ -63,7 +76,17 @@ impl Pipe {
                     break 'out;
                 }
             };
-            sender.send(last_byte).await;
+            // 管道已满，阻塞前先通知等待可读的一方
+            self.event.0.notify(usize::MAX);
+            // 被信号打断时，若已经写入了数据则返回已写的部分
+            match signal::interruptible(sender.send(last_byte)).await {
+                Ok(_) => {}
+                Err(_) if n_write + this_n_write > 0 => {
+                    n_write += this_n_write;
+                    break 'out;
+                }
+                Err(e) => return Err(e),
+            }
             this_n_write += 1;
             n_write += this_n_write;
             buf = buf
@@ -71,17 +94,58 @@ impl Pipe {
                 .expect("this_n_write <= buf.len()");
         }
 
+        self.event.0.notify(usize::MAX);
         let curr_time = time::curr_time_spec();
         self.meta
             .lock_inner_with(|inner| inner.modify_time = curr_time);
         Ok(n_write)
     }
 
+    /// 读端有数据时可读，写端未满时可写。另一端全部关闭时，读端为 `POLLHUP`，写端为 `POLLERR`
+    pub fn poll(&self) -> PollEvents {
+        match &self.inner {
+            PipeInner::ReadEnd(receiver) => {
+                let mut events = PollEvents::empty();
+                if !receiver.is_empty() {
+                    events |= PollEvents::POLLIN;
+                }
+                if receiver.sender_count() == 0 {
+                    events |= PollEvents::POLLHUP;
+                }
+                events
+            }
+            PipeInner::WriteEnd(sender) => {
+                if sender.receiver_count() == 0 {
+                    PollEvents::POLLERR
+                } else if sender.is_full() {
+                    PollEvents::empty()
+                } else {
+                    PollEvents::POLLOUT
+                }
+            }
+        }
+    }
+
+    /// 就绪状态可能变化时通知的事件
+    pub fn event(&self) -> &Event {
+        &self.event.0
+    }
+
     pub fn meta(&self) -> &InodeMeta {
         &self.meta
     }
 }
 
+#[derive(Clone)]
+struct PipeEvent(Arc<Event>);
+
+impl Drop for PipeEvent {
+    fn drop(&mut self) {
+        // 可能是一端的最后一个引用被关闭了，另一端会因此变为 `POLLHUP` 或者 `POLLERR`
+        self.0.notify(usize::MAX);
+    }
+}
+
 #[derive(Clone)]
 enum PipeInner {
     ReadEnd(Receiver<u8>),
@@ -97,14 +161,17 @@ pub fn make_pipe() -> (Pipe, Pipe) {
         inner.data_len = PIPE_CAPACITY as u64;
         inner.change_time = curr_time;
     });
+    let event = Arc::new(Event::new());
     (
         Pipe {
             meta: Arc::clone(&meta),
             inner: PipeInner::ReadEnd(receiver),
+            event: PipeEvent(Arc::clone(&event)),
         },
         Pipe {
             meta,
             inner: PipeInner::WriteEnd(sender),
+            event: PipeEvent(event),
         },
     )
 }
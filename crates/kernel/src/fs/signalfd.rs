@@ -0,0 +1,103 @@
+use core::mem;
+
+use defines::{
+    error::{errno, KResult},
+    fs::PollEvents,
+    signal::SignalFdSigInfo,
+};
+use event_listener::listener;
+use klocks::SpinMutex;
+use triomphe::Arc;
+
+use super::{inode::InodeMeta, InodeMode};
+use crate::{
+    hart::local_hart,
+    memory::UserCheck,
+    signal::{self, KSignalSet},
+    time,
+};
+
+/// 通过 `signalfd4` 创建的文件，读取它会取出调用线程中匹配 `mask` 的待处理信号
+pub struct SignalFd {
+    meta: InodeMeta,
+    mask: SpinMutex<KSignalSet>,
+}
+
+impl SignalFd {
+    pub fn new(mask: KSignalSet) -> Self {
+        // TODO: [low] linux 上 signalfd 是匿名 inode，这里暂且当作普通文件
+        let meta = InodeMeta::new(InodeMode::Regular);
+        let curr_time = time::curr_time_spec();
+        meta.lock_inner_with(|inner| inner.change_time = curr_time);
+        Self {
+            meta,
+            mask: SpinMutex::new(mask),
+        }
+    }
+
+    pub fn set_mask(&self, mask: KSignalSet) {
+        *self.mask.lock() = mask;
+    }
+
+    /// 读取若干个 [`SignalFdSigInfo`]，返回读取的字节数。
+    ///
+    /// 若没有匹配的待处理信号，则在 `nonblock` 时返回 `EAGAIN`，否则等待直到有匹配的信号
+    pub async fn read(&self, buf: UserCheck<[u8]>, nonblock: bool) -> KResult<usize> {
+        const INFO_SIZE: usize = mem::size_of::<SignalFdSigInfo>();
+        let count = buf.len() / INFO_SIZE;
+        if count == 0 {
+            return Err(errno::EINVAL);
+        }
+        let base = buf.addr().get();
+        let thread = Arc::clone(&local_hart().curr_thread_arc());
+        let mut n_read = 0;
+
+        while n_read < count {
+            // 先注册监听再检查，以免错过通知
+            listener!(thread.signal_event => listener);
+            let ptr = UserCheck::new((base + n_read * INFO_SIZE) as *mut SignalFdSigInfo)
+                .ok_or(errno::EFAULT)?;
+            // 先检查地址再取出信号，以免信号取出后无法写入而丢失
+            let info_ptr = unsafe { ptr.check_ptr_mut()? };
+            let mask = *self.mask.lock();
+            if let Some(info) =
+                thread.lock_inner_with(|inner| inner.pending_signal.pop(mask.complement()))
+            {
+                info_ptr.write(info.to_signalfd());
+                n_read += 1;
+                continue;
+            }
+            drop(info_ptr);
+            // 已经读到了信号的话就直接返回，不再等待
+            if n_read > 0 {
+                break;
+            }
+            if nonblock {
+                return Err(errno::EAGAIN);
+            }
+            signal::interruptible(listener).await?;
+        }
+
+        let curr_time = time::curr_time_spec();
+        self.meta
+            .lock_inner_with(|inner| inner.access_time = curr_time);
+        Ok(n_read * INFO_SIZE)
+    }
+
+    /// 调用线程有匹配的待处理信号时可读
+    pub fn poll(&self) -> PollEvents {
+        let mask = *self.mask.lock();
+        let readable = local_hart()
+            .curr_thread()
+            .lock_inner_with(|inner| inner.pending_signal.set().intersects(mask));
+        if readable {
+            PollEvents::POLLIN
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    pub fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+}
@@ -0,0 +1,147 @@
+use core::{mem, time::Duration};
+
+use defines::{
+    error::{errno, KResult},
+    fs::PollEvents,
+};
+use event_listener::{listener, Event};
+use klocks::SpinMutex;
+use triomphe::Arc;
+
+use super::{inode::InodeMeta, InodeMode};
+use crate::{memory::UserCheck, process::RealTimer, signal, time};
+
+/// 通过 `timerfd_create` 创建的文件，读取它会取出自上次读取以来定时器到期的次数
+pub struct TimerFd {
+    meta: InodeMeta,
+    /// 是否按挂钟计时。设置绝对时刻时需要转换为单调时钟
+    realtime: bool,
+    timer: SpinMutex<RealTimer>,
+    /// 由定时器的内核任务共享
+    ticks: Arc<TimerFdTicks>,
+}
+
+struct TimerFdTicks {
+    count: SpinMutex<TickCount>,
+    /// 定时器到期时通知等待的读者
+    event: Event,
+}
+
+#[derive(Default)]
+struct TickCount {
+    /// 自上次读取以来定时器到期的次数
+    count: u64,
+    /// 每次设置定时器都会递增。旧定时器的回调发现不一致时就不再计数
+    generation: u64,
+}
+
+impl TimerFd {
+    pub fn new(realtime: bool) -> Self {
+        // TODO: [low] linux 上 timerfd 是匿名 inode，这里暂且当作普通文件
+        let meta = InodeMeta::new(InodeMode::Regular);
+        let curr_time = time::curr_time_spec();
+        meta.lock_inner_with(|inner| inner.change_time = curr_time);
+        Self {
+            meta,
+            realtime,
+            timer: SpinMutex::new(RealTimer::default()),
+            ticks: Arc::new(TimerFdTicks {
+                count: SpinMutex::new(TickCount::default()),
+                event: Event::new(),
+            }),
+        }
+    }
+
+    /// 返回距离下一次到期的时间和间隔
+    pub fn get(&self) -> (Duration, Duration) {
+        self.timer.lock().get()
+    }
+
+    /// 设置定时器，返回原来的值。会清空尚未读取的到期次数
+    ///
+    /// 参数：
+    /// - `value` 到期的时间，为 0 则停止定时器
+    /// - `interval` 到期后重新计时的间隔
+    /// - `abs` 为真则 `value` 是定时器所用时钟的绝对时刻，否则是相对时间
+    pub fn set(&self, value: Duration, interval: Duration, abs: bool) -> (Duration, Duration) {
+        // TODO: [low] 按挂钟计时的定时器在挂钟被修改时应当随之调整，也不支持 `TFD_TIMER_CANCEL_ON_SET`
+        let deadline = if value.is_zero() {
+            None
+        } else if !abs {
+            Some(time::curr_time().saturating_add(value))
+        } else if self.realtime {
+            Some(time::realtime_to_monotonic(value))
+        } else {
+            Some(value)
+        };
+        let mut timer = self.timer.lock();
+        let old = timer.get();
+        // 旧定时器的回调可能已经在执行了，因此要在持有锁时检查 `generation`，以免它在这之后又计数
+        let generation = {
+            let mut count = self.ticks.count.lock();
+            count.count = 0;
+            count.generation += 1;
+            count.generation
+        };
+        let ticks = Arc::clone(&self.ticks);
+        timer.set(deadline, interval, move |overrun| {
+            let mut count = ticks.count.lock();
+            if count.generation != generation {
+                return;
+            }
+            count.count = count.count.saturating_add(u64::from(overrun) + 1);
+            drop(count);
+            ticks.event.notify(usize::MAX);
+        });
+        old
+    }
+
+    /// 读取自上次读取以来定时器到期的次数，返回读取的字节数即 8。
+    ///
+    /// 若定时器尚未到期，则在 `nonblock` 时返回 `EAGAIN`，否则等待直到定时器到期
+    pub async fn read(&self, buf: UserCheck<[u8]>, nonblock: bool) -> KResult<usize> {
+        if buf.len() < 8 {
+            return Err(errno::EINVAL);
+        }
+        let ptr = UserCheck::new(buf.addr().get() as *mut u64).ok_or(errno::EFAULT)?;
+        loop {
+            // 先注册监听再检查，以免错过通知
+            listener!(self.ticks.event => listener);
+            // 先检查地址再取出到期次数，以免无法写入而丢失
+            let count_ptr = unsafe { ptr.check_ptr_mut()? };
+            let count = mem::take(&mut self.ticks.count.lock().count);
+            if count > 0 {
+                count_ptr.write(count);
+                break;
+            }
+            drop(count_ptr);
+            if nonblock {
+                return Err(errno::EAGAIN);
+            }
+            signal::interruptible(listener).await?;
+        }
+
+        let curr_time = time::curr_time_spec();
+        self.meta
+            .lock_inner_with(|inner| inner.access_time = curr_time);
+        Ok(8)
+    }
+
+    /// 定时器到期过且尚未被读取时可读
+    pub fn poll(&self) -> PollEvents {
+        if self.ticks.count.lock().count > 0 {
+            PollEvents::POLLIN
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    /// 就绪状态可能变化时通知的事件
+    pub fn event(&self) -> &Event {
+        &self.ticks.event
+    }
+
+    pub fn meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+}
@@ -0,0 +1,82 @@
+//! 核间中断（IPI）。通过 SBI 向其他 hart 发送 supervisor 软件中断，
+//! 用于唤醒空闲的 hart，以及在其他 hart 上执行函数（如 TLB shootdown）
+
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use common::config::MAX_HART_NUM;
+use crossbeam_queue::SegQueue;
+use klocks::Lazy;
+use riscv::register::sip;
+use triomphe::Arc;
+
+use super::{local_hart, online_harts};
+
+/// 每个 hart 待执行的跨核调用
+static MAILBOXES: Lazy<[SegQueue<Arc<CrossCall>>; MAX_HART_NUM]> =
+    Lazy::new(|| core::array::from_fn(|_| SegQueue::new()));
+
+/// 一次跨核调用，由所有目标 hart 共享
+struct CrossCall {
+    func: Box<dyn Fn() + Send + Sync>,
+    /// 还未执行完的 hart 数量
+    pending: AtomicUsize,
+}
+
+/// 向 `hart_mask` 中的 hart 发送 IPI。第 i 位为 1 表示 hart i
+pub fn send_ipi(hart_mask: usize) {
+    if hart_mask == 0 {
+        return;
+    }
+    let ret = sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(hart_mask, 0));
+    if ret.is_err() {
+        error!("failed to send ipi to harts {hart_mask:#b}: {ret:?}");
+    }
+}
+
+/// 在 `hart_mask` 中的每个已启动的 hart 上执行 `func`，包括当前 hart。
+///
+/// 当前 hart 会直接执行 `func`，其他 hart 则在收到 IPI 后于中断上下文中执行，因此 `func` 不应阻塞。
+/// 若 `wait` 为真，则等待所有 hart 都执行完后才返回。等待期间会顺带执行发给当前 hart 的跨核调用，
+/// 因此两个 hart 互相等待对方时不会死锁。
+///
+/// 但其他 hart 可能正关着中断自旋等待某个锁，所以 `wait` 为真时调用者不能持有任何锁
+pub fn cross_call(hart_mask: usize, func: impl Fn() + Send + Sync + 'static, wait: bool) {
+    let hart_id = local_hart().hart_id();
+    let remote_mask = hart_mask & online_harts() & !(1 << hart_id);
+    let call = Arc::new(CrossCall {
+        func: Box::new(func),
+        pending: AtomicUsize::new(remote_mask.count_ones() as usize),
+    });
+    for target in (0..MAX_HART_NUM).filter(|&i| remote_mask & (1 << i) != 0) {
+        MAILBOXES[target].push(Arc::clone(&call));
+    }
+    send_ipi(remote_mask);
+    if hart_mask & (1 << hart_id) != 0 {
+        (call.func)();
+    }
+    if wait {
+        while call.pending.load(Ordering::Acquire) != 0 {
+            run_cross_calls();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 处理 supervisor 软件中断，执行发给当前 hart 的所有跨核调用
+pub fn handle_ipi() {
+    // 先清除中断再处理，这样处理期间新到来的 IPI 不会丢失
+    unsafe {
+        sip::clear_ssoft();
+    }
+    run_cross_calls();
+}
+
+/// 执行发给当前 hart 的所有跨核调用
+fn run_cross_calls() {
+    let mailbox = &MAILBOXES[local_hart().hart_id()];
+    while let Some(call) = mailbox.pop() {
+        (call.func)();
+        call.pending.fetch_sub(1, Ordering::Release);
+    }
+}
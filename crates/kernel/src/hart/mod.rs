@@ -1,3 +1,5 @@
+mod ipi;
+
 use alloc::vec::Vec;
 use core::{
     arch::asm,
@@ -11,11 +13,13 @@ use kernel_tracer::SpanId;
 use memory::KERNEL_SPACE;
 use triomphe::Arc;
 
+pub use self::ipi::{cross_call, handle_ipi, send_ipi};
 use crate::{
     drivers::{self, qemu_block::BLOCK_SIZE},
     fs, memory,
     process::{Process, INITPROC},
     thread::{self, Thread},
+    time,
 };
 
 core::arch::global_asm!(include_str!("entry.S"));
@@ -25,6 +29,14 @@ core::arch::global_asm!(include_str!("entry.S"));
 static HARTS: [SyncUnsafeCell<CachePadded<Hart>>; MAX_HART_NUM] =
     [const { SyncUnsafeCell::new(CachePadded::new(Hart::new())) }; MAX_HART_NUM];
 
+/// 第 i 位为 1 表示 hart i 已启动
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// 已启动的 hart 的掩码，第 i 位为 1 表示 hart i 已启动
+pub fn online_harts() -> usize {
+    ONLINE_HARTS.load(Ordering::Acquire)
+}
+
 /// # SAFETY
 /// Hart 结构实际上只会被对应的 hart 访问
 unsafe impl Sync for Hart {}
@@ -73,6 +85,10 @@ impl Hart {
     pub fn curr_process_arc(&self) -> Ref<'_, Arc<Process>> {
         Ref::map(self.curr_thread(), |t| &t.process)
     }
+
+    pub fn curr_thread_arc(&self) -> Ref<'_, Arc<Thread>> {
+        Ref::map(self.thread.borrow(), |t| t.as_ref().unwrap())
+    }
 }
 
 pub static BOOT_HART: AtomicUsize = AtomicUsize::new(usize::MAX);
@@ -96,11 +112,12 @@ pub extern "C" fn __hart_entry(hart_id: usize) -> ! {
         drivers::init();
         // log 实现依赖于 uart 和 virtio_block
         crate::tracer::init();
+        time::init();
         memory::log_kernel_sections();
 
         fs::init();
 
-        thread::spawn_user_thread(INITPROC.lock_inner_with(|inner| inner.main_thread()));
+        thread::spawn_user_thread(INITPROC.main_thread().unwrap());
         info!("Init hart {hart_id} started");
         INIT_FINISHED.store(true, Ordering::SeqCst);
 
@@ -169,6 +186,7 @@ unsafe fn set_local_hart(hart_id: usize) {
         (*hart_ptr).hart_id = hart_id;
         asm!("mv tp, {}", in(reg) hart_ptr as usize);
     }
+    ONLINE_HARTS.fetch_or(1 << hart_id, Ordering::Release);
 }
 
 pub fn local_hart<'a>() -> &'a Hart {
@@ -20,6 +20,8 @@ pub const AT_BASE: u8 = 7;
 pub const AT_ENTRY: u8 = 9;
 /// 指向 16 字节随机值的地址
 pub const AT_RANDOM: u8 = 25;
+/// vDSO 映像的起始地址
+pub const AT_SYSINFO_EHDR: u8 = 33;
 
 impl<'a, 'b> FramedVmArea {
     /// 返回 `user_sp` 与 `argv_base`
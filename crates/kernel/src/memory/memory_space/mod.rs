@@ -5,26 +5,35 @@ use core::{
 };
 
 use bitflags::bitflags;
-use common::config::{LOW_ADDRESS_END, MEMORY_END, MMAP_START, MMIO, PAGE_OFFSET_MASK, PA_TO_VA};
+use common::config::{
+    LOW_ADDRESS_END, MEMORY_END, MMAP_START, MMIO, PAGE_OFFSET_MASK, PA_TO_VA, SIGNAL_TRAMPOLINE,
+    VDSO_BASE, VDSO_DATA,
+};
 use compact_str::CompactString;
 use defines::{
     error::{errno, KResult},
     misc::{MmapFlags, MmapProt},
+    syscall::RT_SIGRETURN,
 };
 use elf::{Elf, PF_R, PF_W, PF_X, PT_INTERP, PT_LOAD};
 use klocks::Lazy;
 use smallvec::SmallVec;
+use triomphe::Arc;
 use virtio_drivers::PAGE_SIZE;
 use vm_area::AreaType;
 
 use self::{
-    init_stack::{StackInitCtx, AT_BASE, AT_ENTRY, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM},
+    init_stack::{
+        StackInitCtx, AT_BASE, AT_ENTRY, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_SYSINFO_EHDR,
+    },
     vm_area::{BackedInode, FramedVmArea},
 };
 use super::{
-    kernel_pa_to_va, kernel_vpn_to_ppn, PTEFlags, PageTable, PhysAddr, VirtAddr, VirtPageNum,
+    kernel_pa_to_va, kernel_vpn_to_ppn,
+    vdso::{VDSO_DATA_FRAME, VDSO_FRAME},
+    Frame, PTEFlags, Page, PageTable, PhysAddr, PhysPageNum, VirtAddr, VirtPageNum,
 };
-use crate::thread::Thread;
+use crate::{hart, thread::Thread};
 
 pub mod init_stack;
 pub mod page_table;
@@ -32,6 +41,21 @@ pub mod vm_area;
 
 pub static KERNEL_SPACE: Lazy<MemorySpace> = Lazy::new(MemorySpace::new_kernel);
 
+/// 信号 trampoline 所在的物理页，所有用户地址空间共享，映射在 [`SIGNAL_TRAMPOLINE`] 处。
+///
+/// 其中只有 `li a7, RT_SIGRETURN; ecall` 两条指令，
+/// 用作没有设置 `SA_RESTORER` 的 signal handler 的返回地址
+static SIGNAL_TRAMPOLINE_FRAME: Lazy<Frame> = Lazy::new(|| {
+    let mut frame = Frame::alloc().unwrap();
+    // addi a7, zero, RT_SIGRETURN
+    let li_a7 = ((RT_SIGRETURN as u32) << 20) | (17 << 7) | 0x13;
+    let ecall: u32 = 0x73;
+    let bytes = frame.as_page_bytes_mut();
+    bytes[0..4].copy_from_slice(&li_a7.to_le_bytes());
+    bytes[4..8].copy_from_slice(&ecall.to_le_bytes());
+    frame
+});
+
 /// 进程的内存地址空间
 pub struct MemorySpace {
     page_table: PageTable,
@@ -106,6 +130,8 @@ impl MemorySpace {
     pub fn empty_user() -> Self {
         let mut ret = Self::new_bare();
         ret.map_kernel_areas();
+        ret.map_signal_trampoline();
+        ret.map_vdso();
         ret
     }
 
@@ -113,6 +139,10 @@ impl MemorySpace {
     pub fn from_other(user_space: &Self) -> Self {
         let mut memory_set = Self::new_bare();
         for src_area in user_space.user_areas.values() {
+            // 保留区域在下面重新映射
+            if src_area.area_type() == AreaType::Reserved {
+                continue;
+            }
             let vpn_range = src_area.vpn_range();
             unsafe {
                 if let Some(backed_inode) = src_area.backed_inode() {
@@ -138,6 +168,8 @@ impl MemorySpace {
             }
         }
         memory_set.map_kernel_areas();
+        memory_set.map_signal_trampoline();
+        memory_set.map_vdso();
         memory_set
     }
 
@@ -152,7 +184,7 @@ impl MemorySpace {
         elf_data: &[u8],
     ) -> KResult<(VirtAddr, Vec<(u8, usize)>, usize)> {
         let mut elf_end = VirtAddr(0);
-        let mut auxv = Vec::with_capacity(6);
+        let mut auxv = Vec::with_capacity(7);
         // TODO: [low] 实现动态链接和 elf 懒加载
         let elf_entry = elf.entry as usize;
         auxv.extend_from_slice(&[
@@ -161,6 +193,7 @@ impl MemorySpace {
             (AT_PAGESZ, PAGE_SIZE),
             (AT_BASE, 0),
             (AT_ENTRY, elf_entry),
+            (AT_SYSINFO_EHDR, VDSO_BASE),
         ]);
 
         if let Some(interpreter) = elf.interpreter {
@@ -219,24 +252,61 @@ impl MemorySpace {
         self.page_table.map_kernel_areas();
     }
 
-    /// 需保证 `heap_start` < `new_end`，且还有足够的虚地址和物理空间可以映射
-    pub fn set_user_brk(&mut self, heap_start: VirtPageNum, new_end: VirtPageNum) {
+    /// 映射信号 trampoline 页，只读且可执行。调用 [`Self::recycle_user_pages()`] 之后需要重新映射
+    pub fn map_signal_trampoline(&mut self) {
+        self.map_reserved_page(
+            VirtAddr(SIGNAL_TRAMPOLINE).vpn_floor(),
+            SIGNAL_TRAMPOLINE_FRAME.ppn(),
+            MapPermission::R | MapPermission::X | MapPermission::U,
+        );
+    }
+
+    /// 将共享的物理页 `ppn` 映射在 `vpn` 处，并登记为保留区域
+    fn map_reserved_page(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, perm: MapPermission) {
+        self.page_table.map(vpn, ppn, PTEFlags::from(perm));
+        let area = FramedVmArea::new(vpn..vpn + 1, perm, AreaType::Reserved);
+        self.user_areas.insert(vpn, area);
+    }
+
+    /// 映射 vDSO 映像页和数据页，前者只读且可执行，后者只读。同信号 trampoline 一样，
+    /// 调用 [`Self::recycle_user_pages()`] 之后需要重新映射
+    pub fn map_vdso(&mut self) {
+        self.map_reserved_page(
+            VirtAddr(VDSO_BASE).vpn_floor(),
+            VDSO_FRAME.ppn(),
+            MapPermission::R | MapPermission::X | MapPermission::U,
+        );
+        self.map_reserved_page(
+            VirtAddr(VDSO_DATA).vpn_floor(),
+            VDSO_DATA_FRAME.ppn(),
+            MapPermission::R | MapPermission::U,
+        );
+    }
+
+    /// 需保证 `heap_start` < `new_end`，且还有足够的虚地址和物理空间可以映射。
+    ///
+    /// 堆区收缩时返回被取消映射的页，调用者需要在释放锁后调用 [`UnmappedPages::shootdown()`]
+    pub fn set_user_brk(
+        &mut self,
+        heap_start: VirtPageNum,
+        new_end: VirtPageNum,
+    ) -> Option<UnmappedPages> {
         // TODO: [low] 其实这里还需要考虑堆区之上有没有已经映射过的地址吧？
         // 堆区已经映射过了，就扩张或者收缩。否则插入堆区
         // 注意扩张和插入的堆区都是懒分配的
         if let Some(map_area) = self.user_areas.get_mut(&heap_start) {
             if new_end <= map_area.vpn_range().end {
-                map_area.shrink(new_end, &mut self.page_table);
-                flush_tlb(None);
-            } else {
-                map_area.expand(new_end);
+                let pages = map_area.shrink(new_end, &mut self.page_table);
+                return Some(UnmappedPages(pages.into_values().collect()));
             }
+            map_area.expand(new_end);
         } else {
             let perm = MapPermission::R | MapPermission::W | MapPermission::U;
             unsafe {
                 self.user_map(heap_start..new_end, perm);
             }
         }
+        None
     }
 
     /// 尝试根据 `va_range` 进行映射
@@ -255,7 +325,8 @@ impl MemorySpace {
         Ok(vpn_range.start)
     }
 
-    /// 尝试根据 `va_range` 进行映射
+    /// 尝试根据 `va_range` 进行映射。可能会立刻映射文件已缓存的页，
+    /// 因此调用者需要在释放锁后调用 [`shootdown_tlb()`]
     pub fn try_map_inode(
         &mut self,
         addr: usize,
@@ -270,8 +341,6 @@ impl MemorySpace {
         unsafe {
             self.user_map_with_file(vpn_range.clone(), perm, inode, inode_page_id);
         }
-        // TODO: [mid] 映射函数其实可以返回是否有真正映射，有的话才需要刷新 TLB
-        flush_tlb(None);
         Ok(vpn_range.start)
     }
 
@@ -311,8 +380,13 @@ impl MemorySpace {
         Err(errno::ENOMEM)
     }
 
-    /// 将 `va_range` 范围内的所有页取消映射。有可能导致某个 area 被部分截断
-    pub fn unmap(&mut self, va_range: Range<VirtAddr>) {
+    /// 将 `va_range` 范围内的所有页取消映射。有可能导致某个 area 被部分截断。
+    ///
+    /// 调用者需要在释放锁后对返回值调用 [`UnmappedPages::shootdown()`]
+    ///
+    /// 错误：
+    /// - `EINVAL` 范围与保留区域重叠，此时不会取消任何映射
+    pub fn unmap(&mut self, va_range: Range<VirtAddr>) -> KResult<UnmappedPages> {
         let vpn_range = va_range.start.vpn_floor()..va_range.end.vpn_ceil();
         let mut cursor = self
             .user_areas
@@ -323,6 +397,12 @@ impl MemorySpace {
 
         while let Some((_, area)) = cursor.next() {
             let area_vpn_range = area.vpn_range();
+            if area.area_type() == AreaType::Reserved
+                && area_vpn_range.start < vpn_range.end
+                && vpn_range.start < area_vpn_range.end
+            {
+                return Err(errno::EINVAL);
+            }
             if vpn_range.start <= area_vpn_range.start && area_vpn_range.end <= vpn_range.end {
                 to_unmap.push(area_vpn_range.start);
                 // area 被完全包含在内
@@ -338,18 +418,34 @@ impl MemorySpace {
             }
         }
 
+        let mut unmapped = UnmappedPages(Vec::new());
         for vpn in to_unmap {
             let mut area = self.user_areas.remove(&vpn).unwrap();
-            area.unmap(&mut self.page_table);
+            unmapped
+                .0
+                .extend(area.unmap(&mut self.page_table).into_values());
         }
+        Ok(unmapped)
+    }
 
-        flush_tlb(None);
+    /// 所有用户区域的总大小（字节），用于 `RLIMIT_AS` 的检查。不包括信号 trampoline 等内核映射的保留区域
+    pub fn user_size(&self) -> usize {
+        self.user_areas
+            .values()
+            .filter(|area| area.area_type() != AreaType::Reserved)
+            .map(FramedVmArea::len)
+            .sum()
     }
 
-    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+    /// 移除以 `start_vpn` 开头的区域。调用者需要在释放锁后对返回值调用 [`UnmappedPages::shootdown()`]
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) -> UnmappedPages {
+        let mut unmapped = UnmappedPages(Vec::new());
         if let Some(mut area) = self.user_areas.remove(&start_vpn) {
-            area.unmap(&mut self.page_table);
+            unmapped
+                .0
+                .extend(area.unmap(&mut self.page_table).into_values());
         }
+        unmapped
     }
 
     /// 映射一段用户的帧映射内存区域。但并不立刻分配内存
@@ -419,6 +515,12 @@ impl MemorySpace {
         }
     }
 
+    /// 查询虚拟地址 `va` 对应的物理地址。未映射则返回 `None`
+    pub fn translate(&self, va: VirtAddr) -> Option<PhysAddr> {
+        let ppn = self.page_table.translate(va.vpn_floor())?;
+        Some(ppn.page_start() + va.page_offset())
+    }
+
     pub fn recycle_user_pages(&mut self) {
         self.user_areas.clear();
         self.page_table.clear();
@@ -442,6 +544,8 @@ impl MemorySpace {
                         return true;
                     }
                     AreaType::Mmap => todo!("[high] impl mmap memory"),
+                    // 保留区域总是已经映射好的，出现异常说明访问权限不符
+                    AreaType::Reserved => {}
                 }
             }
             false
@@ -451,12 +555,13 @@ impl MemorySpace {
     // 返回 `user_sp` 与 `argv_base`
     pub fn init_stack(
         &mut self,
-        tid: usize,
+        stack_id: usize,
+        stack_size: usize,
         args: Vec<CompactString>,
         envs: Vec<CompactString>,
         auxv: Vec<(u8, usize)>,
     ) -> (usize, usize) {
-        let ustack_range = Thread::alloc_user_stack(tid, self);
+        let ustack_range = Thread::alloc_user_stack(stack_id, stack_size, self);
         let area = self.user_areas.get_mut(&ustack_range.start).unwrap();
 
         let ctx = StackInitCtx::new(ustack_range.end, &mut self.page_table, args, envs, auxv);
@@ -493,6 +598,26 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// 在所有已启动的 hart 上刷新 tlb。修改映射后需要调用，因为同一进程的其他线程可能正运行在其他 hart 上。
+///
+/// 会等待其他 hart 刷新完毕，因此调用时需要开中断，且不能持有任何锁。
+/// 否则另一个 hart 关着中断等待该锁时，双方会互相等待
+pub fn shootdown_tlb(vaddr: Option<VirtAddr>) {
+    hart::cross_call(hart::online_harts(), move || flush_tlb(vaddr), true);
+}
+
+/// 被取消映射的用户页。其他 hart 的 TLB 中可能还缓存着它们的映射，
+/// 因此要先通过 [`Self::shootdown()`] 刷新所有 hart 的 TLB 才能释放
+#[must_use]
+pub struct UnmappedPages(Vec<Arc<Page>>);
+
+impl UnmappedPages {
+    /// 在所有 hart 上刷新 TLB，然后释放这些页。同 [`shootdown_tlb()`]，调用时不能持有任何锁
+    pub fn shootdown(self) {
+        shootdown_tlb(None);
+    }
+}
+
 extern "C" {
     fn stext();
     fn etext();
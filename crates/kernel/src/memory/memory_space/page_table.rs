@@ -133,6 +133,32 @@ impl PageTable {
         ret
     }
 
+    /// 找到 `vpn` 对应的叶子页表项，不会创建中间的页表。若中间的页表不存在则返回 `None`
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<&PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_frame.ppn();
+        for (i, &idx) in idxs.iter().enumerate() {
+            // SAFETY: 页表中指定的 ppn 必然已经分配
+            let pte = unsafe { &Frame::view(ppn).as_page_ptes()[idx] };
+            // 这里假定为 3 级页表
+            if i == 2 {
+                return Some(pte);
+            }
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        None
+    }
+
+    /// 查询 `vpn` 映射到的物理页号。未映射则返回 `None`
+    pub(super) fn translate(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        self.find_pte(vpn)
+            .filter(|pte| pte.is_valid())
+            .map(PageTableEntry::ppn)
+    }
+
     pub(super) fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
         let pte = self.find_pte_create(vpn).unwrap();
         debug_assert!(
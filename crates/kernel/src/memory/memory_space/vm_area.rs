@@ -1,5 +1,8 @@
 use alloc::collections::{BTreeMap, BTreeSet};
-use core::ops::{Deref, Range};
+use core::{
+    mem,
+    ops::{Deref, Range},
+};
 
 use common::config::PAGE_SIZE;
 use triomphe::Arc;
@@ -50,6 +53,9 @@ impl Deref for BackedInode {
 pub enum AreaType {
     Lazy,
     Mmap,
+    /// 映射到所有地址空间共享的内核物理页（如信号 trampoline 和 vDSO），不由 area 管理物理页，
+    /// 也不能被 mmap 覆盖或者被 munmap 取消映射
+    Reserved,
 }
 
 impl FramedVmArea {
@@ -155,27 +161,31 @@ impl FramedVmArea {
         }
     }
 
-    pub(super) fn unmap(&mut self, page_table: &mut PageTable) {
+    /// 取消该区域的所有映射，返回其独占的页。刷新所有 hart 的 TLB 之前，这些页不能被释放
+    pub(super) fn unmap(&mut self, page_table: &mut PageTable) -> BTreeMap<VirtPageNum, Arc<Page>> {
         for &mapped in self.unbacked_map.keys().chain(&self.backed_pages) {
             page_table.unmap(mapped);
         }
-        self.unbacked_map.clear();
         self.backed_inode = None;
         self.backed_pages.clear();
         self.backed_inode_page_id = 0;
+        mem::take(&mut self.unbacked_map)
     }
 
-    /// 尝试收缩末尾区域
-    pub fn shrink(&mut self, new_end: VirtPageNum, page_table: &mut PageTable) {
+    /// 尝试收缩末尾区域，返回被取消映射的页。同 [`Self::unmap()`]，这些页需要在刷新 TLB 之后才能释放
+    pub fn shrink(
+        &mut self,
+        new_end: VirtPageNum,
+        page_table: &mut PageTable,
+    ) -> BTreeMap<VirtPageNum, Arc<Page>> {
         // TODO: vm area 收缩暂时不考虑文件后备
         assert!(self.area_type == AreaType::Lazy);
-        {
-            let split = self.unbacked_map.split_off(&new_end);
-            for &mapped in split.keys() {
-                page_table.unmap(mapped);
-            }
+        let split = self.unbacked_map.split_off(&new_end);
+        for &mapped in split.keys() {
+            page_table.unmap(mapped);
         }
         self.vpn_range.end = new_end;
+        split
     }
 
     /// 尝试扩展末尾区域
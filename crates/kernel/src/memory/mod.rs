@@ -4,6 +4,7 @@ mod kernel_heap;
 mod memory_space;
 mod page;
 mod user_check;
+mod vdso;
 
 use common::config::{PAGE_SIZE, PA_TO_VA};
 
@@ -13,11 +14,13 @@ pub use self::{
     memory_space::{
         flush_tlb, log_kernel_sections,
         page_table::{PTEFlags, PageTable},
+        shootdown_tlb,
         vm_area::{BackedInode, FramedVmArea},
-        MapPermission, MemorySpace, KERNEL_SPACE,
+        MapPermission, MemorySpace, UnmappedPages, KERNEL_SPACE,
     },
     page::Page,
     user_check::{ReadBuffer, UserCheck},
+    vdso::set_realtime_offset,
 };
 
 #[inline]
@@ -0,0 +1,76 @@
+//! vDSO，用于在用户态直接读取时钟而无需陷入内核
+//!
+//! vDSO 映像由 `vdso.S` 汇编而成，映射在每个用户地址空间的 [`VDSO_BASE`](common::config::VDSO_BASE) 处，
+//! 通过 `AT_SYSINFO_EHDR` 告知用户程序。它的代码读取 `time` CSR，再结合数据页中的信息计算时间
+
+use core::{
+    mem::offset_of,
+    sync::atomic::{AtomicI64, Ordering},
+};
+
+use common::{
+    config::{CLOCK_FREQ, PAGE_SIZE},
+    constant::NANO_PER_SEC,
+};
+use defines::syscall::{CLOCK_GETTIME, GET_TIME_OF_DAY};
+use klocks::Lazy;
+
+use super::Frame;
+
+core::arch::global_asm!(
+    include_str!("vdso.S"),
+    CLOCK_GETTIME = const CLOCK_GETTIME,
+    GETTIMEOFDAY = const GET_TIME_OF_DAY,
+    NANO_PER_SEC = const NANO_PER_SEC,
+    DATA_NS_PER_TICK = const offset_of!(VdsoData, ns_per_tick),
+    DATA_REALTIME_OFFSET = const offset_of!(VdsoData, realtime_offset_ns),
+);
+
+// vDSO 中直接用时钟次数乘以每次的纳秒数，因此要求能够整除
+const _: () = assert!(NANO_PER_SEC % CLOCK_FREQ == 0);
+
+/// vDSO 数据页的内容，用户态只读
+#[repr(C)]
+struct VdsoData {
+    /// 挂钟时间与单调时钟之差（纳秒），同 [`crate::time`] 中的记录
+    realtime_offset_ns: AtomicI64,
+    /// 每个时钟次数对应的纳秒数
+    // 内核只在初始化时写入它，之后由 vDSO 的汇编按 `DATA_NS_PER_TICK` 偏移读取。
+    // 保留该字段只是为了固定用户态看到的数据页布局，因此 Rust 中从不读取
+    #[allow(dead_code)]
+    ns_per_tick: u64,
+}
+
+/// vDSO 映像所在的物理页，所有用户地址空间共享
+pub(super) static VDSO_FRAME: Lazy<Frame> = Lazy::new(|| {
+    extern "C" {
+        fn svdso();
+        fn evdso();
+    }
+    let len = evdso as usize - svdso as usize;
+    assert!(len <= PAGE_SIZE, "vDSO image too large: {len} bytes");
+    let mut frame = Frame::alloc().unwrap();
+    // SAFETY: `svdso..evdso` 在内核的 rodata 段中
+    let image = unsafe { core::slice::from_raw_parts(svdso as usize as *const u8, len) };
+    let bytes = frame.as_page_bytes_mut();
+    bytes[..len].copy_from_slice(image);
+    bytes[len..].fill(0);
+    frame
+});
+
+/// vDSO 数据页所在的物理页，所有用户地址空间共享
+pub(super) static VDSO_DATA_FRAME: Lazy<Frame> = Lazy::new(|| {
+    let mut frame = Frame::alloc().unwrap();
+    frame.as_page_bytes_mut().fill(0);
+    // SAFETY: 页起始处满足对齐，且 `VdsoData` 远小于一页
+    let data = unsafe { frame.as_mut_at::<VdsoData>(0) };
+    data.ns_per_tick = (NANO_PER_SEC / CLOCK_FREQ) as u64;
+    frame
+});
+
+/// 更新数据页中挂钟时间与单调时钟之差，在挂钟被设置时调用
+pub fn set_realtime_offset(offset_ns: i64) {
+    // SAFETY: 同上
+    let data = unsafe { VDSO_DATA_FRAME.as_ref_at::<VdsoData>(0) };
+    data.realtime_offset_ns.store(offset_ns, Ordering::Relaxed);
+}
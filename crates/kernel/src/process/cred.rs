@@ -0,0 +1,156 @@
+use alloc::vec::Vec;
+
+use defines::error::{errno, KResult};
+
+/// 进程的用户凭证，参考 linux 的 `struct cred`
+///
+/// 暂时没有实现 capabilities，有效用户 ID 为 0 即视为拥有所有特权。
+/// 也没有实现 fsuid 和 fsgid，文件系统的权限检查使用有效用户 ID 和有效组 ID
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    /// 真实用户 ID
+    pub uid: u32,
+    /// 有效用户 ID，用于权限检查
+    pub euid: u32,
+    /// 保存的 set-user-ID
+    pub suid: u32,
+    /// 真实组 ID
+    pub gid: u32,
+    /// 有效组 ID，用于权限检查
+    pub egid: u32,
+    /// 保存的 set-group-ID
+    pub sgid: u32,
+    /// 附加组
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// root 用户的凭证，也用于内核自身的文件访问
+    pub const ROOT: Self = Self {
+        uid: 0,
+        euid: 0,
+        suid: 0,
+        gid: 0,
+        egid: 0,
+        sgid: 0,
+        groups: Vec::new(),
+    };
+
+    /// 是否拥有特权，即有效用户 ID 是否为 0
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// `gid` 是否为有效组 ID 或者附加组之一
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.egid == gid || self.groups.contains(&gid)
+    }
+
+    /// 是否有权限向凭证为 `target` 的进程发送信号。特权进程可以向任何进程发送信号，
+    /// 否则真实用户 ID 或有效用户 ID 需要与目标的真实用户 ID 或保存的 set-user-ID 相同
+    pub fn can_signal(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || [self.uid, self.euid]
+                .into_iter()
+                .any(|id| id == target.uid || id == target.suid)
+    }
+
+    /// 是否有权限获取或修改凭证为 `target` 的进程的资源限制。特权进程总是可以，否则真实用户 ID
+    /// 需要与目标的三个用户 ID 都相同，且真实组 ID 需要与目标的三个组 ID 都相同
+    pub fn can_prlimit(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || [target.uid, target.euid, target.suid]
+                .into_iter()
+                .all(|id| id == self.uid)
+                && [target.gid, target.egid, target.sgid]
+                    .into_iter()
+                    .all(|id| id == self.gid)
+    }
+
+    /// 是否有权限修改凭证为 `target` 的进程的调度参数（nice 值、调度策略等）。特权进程总是可以，
+    /// 否则有效用户 ID 需要与目标的真实用户 ID 或有效用户 ID 相同
+    pub fn can_set_sched(&self, target: &Credentials) -> bool {
+        self.is_privileged() || self.euid == target.uid || self.euid == target.euid
+    }
+
+    /// `setuid` 的语义：特权进程会同时设置三个用户 ID，否则只能将有效用户 ID 设为真实用户 ID 或保存的
+    /// set-user-ID
+    ///
+    /// 错误：
+    /// - `EPERM` 非特权进程，且 `uid` 不是真实用户 ID 或保存的 set-user-ID
+    pub fn set_uid(&mut self, uid: u32) -> KResult<()> {
+        if self.is_privileged() {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(errno::EPERM);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    /// `setgid` 的语义，同 [`Credentials::set_uid`]
+    ///
+    /// 错误：
+    /// - `EPERM` 非特权进程，且 `gid` 不是真实组 ID 或保存的 set-group-ID
+    pub fn set_gid(&mut self, gid: u32) -> KResult<()> {
+        if self.is_privileged() {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(errno::EPERM);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+
+    /// `setresuid` 的语义。为 None 的 ID 保持不变。非特权进程只能将各 ID 设为当前三个用户 ID 之一
+    ///
+    /// 错误：
+    /// - `EPERM` 非特权进程，且某个 ID 不是当前三个用户 ID 之一
+    pub fn set_resuid(
+        &mut self,
+        ruid: Option<u32>,
+        euid: Option<u32>,
+        suid: Option<u32>,
+    ) -> KResult<()> {
+        let ids = [self.uid, self.euid, self.suid];
+        if !self.is_privileged()
+            && [ruid, euid, suid]
+                .into_iter()
+                .flatten()
+                .any(|id| !ids.contains(&id))
+        {
+            return Err(errno::EPERM);
+        }
+        self.uid = ruid.unwrap_or(self.uid);
+        self.euid = euid.unwrap_or(self.euid);
+        self.suid = suid.unwrap_or(self.suid);
+        Ok(())
+    }
+
+    /// `setresgid` 的语义，同 [`Credentials::set_resuid`]
+    ///
+    /// 错误：
+    /// - `EPERM` 非特权进程，且某个 ID 不是当前三个组 ID 之一
+    pub fn set_resgid(
+        &mut self,
+        rgid: Option<u32>,
+        egid: Option<u32>,
+        sgid: Option<u32>,
+    ) -> KResult<()> {
+        let ids = [self.gid, self.egid, self.sgid];
+        if !self.is_privileged()
+            && [rgid, egid, sgid]
+                .into_iter()
+                .flatten()
+                .any(|id| !ids.contains(&id))
+        {
+            return Err(errno::EPERM);
+        }
+        self.gid = rgid.unwrap_or(self.gid);
+        self.egid = egid.unwrap_or(self.egid);
+        self.sgid = sgid.unwrap_or(self.sgid);
+        Ok(())
+    }
+}
@@ -1,18 +1,22 @@
 use alloc::vec::Vec;
 use core::ops::Range;
 
-use common::config::LOW_ADDRESS_END;
+use common::config::{LOW_ADDRESS_END, MAX_USER_STACK_SIZE, PAGE_SIZE, USER_STACK_SIZE};
+use defines::resource::{
+    RLimit, RLIMIT_AS, RLIMIT_DATA, RLIMIT_NICE, RLIMIT_NOFILE, RLIMIT_RTPRIO, RLIMIT_STACK,
+    RLIM_INFINITY, RLIM_NLIMITS,
+};
 use hashbrown::HashMap;
 use idallocator::RecycleAllocator;
-use memory::{MemorySpace, VirtAddr};
+use memory::{MemorySpace, UnmappedPages, VirtAddr};
 use triomphe::Arc;
 
-use super::Process;
+use super::{Credentials, JobEvent, Process, ProcessTimers};
 use crate::{
     fs::{DEntryDir, FdTable},
     memory,
-    signal::{KSignalSet, Signal, SignalHandlers},
-    thread::Thread,
+    signal::{KSigInfo, KSignalSet, SignalHandlers},
+    thread::{CpuTimes, Thread},
 };
 
 pub struct ProcessInner {
@@ -26,8 +30,28 @@ pub struct ProcessInner {
     ///
     /// `heap_range.end` 即 brk，由 `sys_brk` 系统调用控制
     pub heap_range: Range<VirtAddr>,
+    /// 每个线程用户栈的大小，在加载程序时根据 `RLIMIT_STACK` 确定
+    pub user_stack_size: usize,
 
     // 进程
+    /// 进程组号
+    pub pgid: usize,
+    /// 会话号
+    pub sid: usize,
+    /// 用户凭证
+    pub cred: Credentials,
+    /// 资源限制，以资源种类为下标。其中 `RLIMIT_NOFILE` 保存在 `fd_table` 中，这里的不使用
+    pub rlimits: [RLimit; RLIM_NLIMITS],
+    /// 已退出线程的 CPU 时间之和
+    pub exited_times: CpuTimes,
+    /// 已被回收的子进程的 CPU 时间之和，包括这些子进程自己回收的子进程
+    pub children_times: CpuTimes,
+    /// 间隔定时器和 POSIX 定时器
+    pub timers: ProcessTimers,
+    /// 进程是否因信号而停止
+    pub stopped: bool,
+    /// 尚未被父进程通过 `sys_wait4` 获知的作业控制状态变化
+    pub job_event: Option<JobEvent>,
     /// 父进程引用
     pub parent: Option<Arc<Process>>,
     /// 子进程引用列表
@@ -44,45 +68,138 @@ pub struct ProcessInner {
     pub signal_handlers: SignalHandlers,
 
     // 线程
-    /// 线程 tid 分配器
-    pub tid_allocator: RecycleAllocator,
-    /// 线程引用列表
+    /// 线程 `stack_id` 分配器，决定各线程用户栈的位置
+    pub stack_id_allocator: RecycleAllocator,
+    /// 线程引用列表，以 tid 为键
     pub threads: HashMap<usize, Arc<Thread>>,
 }
 
+/// 新进程的默认资源限制。和 Linux 一样，非特权进程默认不能提高 nice 值和实时优先级，
+/// 除此之外只有用户栈和文件描述符有限制
+pub(super) fn default_rlimits() -> [RLimit; RLIM_NLIMITS] {
+    let mut rlimits = [RLimit {
+        rlim_curr: RLIM_INFINITY,
+        rlim_max: RLIM_INFINITY,
+    }; RLIM_NLIMITS];
+    rlimits[RLIMIT_STACK as usize].rlim_curr = USER_STACK_SIZE;
+    for resource in [RLIMIT_NICE, RLIMIT_RTPRIO] {
+        rlimits[resource as usize] = RLimit {
+            rlim_curr: 0,
+            rlim_max: 0,
+        };
+    }
+    rlimits
+}
+
 impl ProcessInner {
-    pub fn main_thread(&self) -> Arc<Thread> {
-        Arc::clone(self.threads.get(&0).unwrap())
+    /// 进程的 CPU 时间，即所有线程（包括已退出的）的 CPU 时间之和
+    pub fn cpu_times(&self) -> CpuTimes {
+        self.threads
+            .values()
+            .fold(self.exited_times, |times, thread| {
+                times + thread.time_stat.times()
+            })
+    }
+
+    /// 获取资源 `resource` 的限制，需保证 `resource` 小于 `RLIM_NLIMITS`
+    pub fn rlimit(&self, resource: u32) -> RLimit {
+        if resource == RLIMIT_NOFILE {
+            self.fd_table.rlimit()
+        } else {
+            self.rlimits[resource as usize]
+        }
     }
 
-    /// 设置用户堆顶。失败返回原来的 brk，成功则返回新的 brk
+    /// 设置资源 `resource` 的限制，需保证 `resource` 小于 `RLIM_NLIMITS`。
+    ///
+    /// 不会检查权限，也不会检查软上限是否超过了硬上限
+    pub fn set_rlimit(&mut self, resource: u32, rlimit: RLimit) {
+        if resource == RLIMIT_NOFILE {
+            self.fd_table.set_rlimit(rlimit);
+        } else {
+            self.rlimits[resource as usize] = rlimit;
+        }
+    }
+
+    /// 根据 `RLIMIT_STACK` 计算加载新程序时的用户栈大小。会按页对齐，且限制在一页和
+    /// [`MAX_USER_STACK_SIZE`] 之间
+    pub fn stack_size_from_rlimit(&self) -> usize {
+        let size = self.rlimits[RLIMIT_STACK as usize].rlim_curr;
+        (size & !(PAGE_SIZE - 1)).clamp(PAGE_SIZE, MAX_USER_STACK_SIZE)
+    }
+
+    /// 再映射 `len` 字节之后，地址空间的总大小是否会超过 `RLIMIT_AS`
+    pub fn exceeds_address_space_limit(&self, len: usize) -> bool {
+        self.memory_space.user_size().saturating_add(len)
+            > self.rlimits[RLIMIT_AS as usize].rlim_curr
+    }
+
+    /// 设置用户堆顶。失败返回原来的 brk，成功则返回新的 brk。
+    ///
+    /// 堆区收缩时还会返回被取消映射的页，需要在释放锁后调用 [`UnmappedPages::shootdown()`]
     ///
     /// 失败的情况包括：
     ///
     /// - `new_brk` 不大于堆的开头
     /// - `new_brk` 超过低地址空间末端
-    pub fn set_user_brk(&mut self, new_brk: VirtAddr) -> VirtAddr {
+    /// - 堆的大小超过 `RLIMIT_DATA`
+    /// - 堆扩张后地址空间的总大小超过 `RLIMIT_AS`
+    pub fn set_user_brk(&mut self, new_brk: VirtAddr) -> (VirtAddr, Option<UnmappedPages>) {
         if new_brk <= self.heap_range.start || new_brk.0 > LOW_ADDRESS_END {
-            return self.heap_range.end;
+            return (self.heap_range.end, None);
+        }
+        // TODO: [low] Linux 中 `RLIMIT_DATA` 还包括 elf 的数据段以及私有可写的映射
+        if new_brk.0 - self.heap_range.start.0 > self.rlimits[RLIMIT_DATA as usize].rlim_curr {
+            return (self.heap_range.end, None);
         }
         // 由于上面的条件语句，下面一定有 `heap_start < new_end`
         let heap_start = self.heap_range.start.vpn_floor();
         let new_end = new_brk.vpn_ceil();
-        self.memory_space.set_user_brk(heap_start, new_end);
+        let old_end = self.heap_range.end.vpn_ceil();
+        if new_end > old_end
+            && self.exceeds_address_space_limit((new_end.0 - old_end.0) * PAGE_SIZE)
+        {
+            return (self.heap_range.end, None);
+        }
+        let unmapped = self.memory_space.set_user_brk(heap_start, new_end);
         self.heap_range.end = new_brk;
-        new_brk
+        (new_brk, unmapped)
     }
 
-    /// 挑选一个合适的线程让其处理信号
-    pub fn receive_signal(&mut self, signal: Signal) {
+    /// 挑选一个合适的线程让其处理信号。
+    ///
+    /// 优先挑选未屏蔽该信号的线程，若所有线程都屏蔽了该信号，则交给任意一个线程，待其解除屏蔽后处理。
+    /// 如果标准信号已经在某个线程中待处理，则不会重复接收；而实时信号总是会排队
+    pub(super) fn receive_signal(&mut self, info: KSigInfo) {
+        let signal = info.signal;
+        let set = KSignalSet::from(signal);
+        let mut target = None;
         for thread in self.threads.values() {
-            let mut inner = thread.lock_inner();
-            let signal = KSignalSet::from(signal);
-            if !inner.signal_mask.contains(signal) && !inner.pending_signal.contains(signal) {
-                debug!("thread {} receive signal {signal:?}", thread.tid());
-                inner.pending_signal.insert(signal);
+            let inner = thread.lock_inner();
+            if !signal.is_realtime() && inner.pending_signal.contains(signal) {
+                return;
+            }
+            if !inner.signal_mask.contains(set) {
+                target = Some(thread);
                 break;
             }
+            target.get_or_insert(thread);
+        }
+        if let Some(thread) = target {
+            self.receive_thread_signal(thread, info);
+        }
+    }
+
+    /// 令本进程中的线程 `thread` 收到信号。
+    ///
+    /// 未被屏蔽且会被忽略的信号会被直接丢弃，以免无谓地打断线程
+    pub(super) fn receive_thread_signal(&self, thread: &Thread, info: KSigInfo) {
+        let signal = info.signal;
+        let masked = thread.lock_inner_with(|inner| inner.signal_mask.contains(signal.into()));
+        if !masked && self.signal_handlers.is_ignored(signal) {
+            debug!("signal {signal:?} is ignored");
+            return;
         }
+        thread.receive_signal(info);
     }
 }
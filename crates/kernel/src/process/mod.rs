@@ -1,26 +1,36 @@
+mod cred;
 mod inner;
+mod timer;
 
-use alloc::{vec, vec::Vec};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
 use core::num::NonZeroUsize;
 
 use atomic::{Atomic, Ordering};
+use common::config::USER_STACK_SIZE;
 use compact_str::CompactString;
-use defines::error::{errno, KResult};
+use defines::{
+    error::{errno, KResult},
+    resource::RLIMIT_CPU,
+    signal::{SignalActionFlags, SignalStack, CLD_CONTINUED, CLD_STOPPED},
+};
 use elf::Elf;
-use event_listener::Event;
+use event_listener::{listener, Event};
 use hashbrown::HashMap;
 use idallocator::RecycleAllocator;
 use klocks::{Lazy, SpinMutex, SpinMutexGuard};
 use memory::MemorySpace;
 use triomphe::Arc;
 
+pub use self::cred::Credentials;
 use self::inner::ProcessInner;
+pub use self::timer::{PosixTimer, ProcessTimers, RealTimer, TimerNotify, TimerSignal};
 use crate::{
-    executor,
+    executor::{self, SchedEntity},
     fs::{self, DEntry, FdTable, VFS},
+    hart::local_hart,
     memory,
-    signal::{KSignalSet, Signal, SignalHandlers},
-    thread::{self, Thread},
+    signal::{DefaultHandler, KSigInfo, KSignalSet, Signal, SignalHandlers},
+    thread::{self, CpuTimes, Thread},
     trap::TrapContext,
 };
 
@@ -32,6 +42,50 @@ pub static INITPROC: Lazy<Arc<Process>> = Lazy::new(|| {
     .expect("INITPROC Failed.")
 });
 
+/// 所有尚未被回收的进程（包括僵尸进程），以 pid 为键。
+///
+/// 进程在创建时加入，在被父进程 `sys_wait4` 回收时移除
+static PROCESSES: SpinMutex<BTreeMap<usize, Arc<Process>>> = SpinMutex::new(BTreeMap::new());
+
+/// 根据 pid 查找进程
+pub fn find_process(pid: usize) -> Option<Arc<Process>> {
+    PROCESSES.lock().get(&pid).cloned()
+}
+
+/// 获取所有进程的引用。注意返回时不再持有锁，因此之后进程可能已经被回收了
+pub fn all_processes() -> Vec<Arc<Process>> {
+    PROCESSES.lock().values().cloned().collect()
+}
+
+/// 获取进程组 `pgid` 中的所有进程
+pub fn processes_in_group(pgid: usize) -> Vec<Arc<Process>> {
+    all_processes()
+        .into_iter()
+        .filter(|process| process.lock_inner_with(|inner| inner.pgid) == pgid)
+        .collect()
+}
+
+/// 向进程组 `pgid` 中的所有进程发送信号。返回该进程组是否存在
+pub fn signal_group(pgid: usize, signal: Signal) -> bool {
+    let processes = processes_in_group(pgid);
+    for process in &processes {
+        process.receive_signal(KSigInfo::kernel(signal));
+    }
+    !processes.is_empty()
+}
+
+/// 根据 tid 查找线程
+pub fn find_thread(tid: usize) -> Option<Arc<Thread>> {
+    all_processes()
+        .into_iter()
+        .find_map(|process| process.lock_inner_with(|inner| inner.threads.get(&tid).cloned()))
+}
+
+/// 将已被回收的进程移出进程表
+pub fn remove_process(pid: usize) {
+    PROCESSES.lock().remove(&pid);
+}
+
 pub struct Process {
     pid: usize,
     /// 用于 `sys_wait4` 唤醒
@@ -65,109 +119,191 @@ impl Process {
             memory_space.load_elf_sections(&elf, &elf_data)?
         };
 
+        let mut stack_id_allocator = RecycleAllocator::new();
+        let stack_id = stack_id_allocator.alloc();
+        // 第一个线程，主线程，`stack_id` 为 0
+        assert_eq!(stack_id, 0);
+
         // 在用户栈上推入参数、环境变量、辅助向量等
         let argc = args.len();
-        let (user_sp, argv_base) = memory_space.init_stack(0, args, Vec::new(), auxv);
+        let (user_sp, argv_base) =
+            memory_space.init_stack(stack_id, USER_STACK_SIZE, args, Vec::new(), auxv);
 
         let brk = elf_end.vpn_ceil().page_start();
-        let mut tid_allocator = RecycleAllocator::new();
-        let tid = tid_allocator.alloc();
-        // 第一个线程，主线程，tid 为 0
-        assert_eq!(tid, 0);
         let mut trap_context = TrapContext::app_init_context(elf_entry, user_sp);
         *trap_context.a0_mut() = argc;
         *trap_context.a1_mut() = argv_base;
+        let pid = PID_ALLOCATOR.lock().alloc();
         let process = Arc::new(Process {
-            pid: PID_ALLOCATOR.lock().alloc(),
+            pid,
             wait4_event: Event::new(),
             status: Atomic::new(ProcessStatus::normal()),
             exit_signal: None,
             inner: SpinMutex::new(ProcessInner {
                 memory_space,
                 heap_range: brk..brk,
+                user_stack_size: USER_STACK_SIZE,
+                pgid: pid,
+                sid: pid,
+                cred: Credentials::ROOT,
+                rlimits: inner::default_rlimits(),
+                exited_times: CpuTimes::default(),
+                children_times: CpuTimes::default(),
+                timers: ProcessTimers::default(),
+                stopped: false,
+                job_event: None,
                 parent: None,
                 children: Vec::new(),
                 cwd: Arc::clone(VFS.root_dir()),
                 fd_table: FdTable::with_stdio(),
                 signal_handlers: SignalHandlers::new(),
-                tid_allocator,
+                stack_id_allocator,
                 threads: HashMap::new(),
             }),
         });
+        // 主线程的 tid 即为 pid
         process.lock_inner_with(|inner| {
             inner.threads.insert(
-                tid,
+                pid,
                 Arc::new(Thread::new(
                     Arc::clone(&process),
-                    tid,
+                    pid,
+                    stack_id,
                     trap_context,
                     KSignalSet::empty(),
+                    SchedEntity::default(),
                 )),
             );
         });
+        PROCESSES.lock().insert(pid, Arc::clone(&process));
 
         Ok(process)
     }
 
-    /// fork 一个新进程，目前仅支持只有一个主线程的进程。
+    /// fork 一个新进程。新进程中只有一个线程，即调用 fork 的线程 `thread` 的复制
     ///
     /// `stack` 若不为 0 则指定新进程的栈顶
     pub fn fork(
         self: &Arc<Self>,
+        thread: &Thread,
         stack: Option<NonZeroUsize>,
         exit_signal: Option<Signal>,
     ) -> Arc<Self> {
-        let child = self.lock_inner_with(|inner| {
-            assert_eq!(inner.threads.len(), 1);
-            let parent_main_thread = inner.main_thread();
-            let (mut trap_context, signal_mask) = parent_main_thread
-                .lock_inner_with(|inner| (inner.trap_context.clone(), inner.signal_mask));
+        let (mut trap_context, signal_mask, signal_stack) = thread.lock_inner_with(|inner| {
+            (
+                inner.trap_context.clone(),
+                inner.signal_mask,
+                inner.signal_stack,
+            )
+        });
+        let (child, child_thread) = self.lock_inner_with(|inner| {
             if let Some(stack) = stack {
                 *trap_context.sp_mut() = stack.get();
             }
             // 子进程 fork 后返回值为 0
             *trap_context.a0_mut() = 0;
+            let pid = PID_ALLOCATOR.lock().alloc();
             let child = Arc::new(Self {
-                pid: PID_ALLOCATOR.lock().alloc(),
+                pid,
                 wait4_event: Event::new(),
                 status: Atomic::new(self.status.load(Ordering::SeqCst)),
                 exit_signal,
                 inner: SpinMutex::new(ProcessInner {
                     memory_space: MemorySpace::from_other(&inner.memory_space),
                     heap_range: inner.heap_range.clone(),
+                    user_stack_size: inner.user_stack_size,
+                    pgid: inner.pgid,
+                    sid: inner.sid,
+                    cred: inner.cred.clone(),
+                    rlimits: inner.rlimits,
+                    exited_times: CpuTimes::default(),
+                    children_times: CpuTimes::default(),
+                    timers: ProcessTimers::default(),
+                    stopped: false,
+                    job_event: None,
                     parent: Some(Arc::clone(self)),
                     children: Vec::new(),
                     cwd: Arc::clone(&inner.cwd),
                     fd_table: inner.fd_table.clone(),
                     signal_handlers: inner.signal_handlers.clone(),
-                    tid_allocator: inner.tid_allocator.clone(),
+                    stack_id_allocator: inner.stack_id_allocator.clone(),
                     threads: HashMap::new(),
                 }),
             });
-            child.lock_inner_with(|inner| {
-                inner.threads.insert(
-                    parent_main_thread.tid(),
-                    Arc::new(Thread::new(
-                        Arc::clone(&child),
-                        parent_main_thread.tid(),
-                        trap_context,
-                        signal_mask,
-                    )),
-                )
-            });
+            // 子进程的线程成为其主线程，tid 即为 pid。
+            // 但它沿用原线程的 `stack_id`，这样它的用户栈位置也是一致的
+            let child_thread = Arc::new(Thread::new(
+                Arc::clone(&child),
+                pid,
+                thread.stack_id(),
+                trap_context,
+                signal_mask,
+                thread.sched.fork(),
+            ));
+            // 信号栈在子进程中依然有效
+            child_thread.lock_inner_with(|inner| inner.signal_stack = signal_stack);
+            child.lock_inner_with(|inner| inner.threads.insert(pid, Arc::clone(&child_thread)));
             // 新进程添入原进程的子进程表
             inner.children.push(Arc::clone(&child));
-            child
+            (child, child_thread)
         });
-        // 子进程的主线程可以加入调度队列中了
-        child.lock_inner_with(|inner| thread::spawn_user_thread(inner.main_thread()));
+        PROCESSES.lock().insert(child.pid, Arc::clone(&child));
+        // 子进程的线程可以加入调度队列中了
+        thread::spawn_user_thread(child_thread);
         child
     }
 
+    /// 在本进程中创建一个新线程，与 `thread` 共享地址空间、文件描述符表、信号处理函数等
+    ///
+    /// 新线程总会分配一个独立的用户栈。`stack` 若不为 0 则指定新线程的栈顶，否则使用新分配的用户栈。
+    /// `tls` 若不为 `None` 则设置为新线程的 `tp`
+    ///
+    /// 注意新线程不会被加入调度队列，调用者需要自行调用 [`thread::spawn_user_thread`]
+    pub fn clone_thread(
+        self: &Arc<Self>,
+        thread: &Thread,
+        stack: Option<NonZeroUsize>,
+        tls: Option<usize>,
+    ) -> Arc<Thread> {
+        let (mut trap_context, signal_mask) =
+            thread.lock_inner_with(|inner| (inner.trap_context.clone(), inner.signal_mask));
+        // 新线程 clone 后返回值为 0
+        *trap_context.a0_mut() = 0;
+        if let Some(tls) = tls {
+            *trap_context.tp_mut() = tls;
+        }
+        let tid = PID_ALLOCATOR.lock().alloc();
+        self.lock_inner_with(|inner| {
+            let stack_id = inner.stack_id_allocator.alloc();
+            let ustack_range =
+                Thread::alloc_user_stack(stack_id, inner.user_stack_size, &mut inner.memory_space);
+            *trap_context.sp_mut() =
+                stack.map_or(ustack_range.end.page_start().0, NonZeroUsize::get);
+            let new_thread = Arc::new(Thread::new(
+                Arc::clone(self),
+                tid,
+                stack_id,
+                trap_context,
+                signal_mask,
+                thread.sched.fork(),
+            ));
+            // 正在被 `execve` 终止的线程创建的线程也要一同终止
+            if thread.is_exiting() {
+                new_thread.kill();
+            }
+            inner.threads.insert(tid, Arc::clone(&new_thread));
+            new_thread
+        })
+    }
+
     /// 根据 `elf_data` 加载一个新的 ELF 文件并执行。
     ///
-    /// 目前要求原进程仅有一个线程并且没有子进程
-    pub fn exec(
+    /// 和 Linux 一样，会先终止其他线程，当前线程成为进程中唯一的线程。子进程则不受影响
+    ///
+    /// 错误：
+    /// - `ERESTARTSYS` 当前线程在等待其他线程退出时自身也被终止了
+    /// - `ENOEXEC` ELF 文件格式错误
+    pub async fn exec(
         &self,
         elf_data: &[u8],
         args: Vec<CompactString>,
@@ -237,11 +373,14 @@ impl Process {
             warn!("parse elf error {e}");
             errno::ENOEXEC
         })?;
+        let thread = Arc::clone(&local_hart().curr_thread_arc());
+        self.kill_other_threads(&thread).await?;
         let ret = self.lock_inner_with(|inner| {
-            // TODO: 如果是多线程情况下，应该需要先终结其它线程？有子进程可能也类似？
-            assert_eq!(inner.threads.len(), 1);
-            assert_eq!(inner.children.len(), 0);
+            // 唯一的线程未必是主线程，比如由某个非主线程 fork 或者 exec 而来的进程
+            debug_assert_eq!(inner.threads.len(), 1);
             inner.memory_space.recycle_user_pages();
+            inner.memory_space.map_signal_trampoline();
+            inner.memory_space.map_vdso();
             // TODO: 执行新进程过程中发生错误，该退出还是恢复？
             let (elf_end, auxv, elf_entry) =
                 inner.memory_space.load_elf_sections(&elf, elf_data)?;
@@ -251,15 +390,26 @@ impl Process {
             };
             inner.fd_table.close_on_exec();
             inner.signal_handlers = SignalHandlers::new();
+            inner.timers.posix.clear();
+
+            inner.user_stack_size = inner.stack_size_from_rlimit();
 
             let argc = args.len();
-            let (user_sp, argv_base) = inner.memory_space.init_stack(0, args, envs, auxv);
+            let (user_sp, argv_base) = inner.memory_space.init_stack(
+                thread.stack_id(),
+                inner.user_stack_size,
+                args,
+                envs,
+                auxv,
+            );
             memory::flush_tlb(None);
 
-            inner.main_thread().lock_inner_with(|inner| {
+            thread.lock_inner_with(|inner| {
                 inner.trap_context = TrapContext::app_init_context(elf_entry, user_sp);
                 *inner.trap_context.a0_mut() = argc;
                 *inner.trap_context.a1_mut() = argv_base;
+                // 原来的信号栈已经不存在了
+                inner.signal_stack = SignalStack::default();
             });
             Ok(())
         });
@@ -269,6 +419,39 @@ impl Process {
         ret
     }
 
+    /// 终止本进程中除 `curr` 以外的所有线程，并等待它们退出，类似于 Linux 的 `de_thread()`
+    ///
+    /// 错误：
+    /// - `ERESTARTSYS` `curr` 自身也需要退出，比如其他线程先一步调用了 `execve`
+    async fn kill_other_threads(&self, curr: &Thread) -> KResult<()> {
+        // 在锁内检查并终止，这样两个线程同时 `execve` 时只有一个能成功
+        let killed = self.lock_inner_with(|inner| {
+            if curr.is_exiting() {
+                return false;
+            }
+            for thread in inner.threads.values() {
+                if thread.tid() != curr.tid() {
+                    thread.kill();
+                }
+            }
+            true
+        });
+        if !killed {
+            return Err(errno::ERESTARTSYS);
+        }
+        // 最后一个其他线程退出时会通知 `curr`
+        loop {
+            listener!(curr.signal_event => listener);
+            if curr.is_exiting() {
+                return Err(errno::ERESTARTSYS);
+            }
+            if self.lock_inner_with(|inner| inner.threads.len() == 1) {
+                return Ok(());
+            }
+            listener.await;
+        }
+    }
+
     pub fn lock_inner(&self) -> SpinMutexGuard<'_, ProcessInner> {
         self.inner.lock()
     }
@@ -278,10 +461,158 @@ impl Process {
         f(&mut self.inner.lock())
     }
 
+    /// 获取进程用户凭证的副本
+    pub fn cred(&self) -> Credentials {
+        self.lock_inner_with(|inner| inner.cred.clone())
+    }
+
     pub fn pid(&self) -> usize {
         self.pid
     }
 
+    /// 检查进程的 CPU 时间是否超过了 `RLIMIT_CPU`。
+    ///
+    /// 达到硬上限时发送 `SIGKILL`。达到软上限时发送 `SIGXCPU`，并且和 Linux 一样将软上限提高一秒，
+    /// 这样之后每多用一秒都会再收到一次 `SIGXCPU`
+    pub fn check_cpu_limit(&self) {
+        let signal = self.lock_inner_with(|inner| {
+            let secs = inner.cpu_times().total().as_secs() as usize;
+            let rlimit = &mut inner.rlimits[RLIMIT_CPU as usize];
+            if secs >= rlimit.rlim_max {
+                Some(Signal::SIGKILL)
+            } else if secs >= rlimit.rlim_curr {
+                rlimit.rlim_curr += 1;
+                Some(Signal::SIGXCPU)
+            } else {
+                None
+            }
+        });
+        if let Some(signal) = signal {
+            self.receive_signal(KSigInfo::kernel(signal));
+        }
+    }
+
+    /// 检查 `ITIMER_VIRTUAL` 和 `ITIMER_PROF` 是否到期，到期则分别发送 `SIGVTALRM` 和 `SIGPROF`
+    pub fn check_cpu_timers(&self) {
+        let (virt, prof) = self.lock_inner_with(|inner| {
+            let times = inner.cpu_times();
+            (
+                inner.timers.virt.check(times.user),
+                inner.timers.prof.check(times.total()),
+            )
+        });
+        if virt {
+            self.receive_signal(KSigInfo::kernel(Signal::SIGVTALRM));
+        }
+        if prof {
+            self.receive_signal(KSigInfo::kernel(Signal::SIGPROF));
+        }
+    }
+
+    /// 向本进程发送信号，由进程挑选合适的线程处理
+    pub fn receive_signal(&self, info: KSigInfo) {
+        self.prepare_signal(info.signal);
+        self.lock_inner_with(|inner| inner.receive_signal(info));
+    }
+
+    /// 向本进程中的线程 `thread` 发送信号
+    pub fn receive_thread_signal(&self, thread: &Thread, info: KSigInfo) {
+        self.prepare_signal(info.signal);
+        self.lock_inner_with(|inner| inner.receive_thread_signal(thread, info));
+    }
+
+    /// 处理信号产生时就要处理的部分，即作业控制相关的部分。
+    ///
+    /// 停止信号会丢弃待处理的 `SIGCONT`；而 `SIGCONT` 会丢弃待处理的停止信号，并立刻恢复进程，
+    /// 无论 `SIGCONT` 是否被屏蔽或者忽略
+    fn prepare_signal(&self, signal: Signal) {
+        let stop_signals =
+            KSignalSet::SIGSTOP | KSignalSet::SIGTSTP | KSignalSet::SIGTTIN | KSignalSet::SIGTTOU;
+        if matches!(DefaultHandler::new(signal), DefaultHandler::Stop) {
+            self.lock_inner_with(|inner| {
+                for thread in inner.threads.values() {
+                    thread
+                        .lock_inner_with(|inner| inner.pending_signal.remove(KSignalSet::SIGCONT));
+                }
+            });
+        } else if signal == Signal::SIGCONT {
+            let continued = self.lock_inner_with(|inner| {
+                for thread in inner.threads.values() {
+                    thread.lock_inner_with(|inner| inner.pending_signal.remove(stop_signals));
+                }
+                if !inner.stopped {
+                    return false;
+                }
+                inner.stopped = false;
+                inner.job_event = Some(JobEvent::Continued);
+                // 唤醒停止中的线程
+                for thread in inner.threads.values() {
+                    thread.signal_event.notify(usize::MAX);
+                }
+                true
+            });
+            if continued {
+                info!("process continued");
+                self.notify_parent_job_event(JobEvent::Continued);
+            }
+        }
+    }
+
+    /// 因信号 `signal` 停止本进程。各线程会在返回用户态之前停下，直到收到 `SIGCONT`
+    pub fn stop(&self, signal: Signal) {
+        let stopped = self.lock_inner_with(|inner| {
+            if inner.stopped {
+                return false;
+            }
+            inner.stopped = true;
+            inner.job_event = Some(JobEvent::Stopped(signal));
+            true
+        });
+        if stopped {
+            info!("process stopped by {signal:?}");
+            self.notify_parent_job_event(JobEvent::Stopped(signal));
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.lock_inner_with(|inner| inner.stopped)
+    }
+
+    /// 通知父进程，本进程被停止或恢复了。除非父进程设置了 `SA_NOCLDSTOP`，否则还会向其发送 `SIGCHLD`
+    fn notify_parent_job_event(&self, event: JobEvent) {
+        let (parent, uid) = self.lock_inner_with(|inner| (inner.parent.clone(), inner.cred.uid));
+        let Some(parent) = parent else {
+            return;
+        };
+        parent.wait4_event.notify(usize::MAX);
+        let no_cld_stop = parent.lock_inner_with(|inner| {
+            inner
+                .signal_handlers
+                .action(Signal::SIGCHLD)
+                .flags
+                .contains(SignalActionFlags::SA_NOCLDSTOP)
+        });
+        if !no_cld_stop {
+            let (code, signal) = match event {
+                JobEvent::Stopped(signal) => (CLD_STOPPED, signal),
+                JobEvent::Continued => (CLD_CONTINUED, Signal::SIGCONT),
+            };
+            let status = i32::from(signal.to_user());
+            parent.receive_signal(KSigInfo::child(
+                Signal::SIGCHLD,
+                code,
+                self.pid,
+                uid,
+                status,
+            ));
+        }
+    }
+
+    /// 获取主线程。主线程已经退出的话则返回 `None`
+    pub fn main_thread(&self) -> Option<Arc<Thread>> {
+        self.lock_inner_with(|inner| inner.threads.get(&self.pid).cloned())
+    }
+
     // pub fn is_normal(&self) -> bool {
     //     self.status.load(Ordering::SeqCst).0 & (0b1111_1111 << 8) == (0 << 8)
     // }
@@ -309,18 +640,50 @@ impl Drop for Process {
     }
 }
 
+/// pid 与 tid 共用这一个分配器，也就是共用同一个命名空间
 static PID_ALLOCATOR: SpinMutex<RecycleAllocator> = SpinMutex::new(RecycleAllocator::begin_with(1));
 
+/// 回收非主线程的 tid。主线程的 tid 即为 pid，随进程一起回收
+pub fn dealloc_tid(tid: usize) {
+    PID_ALLOCATOR.lock().dealloc(tid);
+}
+
 /// 退出进程，终止其所有线程。
 ///
 /// 但注意，其他线程此时可能正在运行，因此终止不是立刻发生的，仅仅只是标记该进程为退出，而不回收资源
 ///
 /// 其他线程在进入内核时会检查对应的进程是否已标记为退出从而决定是否退出
+///
+/// 多个线程可能同时要求退出进程，此时以第一个为准
 pub fn exit_process(process: &Process, exit_code: i8) {
-    info!("Process exits with code {exit_code}");
     let new_status = ProcessStatus::exited(exit_code);
-    let old_status = process.status.swap(new_status, Ordering::SeqCst);
-    assert_eq!(old_status, ProcessStatus::normal());
+    if process
+        .status
+        .compare_exchange(
+            ProcessStatus::normal(),
+            new_status,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .is_ok()
+    {
+        info!("Process exits with code {exit_code}");
+        // 唤醒正在等待的线程，让它们尽快退出
+        process.lock_inner_with(|inner| {
+            for thread in inner.threads.values() {
+                thread.signal_event.notify(usize::MAX);
+            }
+        });
+    }
+}
+
+/// 进程的作业控制状态变化，父进程可以通过 `sys_wait4` 获知
+#[derive(Clone, Copy, Debug)]
+pub enum JobEvent {
+    /// 被信号停止
+    Stopped(Signal),
+    /// 被 `SIGCONT` 恢复
+    Continued,
 }
 
 /// 标记一个进程的状态，其中低 8 位记录 exit code
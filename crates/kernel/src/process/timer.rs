@@ -0,0 +1,294 @@
+//! 进程的定时器，包括间隔定时器（`setitimer`）和 POSIX 定时器（`timer_create`）
+
+use alloc::collections::BTreeMap;
+use core::{fmt, mem, time::Duration};
+
+use klocks::SpinMutex;
+use triomphe::Arc;
+
+use super::Process;
+use crate::{
+    executor::{self, KernelTask},
+    signal::{KSigInfo, Signal},
+    time,
+};
+
+/// 进程的所有定时器。fork 出的子进程不继承定时器，exec 时会删除 POSIX 定时器
+#[derive(Default)]
+pub struct ProcessTimers {
+    /// `ITIMER_REAL`，到期时发送 `SIGALRM`
+    pub real: RealTimer,
+    /// `ITIMER_VIRTUAL`，按用户态 CPU 时间计时，到期时发送 `SIGVTALRM`
+    pub virt: CpuTimer,
+    /// `ITIMER_PROF`，按 CPU 时间计时，到期时发送 `SIGPROF`
+    pub prof: CpuTimer,
+    /// POSIX 定时器，以定时器 id 为键
+    pub posix: BTreeMap<usize, PosixTimer>,
+}
+
+impl ProcessTimers {
+    /// 找到最小的未使用的 POSIX 定时器 id
+    pub fn alloc_posix_id(&self) -> usize {
+        (0..)
+            .zip(self.posix.keys())
+            .find(|(id, used)| id != *used)
+            .map_or(self.posix.len(), |(id, _)| id)
+    }
+}
+
+/// 按单调时钟到期的定时器，由一个内核任务驱动，到期时调用回调函数
+pub struct RealTimer {
+    state: Arc<SpinMutex<RealTimerState>>,
+    /// 驱动定时器的内核任务，drop 即取消
+    task: Option<KernelTask<()>>,
+}
+
+#[derive(Default)]
+struct RealTimerState {
+    /// 下一次到期的时刻（单调时钟），为 `None` 表示定时器已停止
+    deadline: Option<Duration>,
+    /// 到期后重新计时的间隔，为 0 表示只触发一次
+    interval: Duration,
+    /// 上一次到期时，因为内核任务被延迟调度而错过的到期次数
+    overrun: u32,
+    /// 每次设置定时器都会递增，旧的内核任务发现不一致时就会退出
+    generation: u64,
+}
+
+impl Default for RealTimer {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(SpinMutex::new(RealTimerState::default())),
+            task: None,
+        }
+    }
+}
+
+impl RealTimer {
+    /// 返回距离下一次到期的时间和间隔。定时器已停止时剩余时间为 0
+    pub fn get(&self) -> (Duration, Duration) {
+        let state = self.state.lock();
+        let remaining = state.deadline.map_or(Duration::ZERO, |deadline| {
+            deadline.saturating_sub(time::curr_time())
+        });
+        (remaining, state.interval)
+    }
+
+    /// 设置定时器在 `deadline`（单调时钟）到期，之后每隔 `interval` 到期一次。`deadline` 为 `None` 则停止定时器。
+    ///
+    /// 每次到期时以错过的到期次数为参数调用 `on_expire`。注意它会在内核任务中调用
+    pub fn set(
+        &mut self,
+        deadline: Option<Duration>,
+        interval: Duration,
+        on_expire: impl Fn(u32) + Send + 'static,
+    ) {
+        let generation = {
+            let mut state = self.state.lock();
+            state.generation += 1;
+            state.deadline = deadline;
+            state.interval = interval;
+            state.overrun = 0;
+            state.generation
+        };
+        // 替换掉旧的任务即将其取消
+        self.task = deadline.map(|_| {
+            executor::spawn_kernel_task(run_real_timer(
+                Arc::clone(&self.state),
+                generation,
+                on_expire,
+            ))
+        });
+    }
+}
+
+async fn run_real_timer(
+    state: Arc<SpinMutex<RealTimerState>>,
+    generation: u64,
+    on_expire: impl Fn(u32) + Send + 'static,
+) {
+    loop {
+        let deadline = {
+            let state = state.lock();
+            if state.generation != generation {
+                return;
+            }
+            state.deadline
+        };
+        let Some(deadline) = deadline else {
+            return;
+        };
+        time::sleep_until(deadline).await;
+        let overrun = {
+            let mut state = state.lock();
+            if state.generation != generation {
+                return;
+            }
+            if state.interval.is_zero() {
+                state.deadline = None;
+                state.overrun = 0;
+            } else {
+                let late = time::curr_time().saturating_sub(deadline);
+                let missed = late.as_nanos() / state.interval.as_nanos();
+                state.overrun = u32::try_from(missed).unwrap_or(u32::MAX);
+                state.deadline = Some(
+                    deadline.saturating_add(
+                        state
+                            .interval
+                            .saturating_mul(state.overrun.saturating_add(1)),
+                    ),
+                );
+            }
+            state.overrun
+        };
+        on_expire(overrun);
+    }
+}
+
+/// 按进程的 CPU 时间到期的间隔定时器，在时钟中断时检查
+#[derive(Clone, Copy, Default)]
+pub struct CpuTimer {
+    /// 到期时进程的 CPU 时间，为 `None` 表示定时器已停止
+    expire: Option<Duration>,
+    /// 到期后重新计时的间隔，为 0 表示只触发一次
+    interval: Duration,
+}
+
+impl CpuTimer {
+    /// 返回距离下一次到期的时间和间隔，`now` 为进程当前的 CPU 时间
+    pub fn get(&self, now: Duration) -> (Duration, Duration) {
+        let remaining = self
+            .expire
+            .map_or(Duration::ZERO, |expire| expire.saturating_sub(now));
+        (remaining, self.interval)
+    }
+
+    /// 设置定时器在 `value` 后到期，`value` 为 0 则停止定时器
+    pub fn set(&mut self, now: Duration, value: Duration, interval: Duration) {
+        self.expire = (!value.is_zero()).then(|| now.saturating_add(value));
+        self.interval = interval;
+    }
+
+    /// 检查定时器是否到期，到期则按间隔重新计时
+    pub fn check(&mut self, now: Duration) -> bool {
+        match self.expire {
+            Some(expire) if now >= expire => {
+                self.expire =
+                    (!self.interval.is_zero()).then(|| expire.saturating_add(self.interval));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// POSIX 定时器
+pub struct PosixTimer {
+    /// 是否按挂钟计时。设置绝对时刻时需要转换为单调时钟
+    pub realtime: bool,
+    pub notify: TimerNotify,
+    pub timer: RealTimer,
+    pub signal: Arc<TimerSignal>,
+}
+
+/// POSIX 定时器所发送信号的状态，由定时器和排队中的 [`KSigInfo`] 共享。
+///
+/// 和 Linux 一样，每个定时器至多有一个排队中的信号。信号被取出之前定时器再次到期的话，
+/// 只会累加到该信号的 overrun 中，否则信号被屏蔽时，周期定时器会使信号队列无限增长
+#[derive(Default)]
+pub struct TimerSignal {
+    state: SpinMutex<TimerSignalState>,
+}
+
+#[derive(Debug, Default)]
+struct TimerSignalState {
+    /// 是否有信号正在排队
+    queued: bool,
+    /// 排队中的信号累计的 overrun
+    pending_overrun: u32,
+    /// 上一个被取出的信号的 overrun，即 `timer_getoverrun` 的返回值
+    overrun: u32,
+}
+
+impl TimerSignal {
+    /// 上一个被取出的信号的 overrun
+    pub fn overrun(&self) -> u32 {
+        self.state.lock().overrun
+    }
+
+    /// 定时器到期，此前错过了 `missed` 次。返回是否需要发送新的信号
+    fn expire(&self, missed: u32) -> bool {
+        let mut state = self.state.lock();
+        if state.queued {
+            state.pending_overrun = state
+                .pending_overrun
+                .saturating_add(missed)
+                .saturating_add(1);
+            return false;
+        }
+        state.queued = true;
+        state.pending_overrun = missed;
+        true
+    }
+
+    /// 排队中的信号被取出，返回它最终的 overrun
+    pub fn deliver(&self) -> u32 {
+        let mut state = self.state.lock();
+        state.queued = false;
+        state.overrun = mem::take(&mut state.pending_overrun);
+        state.overrun
+    }
+
+    /// 排队中的信号未被取出就被丢弃了
+    pub fn discard(&self) {
+        let mut state = self.state.lock();
+        state.queued = false;
+        state.pending_overrun = 0;
+    }
+}
+
+impl fmt::Debug for TimerSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.state.lock().fmt(f)
+    }
+}
+
+/// POSIX 定时器到期时的通知方式
+#[derive(Clone, Copy, Debug)]
+pub enum TimerNotify {
+    /// 不通知
+    None,
+    /// 发送信号。指定了 `tid` 时发送给该线程，否则发送给进程
+    Signal {
+        signal: Signal,
+        value: usize,
+        tid: Option<usize>,
+    },
+}
+
+impl TimerNotify {
+    /// 生成定时器 `timer_id` 到期时的回调函数。`timer_signal` 记录了该定时器是否已有信号在排队
+    pub fn on_expire(
+        self,
+        process: Arc<Process>,
+        timer_id: usize,
+        timer_signal: Arc<TimerSignal>,
+    ) -> impl Fn(u32) + Send + 'static {
+        move |overrun| {
+            let Self::Signal { signal, value, tid } = self else {
+                return;
+            };
+            if !timer_signal.expire(overrun) {
+                return;
+            }
+            let info = KSigInfo::timer(signal, timer_id, Arc::clone(&timer_signal), value);
+            // 目标线程已经退出的话就发送给进程
+            let thread = tid
+                .and_then(|tid| process.lock_inner_with(|inner| inner.threads.get(&tid).cloned()));
+            match thread {
+                Some(thread) => process.receive_thread_signal(&thread, info),
+                None => process.receive_signal(info),
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use defines::signal::{KSignalAction, SIGSET_SIZE};
 
-use super::Signal;
+use super::{Signal, SIG_DFL, SIG_IGN};
 
 pub enum DefaultHandler {
     Terminate,
@@ -46,4 +46,13 @@ impl SignalHandlers {
     pub fn action_mut(&mut self, signal: Signal) -> &mut KSignalAction {
         &mut self.actions[signal as usize]
     }
+
+    /// 信号是否会被忽略，包括显式设置为 `SIG_IGN` 以及默认行为是忽略的情况
+    pub fn is_ignored(&self, signal: Signal) -> bool {
+        match self.action(signal).handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(DefaultHandler::new(signal), DefaultHandler::Ignore),
+            _ => false,
+        }
+    }
 }
@@ -5,23 +5,39 @@
 //! `fork` 会继承父进程的 signal action 和线程的掩码，但是线程的待处理信号会置空。
 //!
 //! 而 `execve` 会将 signal action 置为默认值（可能与 linux 不同），但是线程掩码和待处理信号保留
+//!
+//! 标准信号在待处理时不会重复接收，而实时信号会排队，每一个都携带各自的 [`KSigInfo`]
 
 mod handlers;
 
+use alloc::collections::VecDeque;
+use core::{future::Future, pin::pin};
+
 use bitflags::bitflags;
-use defines::signal::KSignalAction;
+use defines::{
+    error::{errno, KResult},
+    signal::{
+        KSignalAction, SigInfo, SignalFdSigInfo, SignalStack, UContext, SI_KERNEL, SI_TIMER,
+        SS_DISABLE, SS_ONSTACK,
+    },
+};
+use event_listener::listener;
 use extend::ext;
+use futures::future::{self, Either};
 pub use handlers::{DefaultHandler, SignalHandlers};
+use triomphe::Arc;
 
-use crate::trap::TrapContext;
+use crate::{hart::local_hart, process::TimerSignal};
 
 pub const SIG_ERR: usize = usize::MAX;
 pub const SIG_DFL: usize = 0;
 pub const SIG_IGN: usize = 1;
 
-pub struct SignalContext {
-    pub old_mask: KSignalSet,
-    pub old_trap_context: TrapContext,
+/// 进入 signal handler 时压入用户栈的栈帧，`sys_rt_sigreturn` 时从中恢复上下文
+#[repr(C)]
+pub struct SignalFrame {
+    pub info: SigInfo,
+    pub ucontext: UContext,
 }
 
 #[derive(Debug)]
@@ -45,15 +61,13 @@ impl SigProcMaskHow {
     }
 }
 
-// TODO: 出于简单性，暂时只考虑标准信号，后续有需要实时信号再添加
-
 bitflags! {
     /// 其实 posix 规定 64 位平台上应该有 1024bits。[Why is sigset_t in glibc/musl 128 bytes large on 64-bit Linux?](https://unix.stackexchange.com/questions/399342/why-is-sigset-t-in-glibc-musl-128-bytes-large-on-64-bit-linux)
     ///
     /// 然而实践中比较混乱。比如理论应该区分 sigset_t(1024bits) 和 kernel_sigset_t(64bits?)，但 linux 内核中后者的名字是前者。
     ///
     /// 而在 syscall 边界上，linux 也是直接使用的 64bits 的
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, Default)]
     pub struct KSignalSet: u64 {
         const SIGHUP    = 1 << (Signal::SIGHUP as u8);
         const SIGINT    = 1 << (Signal::SIGINT as u8);
@@ -86,17 +100,19 @@ bitflags! {
         const SIGIO     = 1 << (Signal::SIGIO as u8);
         const SIGPWR    = 1 << (Signal::SIGPWR as u8);
         const SIGSYS    = 1 << (Signal::SIGSYS as u8);
+        /// 所有实时信号
+        const SIGRT     = u64::MAX << (Signal::SIGRTMIN as u8);
     }
 }
 
 impl KSignalSet {
+    /// 用户的信号集中，信号 `signum` 对应第 `signum - 1` 位，恰好与内核一致
     pub const fn from_user(bits: u64) -> Self {
-        // TODO: [low] 如果用户传入的信号集是非法的，该截断还是返回错误？
-        KSignalSet::from_bits_truncate(bits >> 1)
+        KSignalSet::from_bits_retain(bits)
     }
 
     pub const fn to_user(self) -> u64 {
-        self.bits() << 1
+        self.bits()
     }
 
     pub const fn first_pending(self) -> Option<Signal> {
@@ -106,12 +122,14 @@ impl KSignalSet {
 
 impl From<Signal> for KSignalSet {
     fn from(value: Signal) -> Self {
-        Self::from_bits_truncate(1 << (value as u8))
+        Self::from_bits_retain(1 << (value as u8))
     }
 }
 
 /// 注意，和 linux 不同，信号的编号从 0 开始而非从 1
 /// 开始。因此在一些系统调用上应当将传入的值减 1，传出的值加 1
+///
+/// `SIGRTn` 即 `SIGRTMIN + n`
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u8)]
 #[allow(clippy::upper_case_acronyms)]
@@ -147,6 +165,39 @@ pub enum Signal {
     SIGIO = defines::signal::SIGIO - 1,
     SIGPWR = defines::signal::SIGPWR - 1,
     SIGSYS = defines::signal::SIGSYS - 1,
+    SIGRTMIN = defines::signal::SIGRTMIN - 1,
+    SIGRT1 = defines::signal::SIGRTMIN + 1 - 1,
+    SIGRT2 = defines::signal::SIGRTMIN + 2 - 1,
+    SIGRT3 = defines::signal::SIGRTMIN + 3 - 1,
+    SIGRT4 = defines::signal::SIGRTMIN + 4 - 1,
+    SIGRT5 = defines::signal::SIGRTMIN + 5 - 1,
+    SIGRT6 = defines::signal::SIGRTMIN + 6 - 1,
+    SIGRT7 = defines::signal::SIGRTMIN + 7 - 1,
+    SIGRT8 = defines::signal::SIGRTMIN + 8 - 1,
+    SIGRT9 = defines::signal::SIGRTMIN + 9 - 1,
+    SIGRT10 = defines::signal::SIGRTMIN + 10 - 1,
+    SIGRT11 = defines::signal::SIGRTMIN + 11 - 1,
+    SIGRT12 = defines::signal::SIGRTMIN + 12 - 1,
+    SIGRT13 = defines::signal::SIGRTMIN + 13 - 1,
+    SIGRT14 = defines::signal::SIGRTMIN + 14 - 1,
+    SIGRT15 = defines::signal::SIGRTMIN + 15 - 1,
+    SIGRT16 = defines::signal::SIGRTMIN + 16 - 1,
+    SIGRT17 = defines::signal::SIGRTMIN + 17 - 1,
+    SIGRT18 = defines::signal::SIGRTMIN + 18 - 1,
+    SIGRT19 = defines::signal::SIGRTMIN + 19 - 1,
+    SIGRT20 = defines::signal::SIGRTMIN + 20 - 1,
+    SIGRT21 = defines::signal::SIGRTMIN + 21 - 1,
+    SIGRT22 = defines::signal::SIGRTMIN + 22 - 1,
+    SIGRT23 = defines::signal::SIGRTMIN + 23 - 1,
+    SIGRT24 = defines::signal::SIGRTMIN + 24 - 1,
+    SIGRT25 = defines::signal::SIGRTMIN + 25 - 1,
+    SIGRT26 = defines::signal::SIGRTMIN + 26 - 1,
+    SIGRT27 = defines::signal::SIGRTMIN + 27 - 1,
+    SIGRT28 = defines::signal::SIGRTMIN + 28 - 1,
+    SIGRT29 = defines::signal::SIGRTMIN + 29 - 1,
+    SIGRT30 = defines::signal::SIGRTMIN + 30 - 1,
+    SIGRT31 = defines::signal::SIGRTMIN + 31 - 1,
+    SIGRTMAX = defines::signal::SIGRTMAX - 1,
 }
 
 impl Signal {
@@ -183,6 +234,10 @@ impl Signal {
             defines::signal::SIGIO => Some(Signal::SIGIO),
             defines::signal::SIGPWR => Some(Signal::SIGPWR),
             defines::signal::SIGSYS => Some(Signal::SIGSYS),
+            defines::signal::SIGRTMIN..=defines::signal::SIGRTMAX => {
+                // SAFETY: `Signal` 是 `repr(u8)` 的，且实时信号的编号是连续的
+                Some(unsafe { core::mem::transmute::<u8, Signal>(signum - 1) })
+            }
             _ => None,
         }
     }
@@ -190,6 +245,194 @@ impl Signal {
     pub const fn to_user(self) -> u8 {
         self as u8 + 1
     }
+
+    pub const fn is_realtime(self) -> bool {
+        self as u8 >= Signal::SIGRTMIN as u8
+    }
+}
+
+/// 随信号一同排队的信息，处理信号时会转换为用户的 `siginfo_t`
+#[derive(Debug)]
+pub struct KSigInfo {
+    pub signal: Signal,
+    /// 见 `SI_USER` 等
+    pub code: i32,
+    /// 发送者的 pid，对于 POSIX 定时器则为定时器 id
+    pub pid: usize,
+    /// 发送者的 uid，对于 POSIX 定时器则为 overrun 计数
+    pub uid: u32,
+    /// `sigqueue` 等传入的值，对于 `SIGCHLD` 则为子进程状态
+    pub value: usize,
+    /// 发送该信号的 POSIX 定时器。信号被取出时从中得到最终的 overrun，被丢弃时也需要通知它
+    timer: Option<Arc<TimerSignal>>,
+}
+
+impl KSigInfo {
+    /// 由内核产生的信号
+    pub fn kernel(signal: Signal) -> Self {
+        Self {
+            signal,
+            code: SI_KERNEL,
+            pid: 0,
+            uid: 0,
+            value: 0,
+            timer: None,
+        }
+    }
+
+    /// 由当前进程通过 `kill` 等系统调用发送的信号
+    pub fn user(signal: Signal, code: i32) -> Self {
+        let process = local_hart().curr_process();
+        Self {
+            signal,
+            code,
+            pid: process.pid(),
+            uid: process.lock_inner_with(|inner| inner.cred.uid),
+            value: 0,
+            timer: None,
+        }
+    }
+
+    /// 子进程状态改变时发送给父进程的信号，`uid` 为子进程的真实用户 ID，`status` 为退出码或者导致状态改变的信号
+    pub fn child(signal: Signal, code: i32, pid: usize, uid: u32, status: i32) -> Self {
+        Self {
+            signal,
+            code,
+            pid,
+            uid,
+            value: status as usize,
+            timer: None,
+        }
+    }
+
+    /// POSIX 定时器 `timer_id` 到期时发送的信号。overrun 在信号被取出时才确定，见 [`TimerSignal`]
+    pub fn timer(signal: Signal, timer_id: usize, timer: Arc<TimerSignal>, value: usize) -> Self {
+        Self {
+            signal,
+            code: SI_TIMER,
+            pid: timer_id,
+            uid: 0,
+            value,
+            timer: Some(timer),
+        }
+    }
+
+    pub fn to_user(&self) -> SigInfo {
+        let mut info = SigInfo::new(i32::from(self.signal.to_user()), self.code);
+        info.si_pid = self.pid as i32;
+        info.si_uid = self.uid;
+        info.si_value = self.value;
+        info
+    }
+
+    pub fn to_signalfd(&self) -> SignalFdSigInfo {
+        let mut info = SignalFdSigInfo::new(u32::from(self.signal.to_user()), self.code);
+        info.ssi_pid = self.pid as u32;
+        info.ssi_uid = self.uid;
+        if self.signal == Signal::SIGCHLD {
+            info.ssi_status = self.value as i32;
+        } else {
+            info.ssi_int = self.value as i32;
+            info.ssi_ptr = self.value as u64;
+        }
+        info
+    }
+}
+
+impl Drop for KSigInfo {
+    fn drop(&mut self) {
+        // 未被取出就丢弃了，定时器之后到期时需要重新发送信号
+        if let Some(timer) = self.timer.take() {
+            timer.discard();
+        }
+    }
+}
+
+/// 线程的待处理信号
+#[derive(Default)]
+pub struct PendingSignals {
+    set: KSignalSet,
+    /// 与 `set` 中的信号一一对应，其中实时信号可能有多个
+    queue: VecDeque<KSigInfo>,
+}
+
+impl PendingSignals {
+    /// 所有待处理信号的集合
+    pub fn set(&self) -> KSignalSet {
+        self.set
+    }
+
+    pub fn contains(&self, signal: Signal) -> bool {
+        self.set.contains(KSignalSet::from(signal))
+    }
+
+    /// 加入一个待处理信号。若是已经在待处理的标准信号，则丢弃并返回 false
+    pub fn insert(&mut self, info: KSigInfo) -> bool {
+        let set = KSignalSet::from(info.signal);
+        if !info.signal.is_realtime() && self.set.contains(set) {
+            return false;
+        }
+        self.set.insert(set);
+        self.queue.push_back(info);
+        true
+    }
+
+    /// 丢弃 `set` 中的所有待处理信号
+    pub fn remove(&mut self, set: KSignalSet) {
+        if !self.set.intersects(set) {
+            return;
+        }
+        self.set.remove(set);
+        self.queue
+            .retain(|info| !set.contains(KSignalSet::from(info.signal)));
+    }
+
+    /// 取出一个不在 `mask` 中的待处理信号。编号小的信号优先，同一实时信号则按照发送顺序
+    pub fn pop(&mut self, mask: KSignalSet) -> Option<KSigInfo> {
+        let signal = self.set.difference(mask).first_pending()?;
+        let index = self
+            .queue
+            .iter()
+            .position(|info| info.signal == signal)
+            .expect("pending signal should be in the queue");
+        let mut info = self.queue.remove(index)?;
+        if !self.queue.iter().any(|info| info.signal == signal) {
+            self.set.remove(KSignalSet::from(signal));
+        }
+        if let Some(timer) = info.timer.take() {
+            info.uid = timer.deliver();
+        }
+        Some(info)
+    }
+}
+
+/// 等待 `fut` 完成。若期间当前线程有未被屏蔽的待处理信号，或者线程需要退出，则放弃等待并返回
+/// `ERESTARTSYS`。后者见 [`Thread::is_exiting()`](crate::thread::Thread::is_exiting)
+///
+/// 阻塞的系统调用应当用它包裹等待的部分，这样才能及时响应信号。`ERESTARTSYS` 不会返回给用户，
+/// 而是在处理信号时视 `SA_RESTART` 决定重启系统调用还是返回 `EINTR`，见 [`crate::trap::SyscallRestart`]
+pub async fn interruptible<F: Future>(fut: F) -> KResult<F::Output> {
+    let thread = Arc::clone(&local_hart().curr_thread_arc());
+    let mut fut = pin!(fut);
+    loop {
+        // 先注册监听再检查，以免错过通知
+        listener!(thread.signal_event => listener);
+        let interrupted = thread.lock_inner_with(|inner| {
+            !inner
+                .pending_signal
+                .set()
+                .difference(inner.signal_mask)
+                .is_empty()
+        });
+        if interrupted || thread.is_exiting() {
+            return Err(errno::ERESTARTSYS);
+        }
+        match future::select(fut.as_mut(), listener).await {
+            Either::Left((output, _)) => return Ok(output),
+            // 收到了通知，但信号可能是被屏蔽的，需要再检查一遍
+            Either::Right(((), _)) => {}
+        }
+    }
 }
 
 #[ext]
@@ -198,3 +441,31 @@ pub impl KSignalAction {
         KSignalSet::from_user(self.mask)
     }
 }
+
+/// 线程中保存的信号栈，`ss_size` 为 0 表示禁用，`ss_flags` 中只保存 `SS_AUTODISARM`
+#[ext]
+pub impl SignalStack {
+    fn is_enabled(&self) -> bool {
+        self.ss_size != 0
+    }
+
+    /// 用户栈指针 `sp` 是否位于信号栈上
+    fn contains(&self, sp: usize) -> bool {
+        self.is_enabled() && sp > self.ss_sp && sp - self.ss_sp <= self.ss_size
+    }
+
+    /// 转换为返回给用户的形式，`ss_flags` 中会标记信号栈是否被禁用以及 `sp` 是否位于信号栈上
+    fn to_user(&self, sp: usize) -> SignalStack {
+        let state = if !self.is_enabled() {
+            SS_DISABLE
+        } else if self.contains(sp) {
+            SS_ONSTACK
+        } else {
+            0
+        };
+        SignalStack {
+            ss_flags: self.ss_flags | state,
+            ..*self
+        }
+    }
+}
@@ -1,25 +1,30 @@
 use alloc::vec::Vec;
-use core::{ops::Deref, str::FromStr};
+use core::{ops::Deref, pin::pin, str::FromStr, time::Duration};
 
 use cervine::Cow;
 use defines::{
     error::{errno, KResult},
     fs::{
-        FstatFlags, IoVec, MountFlags, OpenFlags, PollEvents, PollFd, Stat, UnmountFlags, AT_FDCWD,
-        SEEK_CUR, SEEK_END, SEEK_SET,
+        AccessFlags, FstatFlags, IoVec, MountFlags, OpenFlags, PollEvents, PollFd, Stat, StatMode,
+        UnmountFlags, AT_FDCWD, EFD_SEMAPHORE, SEEK_CUR, SEEK_END, SEEK_SET,
     },
     misc::TimeSpec,
+    signal::SIGSET_SIZE_BYTES,
 };
+use event_listener::EventListener;
+use futures::future;
 use kernel_tracer::Instrument;
 use triomphe::Arc;
 
 use crate::{
     fs::{
-        self, resolve_path_with_dir_fd, DEntry, DirFile, File, FileDescriptor, FileSystemType,
-        InodeMode, SeekFrom, SeekableFile, VFS,
+        self, resolve_path_with_dir_fd, DEntry, DirFile, EventFd, File, FileDescriptor,
+        FileSystemType, InodeMode, SeekFrom, SeekableFile, VFS,
     },
     hart::local_hart,
     memory::UserCheck,
+    signal::KSignalSet,
+    time,
 };
 
 /// 操纵某个特殊文件的底层设备，尤其是字符特殊文件。目前只进行错误检验
@@ -44,11 +49,22 @@ pub fn sys_ioctl(fd: usize, request: usize, argp: usize) -> KResult {
 }
 
 /// 创建目录。`mode` 含义同 [`sys_openat()`]
-pub fn sys_mkdirat(dir_fd: usize, path: UserCheck<u8>, _mode: usize) -> KResult {
-    // TODO: [low] 暂时未支持 mode
+///
+/// 错误：
+/// - `EACCES` 没有父目录的写权限，或者没有路径上某个目录的搜索权限
+pub fn sys_mkdirat(dir_fd: usize, path: UserCheck<u8>, mode: usize) -> KResult {
     let path = path.check_cstr()?;
     let p2i = fs::resolve_path_with_dir_fd(dir_fd, &path)?;
-    p2i.dir.mkdir(p2i.last_component)?;
+    let cred = local_hart().curr_process().cred();
+    p2i.dir
+        .inode()
+        .meta()
+        .check_access(&cred, AccessFlags::WRITE)?;
+    let dir = p2i.dir.mkdir(p2i.last_component)?;
+    // TODO: [low] 暂时没有 umask
+    dir.inode()
+        .meta()
+        .init_owner(&cred, StatMode::permission(mode as u32));
     Ok(0)
 }
 
@@ -191,9 +207,12 @@ pub async fn sys_writev(fd: usize, mut iovec: UserCheck<IoVec>, vlen: usize) ->
 /// - `flags` 包括文件打开模式、创建标志、状态标志。
 ///     - 创建标志如 `CLOEXEC`, `CREAT` 等，仅在打开文件时发生作用
 ///     - 状态标志影响后续的 I/O 方式，而且可以动态修改
-/// - `mode` 是用于指定创建新文件时，该文件的 mode。目前应该不会用到
+/// - `mode` 是用于指定创建新文件时，该文件的权限位
 ///     - 它只会影响未来访问该文件的模式，但这一次打开该文件可以是随意的
-pub fn sys_openat(dir_fd: usize, path: UserCheck<u8>, flags: u32, mut _mode: u32) -> KResult {
+///
+/// 错误：
+/// - `EACCES` 没有文件的读写权限，或者创建文件时没有父目录的写权限，或者没有路径上某个目录的搜索权限
+pub fn sys_openat(dir_fd: usize, path: UserCheck<u8>, flags: u32, mut mode: u32) -> KResult {
     let path = path.check_cstr()?;
 
     let Some(flags) = OpenFlags::from_bits(flags) else {
@@ -202,10 +221,9 @@ pub fn sys_openat(dir_fd: usize, path: UserCheck<u8>, flags: u32, mut _mode: u32
     info!("oepnat flags {flags:?}");
 
     // TODO: [low] OpenFlags::DIRECT 目前是被忽略的
-    // TODO: [low] 暂时未支持 mode
     // 不是创建文件（以及临时文件）时，mode 被忽略
     if !flags.contains(OpenFlags::CREATE) {
-        _mode = 0;
+        mode = 0;
     }
 
     // 64 位版本应当是保证可以打开大文件的
@@ -218,12 +236,19 @@ pub fn sys_openat(dir_fd: usize, path: UserCheck<u8>, flags: u32, mut _mode: u32
     }
 
     let p2i = fs::resolve_path_with_dir_fd(dir_fd, &path)?;
+    let cred = local_hart().curr_process().cred();
     let new_file = if let Some(final_dentry) = p2i.dir.lookup(Cow::Borrowed(&p2i.last_component)) {
         // 指定了必须要创建文件，但该文件已存在
         if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) {
             return Err(errno::EEXIST);
         }
 
+        let (readable, writable) = flags.read_write();
+        let mut access = AccessFlags::empty();
+        access.set(AccessFlags::READ, readable);
+        access.set(AccessFlags::WRITE, writable);
+        final_dentry.meta().check_access(&cred, access)?;
+
         match final_dentry {
             DEntry::Dir(dir) => {
                 // 路径名指向一个目录，但是需要写入
@@ -251,7 +276,16 @@ pub fn sys_openat(dir_fd: usize, path: UserCheck<u8>, flags: u32, mut _mode: u32
         }
 
         debug!("create {} under {}", p2i.last_component, p2i.dir.name());
+        p2i.dir
+            .inode()
+            .meta()
+            .check_access(&cred, AccessFlags::WRITE)?;
         let dentry = p2i.dir.mknod(p2i.last_component, InodeMode::Regular)?;
+        // TODO: [low] 暂时没有 umask
+        dentry
+            .inode()
+            .meta()
+            .init_owner(&cred, StatMode::permission(mode));
         File::Seekable(Arc::new(SeekableFile::new(dentry)))
     };
 
@@ -299,6 +333,30 @@ pub fn sys_pipe2(pipefd: UserCheck<[i32; 2]>, flags: u32) -> KResult {
     Ok(0)
 }
 
+/// 创建一个 eventfd，其内部是一个 64 位的计数器，写入会累加计数器，读取会取出计数器的值。返回其文件描述符
+///
+/// 参数：
+/// - `init_val` 计数器的初始值
+/// - `flags` 可以包含 `EFD_SEMAPHORE`、`EFD_NONBLOCK` 和 `EFD_CLOEXEC`，
+///   后两者的值和 [`OpenFlags`] 中对应的标志相同
+///
+/// 错误：
+/// - `EINVAL` `flags` 非法
+/// - `EMFILE` 文件描述符数量达到上限
+pub fn sys_eventfd2(init_val: u32, flags: u32) -> KResult {
+    let semaphore = flags & EFD_SEMAPHORE != 0;
+    let flags = OpenFlags::from_bits(flags & !EFD_SEMAPHORE)
+        .filter(|flags| (OpenFlags::NONBLOCK | OpenFlags::CLOEXEC).contains(*flags))
+        .ok_or(errno::EINVAL)?;
+    let eventfd = File::EventFd(Arc::new(EventFd::new(u64::from(init_val), semaphore)));
+    let desc = FileDescriptor::new(eventfd, flags | OpenFlags::RDWR);
+    let fd = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.fd_table.add(desc))
+        .ok_or(errno::EMFILE)?;
+    Ok(fd as isize)
+}
+
 /// 获取目录项信息
 pub fn sys_getdents64(fd: usize, buf: UserCheck<[u8]>) -> KResult {
     let process = local_hart().curr_process();
@@ -346,6 +404,9 @@ pub fn sys_fcntl64(fd: usize, cmd: usize, arg: usize) -> KResult {
     match cmd {
         F_DUPFD | F_DUPFD_CLOEXEC => {
             let mut desc = inner.fd_table.get(fd).ok_or(errno::EBADF)?.clone();
+            if arg >= inner.fd_table.limit() {
+                return Err(errno::EINVAL);
+            }
             if cmd == F_DUPFD_CLOEXEC {
                 desc.set_close_on_exec(true);
             }
@@ -416,6 +477,9 @@ pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> KResult {
     if old_fd == new_fd {
         return Err(errno::EINVAL);
     }
+    if new_fd >= inner.fd_table.limit() {
+        return Err(errno::EBADF);
+    }
     let mut new_desc = desc.clone();
     if flags.contains(OpenFlags::CLOEXEC) {
         new_desc.set_close_on_exec(true);
@@ -532,8 +596,12 @@ pub fn sys_umount(target: UserCheck<u8>, flags: u32) -> KResult {
     let Some(flags) = UnmountFlags::from_bits(flags) else {
         todo!("[low] unsupported MountFlags: {flags:#b}");
     };
+    if !local_hart().curr_process().cred().is_privileged() {
+        return Err(errno::EPERM);
+    }
     let target = target.check_cstr()?;
-    VFS.unmount(&target, flags)?;
+    let p2i = resolve_path_with_dir_fd(AT_FDCWD, &target)?;
+    VFS.unmount(p2i, flags)?;
     Ok(0)
 }
 
@@ -555,7 +623,11 @@ pub fn sys_mount(
         let _data = data.check_cstr()?;
     }
 
-    VFS.mount(&target, &source, fs_type, flags)?;
+    if !local_hart().curr_process().cred().is_privileged() {
+        return Err(errno::EPERM);
+    }
+    let p2i = resolve_path_with_dir_fd(AT_FDCWD, &target)?;
+    VFS.mount(p2i, &source, fs_type, flags)?;
     Ok(0)
 }
 
@@ -623,7 +695,7 @@ pub fn sys_getcwd(buf: UserCheck<[u8]>) -> KResult {
     Ok(ret)
 }
 
-/// 等待一组文件描述符上的事件。返回就绪的文件描述符数量，如果超时则返回 0
+/// 等待一组文件描述符上的事件。返回就绪（包括 `POLLNVAL`）的文件描述符数量，如果超时则返回 0
 ///
 /// 如果任何文件描述符都没有发生请求的事件且没有错误，则将阻塞直到：
 ///
@@ -637,10 +709,14 @@ pub fn sys_getcwd(buf: UserCheck<[u8]>) -> KResult {
 /// - `fds` 描述感兴趣的所有文件描述符及事件，同时也是返回事件的输出参数
 ///     - 如果长度超过 `RLIMIT_NOFILE` 指定的 rlimit，则返回 EINVAL
 /// - `timeout` 如果为 `NULL` 则意味着无限的超时。为负返回 `EINVAL`
-/// - `signal_mask` 指定在阻塞期间忽略的信号
-/// - `sig_set_size` 是信号集的大小。似乎没有用
-#[allow(unused)]
-pub fn sys_ppoll(
+/// - `signal_mask` 若非空，则在等待期间临时替换线程的信号掩码
+/// - `sig_set_size` 是信号集的大小
+///
+/// 错误：
+/// - `EFAULT` 参数指向非法地址
+/// - `EINVAL` `fds` 过长，或者 `timeout` 非法，或者内核不支持 `sig_set_size`
+/// - `EINTR` 被信号打断并调用了 signal handler。没有调用 handler 的话会重启，只等待剩余的时间
+pub async fn sys_ppoll(
     fds: UserCheck<[PollFd]>,
     timeout: Option<UserCheck<TimeSpec>>,
     signal_mask: Option<UserCheck<u64>>,
@@ -648,19 +724,81 @@ pub fn sys_ppoll(
 ) -> KResult {
     let _enter = trace_span!("sys_ppoll");
 
-    // TODO: [mid] ppoll 要考虑 `timeout`
-    let _timeout = if let Some(timeout) = timeout {
-        let timeout = timeout.check_ptr()?.read();
-        if timeout < TimeSpec::default() {
+    let thread = Arc::clone(&local_hart().curr_thread_arc());
+    // 重启的 ppoll 只等待到原本的时刻
+    let restart_deadline = thread.lock_inner_with(|inner| inner.restart_deadline.take());
+    let deadline = match timeout {
+        Some(timeout) => {
+            let timeout = Duration::try_from(timeout.check_ptr()?.read())?;
+            Some(restart_deadline.unwrap_or_else(|| time::curr_time().saturating_add(timeout)))
+        }
+        None => None,
+    };
+    if let Some(signal_mask) = signal_mask {
+        if sig_set_size > SIGSET_SIZE_BYTES {
             return Err(errno::EINVAL);
         }
-        Some(timeout)
-    } else {
-        None
+        let mut mask = KSignalSet::from_user(signal_mask.check_ptr()?.read());
+        mask.remove(KSignalSet::SIGKILL | KSignalSet::SIGSTOP);
+        thread.lock_inner_with(|inner| {
+            let old_mask = core::mem::replace(&mut inner.signal_mask, mask);
+            inner.saved_signal_mask = Some(old_mask);
+        });
+    }
+
+    let ret = loop {
+        // 先注册监听再检查，以免错过通知。信号到来或者 signalfd 就绪都会通知 `signal_event`
+        let mut listeners = Vec::with_capacity(fds.len() + 1);
+        listeners.push(thread.signal_event.listen());
+        let ret = match poll_fds(&fds, &mut listeners) {
+            Ok(ret) => ret,
+            Err(e) => break Err(e),
+        };
+        if ret > 0 {
+            break Ok(ret);
+        }
+        if deadline.is_some_and(|deadline| time::curr_time() >= deadline) {
+            break Ok(0);
+        }
+        let interrupted = thread.lock_inner_with(|inner| {
+            !inner
+                .pending_signal
+                .set()
+                .difference(inner.signal_mask)
+                .is_empty()
+        });
+        if interrupted || thread.is_exiting() {
+            // 临时的信号掩码留到处理信号之后再恢复，重启时会重新设置
+            thread.lock_inner_with(|inner| inner.restart_deadline = deadline);
+            return Err(errno::ERESTARTNOHAND);
+        }
+        let wait = future::select_all(listeners);
+        match deadline {
+            // 超时或者收到通知，都回到循环开头重新检查
+            Some(deadline) => {
+                future::select(pin!(time::sleep_until(deadline)), wait).await;
+            }
+            None => {
+                wait.await;
+            }
+        }
     };
 
+    // 没有被信号打断的话，立即恢复信号掩码
+    thread.lock_inner_with(|inner| {
+        if let Some(mask) = inner.saved_signal_mask.take() {
+            inner.signal_mask = mask;
+        }
+    });
+    ret.map(|ret| ret as isize)
+}
+
+/// 检查 `fds` 中每个文件描述符的就绪状态并写入 `revents`，返回 `revents` 非空的数量。
+///
+/// 检查之前会先监听文件的就绪事件，放入 `listeners` 中
+fn poll_fds(fds: &UserCheck<[PollFd]>, listeners: &mut Vec<EventListener>) -> KResult<usize> {
     let process = local_hart().curr_process();
-    let mut inner = process.lock_inner();
+    let inner = process.lock_inner();
     if fds.len() >= inner.fd_table.limit() {
         return Err(errno::EINVAL);
     }
@@ -682,17 +820,35 @@ pub fn sys_ppoll(
                 todo!("[low] unsupported poll events: {:#b}", poll_fd_val.events);
             };
             debug!("poll events {:?}", events);
-            match &**fd {
+            let ready = match &**fd {
                 // TODO: `stream` 应该建立起合适的轮询机制用以支持 ppoll
                 File::Stream(_) | File::Dir(_) | File::Seekable(_) => {
-                    poll_fd_val.revents =
-                        (events & (PollEvents::POLLIN | PollEvents::POLLOUT)).bits();
+                    PollEvents::POLLIN | PollEvents::POLLOUT
+                }
+                File::Pipe(pipe) => {
+                    listeners.push(pipe.event().listen());
+                    pipe.poll()
                 }
-                File::Pipe(_) => todo!("[mid] impl other ppoll target"),
+                // signalfd 读取的是调用线程的信号，已经在监听 `signal_event` 了
+                File::SignalFd(signalfd) => signalfd.poll(),
+                File::TimerFd(timerfd) => {
+                    listeners.push(timerfd.event().listen());
+                    timerfd.poll()
+                }
+                File::EventFd(eventfd) => {
+                    listeners.push(eventfd.event().listen());
+                    eventfd.poll()
+                }
+            };
+            // `POLLERR` 和 `POLLHUP` 总是会返回，无需在 `events` 中指定
+            let revents = ready & (events | PollEvents::POLLERR | PollEvents::POLLHUP);
+            poll_fd_val.revents = revents.bits();
+            if !revents.is_empty() {
+                ret += 1;
             }
-            ret += 1;
         } else {
             poll_fd_val.revents = PollEvents::POLLNVAL.bits();
+            ret += 1;
         }
         poll_fd.write(poll_fd_val);
     }
@@ -10,7 +10,7 @@ use defines::{
 use crate::{
     fs::{File, InodeMode},
     hart::local_hart,
-    memory::{BackedInode, MapPermission, VirtAddr, VirtPageNum},
+    memory::{self, BackedInode, MapPermission, VirtAddr, VirtPageNum},
 };
 
 /// 映射虚拟内存。返回实际映射的地址（一般是页对齐的）。
@@ -35,6 +35,9 @@ use crate::{
 /// - `flags` 描述映射的特征，详细参考 [`MmapFlags`]
 /// - `fd` 被映射的文件描述符
 /// - `offset` 映射的起始偏移，必须是 `PAGE_SIZE` 的整数倍
+///
+/// 错误：
+/// - `ENOMEM` 没有足够的地址空间，或者映射后地址空间的大小会超过 `RLIMIT_AS`
 pub fn sys_mmap(
     addr: usize,
     len: usize,
@@ -97,6 +100,9 @@ fn private_anonymous_map(
     debug!("private anonymous map, addr: {addr:#}, len: {len}");
     let process = local_hart().curr_process();
     process.lock_inner_with(|inner| {
+        if inner.exceeds_address_space_limit(len.get()) {
+            return Err(errno::ENOMEM);
+        }
         inner
             .memory_space
             .try_map(addr, len, MapPermission::from(prot), flags)
@@ -134,6 +140,10 @@ fn shared_file_map(
         }
     }
 
+    if inner.exceeds_address_space_limit(len.get()) {
+        return Err(errno::ENOMEM);
+    }
+
     let backed_inode = (|| {
         let File::Seekable(bytes) = &**desc else {
             return Err(errno::EACCES);
@@ -141,21 +151,25 @@ fn shared_file_map(
         BackedInode::new(bytes.inode()).ok_or(errno::EACCES)
     })()?;
 
-    inner.memory_space.try_map_inode(
+    let vpn = inner.memory_space.try_map_inode(
         addr,
         len,
         MapPermission::from(prot),
         flags,
         backed_inode,
         file_page_id,
-    )
+    )?;
+    drop(inner);
+    memory::shootdown_tlb(None);
+    Ok(vpn)
 }
 
 /// 将一块区域取消映射。
 ///
 /// （未实现）有可能产生多个新的区域，比如 unmap 一个大区域的中间，左右两遍会变成两个单独的小区域
 ///
-/// 在目前的实现中应该只会在参数不正确（`addr` 未对齐、`len` 为 0）时返回 `EINVAL` 一种错误
+/// 在目前的实现中应该只会在参数不正确（`addr` 未对齐、`len` 为 0），
+/// 或者范围包含信号 trampoline 等保留区域时返回 `EINVAL` 一种错误
 pub fn sys_munmap(addr: usize, len: usize) -> KResult {
     debug!("unmap {addr}..{}", addr + len);
     if addr & PAGE_OFFSET_MASK != 0 || len == 0 || addr.saturating_add(len) > LOW_ADDRESS_END {
@@ -164,7 +178,8 @@ pub fn sys_munmap(addr: usize, len: usize) -> KResult {
     let va_start = VirtAddr(addr);
     local_hart()
         .curr_process()
-        .lock_inner_with(|inner| inner.memory_space.unmap(va_start..va_start + len));
+        .lock_inner_with(|inner| inner.memory_space.unmap(va_start..va_start + len))?
+        .shootdown();
     Ok(0)
 }
 
@@ -175,7 +190,11 @@ pub fn sys_munmap(addr: usize, len: usize) -> KResult {
 /// 参数：
 /// - `brk` 希望设置的 program break 值
 pub fn sys_brk(brk: usize) -> KResult {
-    let process = local_hart().curr_process();
-    let mut inner = process.lock_inner();
-    Ok(inner.set_user_brk(VirtAddr(brk)).0 as isize)
+    let (new_brk, unmapped) = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.set_user_brk(VirtAddr(brk)));
+    if let Some(unmapped) = unmapped {
+        unmapped.shootdown();
+    }
+    Ok(new_brk.0 as isize)
 }
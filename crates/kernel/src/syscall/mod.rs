@@ -1,9 +1,11 @@
 mod fs;
 mod memory;
 mod process;
+mod sched;
 mod signal;
 mod thread;
 mod time;
+mod timer;
 
 use defines::{
     error::{errno, KResult},
@@ -12,9 +14,11 @@ use defines::{
 use fs::*;
 use memory::*;
 use process::*;
+use sched::*;
 use signal::*;
 use thread::*;
 use time::*;
+use timer::*;
 
 use crate::{hart::local_hart, memory::UserCheck, process::exit_process};
 
@@ -53,6 +57,7 @@ pub async fn syscall(id: usize, args: [usize; 6]) -> isize {
 async fn syscall_impl(id: usize, args: [usize; 6]) -> KResult {
     match id {
         GETCWD => sys_getcwd(UserCheck::new_slice(args[0] as _, args[1]).ok_or(errno::EINVAL)?),
+        EVENTFD2 => sys_eventfd2(args[0] as _, args[1] as _),
         DUP => sys_dup(args[0]),
         DUP3 => sys_dup3(args[0], args[1], args[2] as _),
         FCNTL64 => sys_fcntl64(args[0], args[1], args[2]),
@@ -126,11 +131,20 @@ async fn syscall_impl(id: usize, args: [usize; 6]) -> KResult {
             )
             .await
         }
-        PPOLL => sys_ppoll(
-            UserCheck::new_slice(args[0] as _, args[1]).ok_or(errno::EINVAL)?,
-            UserCheck::new(args[2] as _),
-            UserCheck::new(args[3] as _),
-            args[4],
+        PPOLL => {
+            sys_ppoll(
+                UserCheck::new_slice(args[0] as _, args[1]).ok_or(errno::EINVAL)?,
+                UserCheck::new(args[2] as _),
+                UserCheck::new(args[3] as _),
+                args[4],
+            )
+            .await
+        }
+        SIGNALFD4 => sys_signalfd4(
+            args[0] as _,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+            args[2],
+            args[3] as _,
         ),
         NEWFSTATAT => sys_newfstatat(
             args[0],
@@ -139,15 +153,110 @@ async fn syscall_impl(id: usize, args: [usize; 6]) -> KResult {
             args[3],
         ),
         NEWFSTAT => sys_newfstat(args[0], UserCheck::new(args[1] as _).ok_or(errno::EINVAL)?),
+        TIMERFD_CREATE => sys_timerfd_create(args[0], args[1] as _),
+        TIMERFD_SETTIME => sys_timerfd_settime(
+            args[0],
+            args[1] as _,
+            UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+            UserCheck::new(args[3] as _),
+        ),
+        TIMERFD_GETTIME => {
+            sys_timerfd_gettime(args[0], UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?)
+        }
         EXIT => sys_exit(args[0] as _),
         EXIT_GROUP => sys_exit_group(args[0] as _),
         SET_TID_ADDRESS => sys_set_tid_address(args[0] as _),
-        NANOSLEEP => sys_nanosleep(UserCheck::new(args[0] as _).ok_or(errno::EINVAL)?).await,
+        FUTEX => {
+            sys_futex(
+                args[0],
+                args[1] as _,
+                args[2] as _,
+                args[3],
+                args[4],
+                args[5] as _,
+            )
+            .await
+        }
+        CLOCK_NANOSLEEP => {
+            sys_clock_nanosleep(
+                args[0],
+                args[1] as _,
+                UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+                UserCheck::new(args[3] as _),
+            )
+            .await
+        }
+        NANOSLEEP => {
+            sys_nanosleep(
+                UserCheck::new(args[0] as _).ok_or(errno::EFAULT)?,
+                UserCheck::new(args[1] as _),
+            )
+            .await
+        }
+        GETITIMER => sys_getitimer(
+            args[0] as _,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+        ),
+        SETITIMER => sys_setitimer(
+            args[0] as _,
+            UserCheck::new(args[1] as _),
+            UserCheck::new(args[2] as _),
+        ),
+        TIMER_CREATE => sys_timer_create(
+            args[0],
+            UserCheck::new(args[1] as _),
+            UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+        ),
+        TIMER_SETTIME => sys_timer_settime(
+            args[0],
+            args[1] as _,
+            UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+            UserCheck::new(args[3] as _),
+        ),
+        TIMER_GETTIME => {
+            sys_timer_gettime(args[0], UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?)
+        }
+        TIMER_GETOVERRUN => sys_timer_getoverrun(args[0]),
+        TIMER_DELETE => sys_timer_delete(args[0]),
+        CLOCK_SETTIME => sys_clock_settime(
+            args[0] as _,
+            UserCheck::new(args[1] as _).ok_or(errno::EINVAL)?,
+        ),
+        CLOCK_GETRES => sys_clock_getres(args[0] as _, UserCheck::new(args[1] as _)),
         CLOCK_GETTIME => sys_clock_gettime(
             args[0] as _,
             UserCheck::new(args[1] as _).ok_or(errno::EINVAL)?,
         ),
+        SCHED_SETPARAM => {
+            sys_sched_setparam(args[0], UserCheck::new(args[1] as _).ok_or(errno::EINVAL)?)
+        }
+        SCHED_SETSCHEDULER => sys_sched_setscheduler(
+            args[0],
+            args[1] as _,
+            UserCheck::new(args[2] as _).ok_or(errno::EINVAL)?,
+        ),
+        SCHED_GETSCHEDULER => sys_sched_getscheduler(args[0]),
+        SCHED_GETPARAM => {
+            sys_sched_getparam(args[0], UserCheck::new(args[1] as _).ok_or(errno::EINVAL)?)
+        }
+        SCHED_SETAFFINITY => {
+            sys_sched_setaffinity(
+                args[0],
+                UserCheck::new_slice(args[2] as _, args[1]).ok_or(errno::EINVAL)?,
+            )
+            .await
+        }
+        SCHED_GETAFFINITY => sys_sched_getaffinity(
+            args[0],
+            UserCheck::new_slice(args[2] as _, args[1]).ok_or(errno::EINVAL)?,
+        ),
         SCHED_YIELD => sys_sched_yield().await,
+        SCHED_GET_PRIORITY_MAX => sys_sched_get_priority_max(args[0] as _),
+        SCHED_GET_PRIORITY_MIN => sys_sched_get_priority_min(args[0] as _),
+        KILL => sys_kill(args[0] as _, args[1]),
+        TKILL => sys_tkill(args[0] as _, args[1]),
+        TGKILL => sys_tgkill(args[0] as _, args[1] as _, args[2]),
+        SIGALTSTACK => sys_sigaltstack(UserCheck::new(args[0] as _), UserCheck::new(args[1] as _)),
         RT_SIGACTION => sys_rt_sigaction(
             args[0],
             UserCheck::new(args[1] as _),
@@ -159,18 +268,73 @@ async fn syscall_impl(id: usize, args: [usize; 6]) -> KResult {
             UserCheck::new(args[2] as _),
             args[3],
         ),
+        RT_SIGQUEUEINFO => sys_rt_sigqueueinfo(
+            args[0] as _,
+            args[1],
+            UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+        ),
         RT_SIGRETURN => sys_rt_sigreturn(),
-        SETPRIORITY => sys_setpriority(args[0] as _),
+        RT_SIGSUSPEND => {
+            sys_rt_sigsuspend(UserCheck::new(args[0] as _).ok_or(errno::EFAULT)?, args[1]).await
+        }
+        RT_SIGPENDING => {
+            sys_rt_sigpending(UserCheck::new(args[0] as _).ok_or(errno::EFAULT)?, args[1])
+        }
+        RT_SIGTIMEDWAIT => {
+            sys_rt_sigtimedwait(
+                UserCheck::new(args[0] as _).ok_or(errno::EFAULT)?,
+                UserCheck::new(args[1] as _),
+                UserCheck::new(args[2] as _),
+                args[3],
+            )
+            .await
+        }
+        SETPRIORITY => sys_setpriority(args[0] as _, args[1], args[2] as _),
+        GETPRIORITY => sys_getpriority(args[0] as _, args[1]),
         TIMES => sys_times(UserCheck::new(args[0] as _).ok_or(errno::EINVAL)?),
-        SETPGID => sys_setpgid(args[0], args[1]),
+        SETPGID => sys_setpgid(args[0] as _, args[1] as _),
         GETPGID => sys_getpgid(args[0]),
+        GETSID => sys_getsid(args[0]),
+        SETSID => sys_setsid(),
+        SETUID => sys_setuid(args[0] as _),
+        SETGID => sys_setgid(args[0] as _),
+        SETRESUID => sys_setresuid(args[0] as _, args[1] as _, args[2] as _),
+        SETRESGID => sys_setresgid(args[0] as _, args[1] as _, args[2] as _),
+        GETRESUID => sys_getresuid(
+            UserCheck::new(args[0] as _).ok_or(errno::EFAULT)?,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+            UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+        ),
+        GETRESGID => sys_getresgid(
+            UserCheck::new(args[0] as _).ok_or(errno::EFAULT)?,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+            UserCheck::new(args[2] as _).ok_or(errno::EFAULT)?,
+        ),
+        GETGROUPS => sys_getgroups(args[0], UserCheck::new_slice(args[1] as _, args[0])),
+        SETGROUPS => sys_setgroups(args[0], UserCheck::new(args[1] as _)),
         UNAME => sys_uname(UserCheck::new(args[0] as _).ok_or(errno::EINVAL)?),
+        GETRLIMIT => sys_getrlimit(
+            args[0] as _,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+        ),
+        SETRLIMIT => sys_setrlimit(
+            args[0] as _,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+        ),
+        GETRUSAGE => sys_getrusage(
+            args[0] as _,
+            UserCheck::new(args[1] as _).ok_or(errno::EFAULT)?,
+        ),
         GET_TIME_OF_DAY => {
             sys_get_time_of_day(UserCheck::new(args[0] as _).ok_or(errno::EINVAL)?, args[1])
         }
+        SETTIMEOFDAY => sys_settimeofday(UserCheck::new(args[0] as _), args[1]),
         GETPID => sys_getpid(),
         GETPPID => sys_getppid(),
-        GETUID | GETEUID | GETGID | GETEGID => Ok(0), // TODO: 目前不实现用户和用户组相关的部分
+        GETUID => sys_getuid(),
+        GETEUID => sys_geteuid(),
+        GETGID => sys_getgid(),
+        GETEGID => sys_getegid(),
         GETTID => sys_gettid(),
         BRK => sys_brk(args[0]),
         MUNMAP => sys_munmap(args[0], args[1]),
@@ -191,7 +355,28 @@ async fn syscall_impl(id: usize, args: [usize; 6]) -> KResult {
             args[4] as _,
             args[5],
         ),
-        WAIT4 => sys_wait4(args[0] as _, UserCheck::new(args[1] as _), args[2], args[3]).await,
+        RT_TGSIGQUEUEINFO => sys_rt_tgsigqueueinfo(
+            args[0] as _,
+            args[1] as _,
+            args[2],
+            UserCheck::new(args[3] as _).ok_or(errno::EFAULT)?,
+        ),
+        WAIT4 => {
+            sys_wait4(
+                args[0] as _,
+                UserCheck::new(args[1] as _),
+                args[2],
+                UserCheck::new(args[3] as _),
+            )
+            .await
+        }
+        GETCPU => sys_getcpu(UserCheck::new(args[0] as _), UserCheck::new(args[1] as _)),
+        PRLIMIT64 => sys_prlimit64(
+            args[0],
+            args[1] as _,
+            UserCheck::new(args[2] as _),
+            UserCheck::new(args[3] as _),
+        ),
         _ => {
             error!("Unsupported syscall id: {id}");
             exit_process(&local_hart().curr_process(), -10);
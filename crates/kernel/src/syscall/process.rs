@@ -4,10 +4,13 @@ use alloc::vec::Vec;
 use core::num::NonZeroUsize;
 
 use atomic::Ordering;
+use cervine::Cow;
 use compact_str::CompactString;
 use defines::{
     error::{errno, KResult},
-    misc::{CloneFlags, UtsName, WaitFlags},
+    fs::{AccessFlags, AT_FDCWD},
+    misc::{CloneFlags, Rusage, UtsName, WaitFlags, NGROUPS_MAX},
+    resource::{RLimit, RLIM_NLIMITS},
 };
 use event_listener::listener;
 use triomphe::Arc;
@@ -17,8 +20,9 @@ use crate::{
     fs::{self, DEntry, InodeMode},
     hart::local_hart,
     memory::UserCheck,
-    process::{exit_process, INITPROC},
-    signal::Signal,
+    process::{self, exit_process, JobEvent, Process},
+    signal::{self, Signal},
+    thread,
 };
 
 /// 退出当前线程，结束用户线程循环。
@@ -57,21 +61,17 @@ pub fn sys_getppid() -> KResult {
     Ok(ppid)
 }
 
-/// 创建子任务，通过 flags 进行精确控制。父进程返回子进程 pid，子进程返回 0。
-///
-/// TODO: 完善 `sys_clone()` 及文档
+/// 创建子任务，通过 flags 进行精确控制。父进程返回子任务的 pid 或 tid，子任务返回 0。
 ///
 /// 参数：
 /// - `flags` 低八位 `exit_signal`，高位指定 clone 的方式。具体参看
 ///   [`CloneFlags`]
-/// - `user_stack` 指定用户栈的
-pub fn sys_clone(
-    flags: usize,
-    user_stack: usize,
-    _ptid: usize,
-    _tls: usize,
-    _ctid: usize,
-) -> KResult {
+/// - `user_stack` 指定子任务的用户栈栈顶，为 0 则沿用（进程）或使用新分配的用户栈（线程）
+/// - `ptid` 若指定了 `CLONE_PARENT_SETTID`，则在父任务的该地址写入子任务的 tid
+/// - `tls` 若指定了 `CLONE_SETTLS`，则设置为子任务的 `tp`
+/// - `ctid` 若指定了 `CLONE_CHILD_SETTID`，则在子任务的该地址写入子任务的 tid；
+///   若指定了 `CLONE_CHILD_CLEARTID`，则作为子任务的 `clear_child_tid`
+pub fn sys_clone(flags: usize, user_stack: usize, ptid: usize, tls: usize, ctid: usize) -> KResult {
     let Ok(flags) = u32::try_from(flags) else {
         error!("flags exceeds u32: {flags:#b}");
         return Err(errno::UNSUPPORTED);
@@ -83,18 +83,16 @@ pub fn sys_clone(
         return Err(errno::UNSUPPORTED);
     };
     if clone_flags.contains(CloneFlags::CLONE_THREAD) {
-        // 创建线程的情况。这些 flag 应该同时设置
-        assert!(clone_flags.contains(
-            CloneFlags::CLONE_VM
-                | CloneFlags::CLONE_FS
-                | CloneFlags::CLONE_FILES
-                | CloneFlags::CLONE_SIGHAND
-                | CloneFlags::CLONE_THREAD
-                | CloneFlags::CLONE_SYSVSEM
-                | CloneFlags::CLONE_SETTLS
-                | CloneFlags::CLONE_PARENT_SETTID
-                | CloneFlags::CLONE_CHILD_CLEARTID
-        ));
+        // 创建线程的情况。`CLONE_THREAD` 要求 `CLONE_SIGHAND`，而 `CLONE_SIGHAND` 又要求 `CLONE_VM`
+        if !clone_flags.contains(CloneFlags::CLONE_VM | CloneFlags::CLONE_SIGHAND) {
+            warn!("create thread without CLONE_VM or CLONE_SIGHAND: {clone_flags:?}");
+            return Err(errno::EINVAL);
+        }
+        // 目前文件系统信息和文件描述符表都是进程级别的，线程只能共享它们
+        if !clone_flags.contains(CloneFlags::CLONE_FS | CloneFlags::CLONE_FILES) {
+            error!("create thread without CLONE_FS or CLONE_FILES: {clone_flags:?}");
+            return Err(errno::UNSUPPORTED);
+        }
 
         // 创建线程时不该有 `exit_signal`
         if flags as u8 != 0 {
@@ -104,7 +102,49 @@ pub fn sys_clone(
             );
             return Err(errno::EINVAL);
         }
-        todo!("[mid] support create thread");
+
+        // 先检查用户指针，以免创建线程后才发现地址非法
+        let ptid = if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
+            Some(unsafe {
+                UserCheck::new(ptid as *mut i32)
+                    .ok_or(errno::EFAULT)?
+                    .check_ptr_mut()?
+            })
+        } else {
+            None
+        };
+        let set_child_tid = if clone_flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+            Some(unsafe {
+                UserCheck::new(ctid as *mut i32)
+                    .ok_or(errno::EFAULT)?
+                    .check_ptr_mut()?
+            })
+        } else {
+            None
+        };
+        let tls = clone_flags
+            .contains(CloneFlags::CLONE_SETTLS)
+            .then_some(tls);
+
+        let new_thread = local_hart().curr_process_arc().clone_thread(
+            &local_hart().curr_thread(),
+            NonZeroUsize::new(user_stack),
+            tls,
+        );
+        let tid = new_thread.tid();
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+            new_thread.lock_inner_with(|inner| inner.clear_child_tid = ctid);
+        }
+        // 线程共享地址空间，因此父子线程写入的其实是同一个地址空间
+        if let Some(ptid) = ptid {
+            ptid.write(tid as i32);
+        }
+        if let Some(set_child_tid) = set_child_tid {
+            set_child_tid.write(tid as i32);
+        }
+        debug!("create thread {tid}");
+        thread::spawn_user_thread(new_thread);
+        Ok(tid as isize)
     } else {
         // 创建进程的情况。这些 flag 都不应该设置
         assert!(!clone_flags.intersects(
@@ -132,10 +172,11 @@ pub fn sys_clone(
             exit_signal = Some(signal);
         }
         let user_stack = NonZeroUsize::new(user_stack);
-        let new_process = local_hart()
-            .curr_thread()
-            .process
-            .fork(user_stack, exit_signal);
+        let new_process = local_hart().curr_process_arc().fork(
+            &local_hart().curr_thread(),
+            user_stack,
+            exit_signal,
+        );
         Ok(new_process.pid() as isize)
     }
 }
@@ -146,6 +187,9 @@ pub fn sys_clone(
 /// - `pathname` 给出了要加载的可执行文件的名字，必须以 `\0` 结尾
 /// - `argv` 给出了参数列表。其最后一个元素必须是 0
 /// - `envp` 给出环境变量列表，其最后一个元素必须是 0
+///
+/// 错误：
+/// - `EACCES` 不是常规文件，或者没有执行权限，或者没有路径上某个目录的搜索权限
 pub async fn sys_execve(
     pathname: UserCheck<u8>,
     argv: UserCheck<usize>,
@@ -179,52 +223,58 @@ pub async fn sys_execve(
     // 执行新进程
 
     let elf_data = {
-        let DEntry::Bytes(bytes) = fs::find_file(&pathname.check_cstr()?)? else {
+        let p2i = fs::resolve_path_with_dir_fd(AT_FDCWD, &pathname.check_cstr()?)?;
+        let DEntry::Bytes(bytes) = p2i
+            .dir
+            .lookup(Cow::Owned(p2i.last_component))
+            .ok_or(errno::ENOENT)?
+        else {
             return Err(errno::EISDIR);
         };
-        if bytes.inode().meta().mode() != InodeMode::Regular {
+        let meta = bytes.inode().meta();
+        if meta.mode() != InodeMode::Regular {
             return Err(errno::EACCES);
         }
+        // TODO: [low] 暂不支持 set-user-ID 和 set-group-ID 位
+        meta.check_access(&local_hart().curr_process().cred(), AccessFlags::EXECUTE)?;
         fs::read_file(bytes.inode()).await?
     };
 
     let argc = args.len();
-    local_hart().curr_process().exec(&elf_data, args, envs)?;
+    let process = Arc::clone(&local_hart().curr_process_arc());
+    process.exec(&elf_data, args, envs).await?;
     Ok(argc as isize)
 }
 
-/// 挂起本线程，等待子进程改变状态（终止、或信号处理）。默认而言，会阻塞式等待子进程终止
+/// 挂起本线程，等待子进程改变状态（终止、被信号停止或恢复）。默认而言，会阻塞式等待子进程终止
 ///
 /// 若成功，返回子进程 pid，若 `options` 指定了 `WNOHANG` 且子线程存在但状态未改变，则返回 0
 ///
 /// 参数：
 /// - `pid` 要等待的 pid
-///     - `pid` < -1，则等待一个 pgid 为 `pid` 绝对值的子进程，目前不支持
+///     - `pid` < -1，则等待一个 pgid 为 `pid` 绝对值的子进程
 ///     - `pid` == -1，则等待任意一个子进程
-///     - `pid` == 0，则等待一个 pgid 与调用进程**调用时**的 pgid 相同的子进程，目前不支持
+///     - `pid` == 0，则等待一个 pgid 与调用进程**调用时**的 pgid 相同的子进程
 ///     - `pid` > 0，则等待指定 `pid` 的子进程
-/// - `wstatus` 若非空则用于表示某些状态，目前而言似乎仅需往里写入子进程的 exit code
-/// - `options` 控制等待方式，详细查看 [`WaitFlags`]，目前只支持 `WNOHANG`
-/// - `rusgae` 用于统计子进程资源使用情况，目前不支持
+/// - `wstatus` 若非空则写入子进程的状态，构成参考 `WIFEXITED`、`WIFSTOPPED`、`WIFCONTINUED` 等宏
+/// - `options` 控制等待方式，详细查看 [`WaitFlags`]，支持 `WNOHANG`、`WUNTRACED` 和 `WCONTINUED`
+/// - `rusage` 若非空则写入子进程的资源使用情况，目前只有 CPU 时间。对于终止的子进程，包括它回收了的子进程
 pub async fn sys_wait4(
     pid: isize,
     wstatus: Option<UserCheck<i32>>,
     options: usize,
-    rusage: usize,
+    rusage: Option<UserCheck<Rusage>>,
 ) -> KResult {
-    assert!(
-        pid == -1 || pid > 0,
-        "pid < -1 && pid == 0, i.e. wait process group, not supported yet"
-    );
-    assert_eq!(rusage, 0, "rusage not supported, so must be nullptr");
     let options = WaitFlags::from_bits(options as u32).ok_or(errno::EINVAL)?;
-    if options.contains(WaitFlags::WIMTRACED) || options.contains(WaitFlags::WCONTINUED) {
-        error!("only support WNOHANG now");
-        return Err(errno::UNSUPPORTED);
-    }
 
-    // 尝试找到一个符合条件，且已经是僵尸的子进程
     let process = Arc::clone(&*local_hart().curr_process_arc());
+    // pid == 0 时，使用调用时的 pgid
+    let pgid = match pid {
+        0 => Some(process.lock_inner_with(|inner| inner.pgid)),
+        ..=-2 => Some(pid.unsigned_abs()),
+        _ => None,
+    };
+    // 尝试找到一个符合条件，且已经是僵尸或者状态改变了的子进程
     loop {
         listener!(process.wait4_event => listener);
         {
@@ -234,13 +284,22 @@ pub async fn sys_wait4(
             let mut inner = process.lock_inner();
             let mut has_proper_child = false;
             let mut child_index = None;
+            let mut job_children = Vec::new();
             for (index, child) in inner.children.iter().enumerate() {
-                if pid == -1 || child.pid() == pid as usize {
-                    has_proper_child = true;
-                    if child.is_zombie() {
-                        child_index = Some(index);
-                    }
+                let matched = if let Some(pgid) = pgid {
+                    child.lock_inner_with(|inner| inner.pgid) == pgid
+                } else {
+                    pid == -1 || child.pid() == pid as usize
+                };
+                if !matched {
+                    continue;
                 }
+                has_proper_child = true;
+                if child.is_zombie() {
+                    child_index = Some(index);
+                    break;
+                }
+                job_children.push(Arc::clone(child));
             }
 
             if !has_proper_child {
@@ -249,14 +308,53 @@ pub async fn sys_wait4(
 
             if let Some(index) = child_index {
                 let child = inner.children.remove(index);
+                // 子进程的 CPU 时间并入本进程的子进程统计中
+                let child_times = child.lock_inner_with(|child_inner| {
+                    child_inner.cpu_times() + child_inner.children_times
+                });
+                inner.children_times += child_times;
                 drop(inner);
                 let found_pid = child.pid();
+                process::remove_process(found_pid);
                 let exit_code = child.exit_code().expect("Thread should be zombie");
                 if let Some(wstatus) = wstatus {
                     let wstatus = unsafe { wstatus.check_ptr_mut()? };
                     // *wstatus 的构成，可能要参考 WEXITSTATUS 那几个宏
                     wstatus.write((exit_code as u8 as i32) << 8);
                 }
+                if let Some(rusage) = rusage {
+                    unsafe { rusage.check_ptr_mut()? }.write(Rusage::from(child_times));
+                }
+                return Ok(found_pid as isize);
+            }
+
+            // 没有僵尸子进程，再看有没有被停止或者恢复的子进程。报告过的状态变化不会再被报告
+            let job_child = job_children.iter().find_map(|child| {
+                child.lock_inner_with(|inner| {
+                    let status = match inner.job_event {
+                        Some(JobEvent::Stopped(signal))
+                            if options.contains(WaitFlags::WUNTRACED) =>
+                        {
+                            ((signal.to_user() as i32) << 8) | 0x7f
+                        }
+                        Some(JobEvent::Continued) if options.contains(WaitFlags::WCONTINUED) => {
+                            0xffff
+                        }
+                        _ => return None,
+                    };
+                    inner.job_event = None;
+                    Some((child.pid(), status, inner.cpu_times()))
+                })
+            });
+            if let Some((found_pid, status, child_times)) = job_child {
+                drop(inner);
+                if let Some(wstatus) = wstatus {
+                    let wstatus = unsafe { wstatus.check_ptr_mut()? };
+                    wstatus.write(status);
+                }
+                if let Some(rusage) = rusage {
+                    unsafe { rusage.check_ptr_mut()? }.write(Rusage::from(child_times));
+                }
                 return Ok(found_pid as isize);
             }
 
@@ -267,14 +365,10 @@ pub async fn sys_wait4(
         }
 
         trace!("no proper child exited");
-        listener.await;
+        signal::interruptible(listener).await?;
     }
 }
 
-pub fn sys_setpriority(_prio: isize) -> KResult {
-    todo!("[low] sys_setpriority")
-}
-
 /// 设置线程控制块中 `clear_child_tid` 的值为 `tidptr`。总是返回调用者线程的 tid。
 ///
 /// 参数：
@@ -295,18 +389,346 @@ pub fn sys_uname(utsname: UserCheck<UtsName>) -> KResult {
     Ok(0)
 }
 
-/// 返回进程组号
+/// 根据 `pid` 找到目标进程，`pid` 为 0 表示调用进程
+fn target_process(pid: usize) -> KResult<Arc<Process>> {
+    if pid == 0 {
+        Ok(Arc::clone(&local_hart().curr_process_arc()))
+    } else {
+        process::find_process(pid).ok_or(errno::ESRCH)
+    }
+}
+
+/// 设置进程 `pid` 的进程组号为 `pgid`。成功返回 0
+///
+/// 参数：
+/// - `pid` 目标进程，必须是调用进程或者其子进程。为 0 表示调用进程
+/// - `pgid` 目标进程组。为 0 表示使用目标进程的 pid 作为进程组号，即新建一个进程组
+///
+/// 错误：
+/// - `EINVAL` `pgid` 小于 0
+/// - `ESRCH` 目标进程不是调用进程或其子进程
+/// - `EPERM` 目标进程是会话首进程，或与调用进程不在同一会话，或 `pgid` 不是同一会话中已有的进程组
+pub fn sys_setpgid(pid: isize, pgid: isize) -> KResult {
+    if pid < 0 || pgid < 0 {
+        return Err(errno::EINVAL);
+    }
+    let curr = Arc::clone(&local_hart().curr_process_arc());
+    let target = if pid == 0 || pid as usize == curr.pid() {
+        Arc::clone(&curr)
+    } else {
+        curr.lock_inner_with(|inner| {
+            inner
+                .children
+                .iter()
+                .find(|child| child.pid() == pid as usize)
+                .cloned()
+        })
+        .ok_or(errno::ESRCH)?
+    };
+    let pgid = if pgid == 0 {
+        target.pid()
+    } else {
+        pgid as usize
+    };
+    debug!("set pgid of {} to {pgid}", target.pid());
+
+    let curr_sid = curr.lock_inner_with(|inner| inner.sid);
+    let target_sid = target.lock_inner_with(|inner| inner.sid);
+    if target_sid != curr_sid || target_sid == target.pid() {
+        return Err(errno::EPERM);
+    }
+    // 除非是新建进程组，否则要求进程组在同一会话中已存在
+    if pgid != target.pid()
+        && !process::processes_in_group(pgid)
+            .iter()
+            .any(|process| process.lock_inner_with(|inner| inner.sid) == curr_sid)
+    {
+        return Err(errno::EPERM);
+    }
+    target.lock_inner_with(|inner| inner.pgid = pgid);
+    Ok(0)
+}
+
+/// 返回进程 `pid` 的进程组号，`pid` 为 0 表示调用进程
 ///
-/// TODO: 暂时未实现
-pub fn sys_setpgid(_pid: usize, _pgid: usize) -> KResult {
-    debug!("set pgid of {_pid} to {_pgid}");
-    Ok(INITPROC.pid() as isize)
+/// 错误：
+/// - `ESRCH` 找不到进程 `pid`
+pub fn sys_getpgid(pid: usize) -> KResult {
+    let pgid = target_process(pid)?.lock_inner_with(|inner| inner.pgid);
+    Ok(pgid as isize)
 }
 
-/// 返回进程组号
+/// 新建一个会话，调用进程成为会话首进程以及新进程组的组长，且没有控制终端。返回新的会话号
 ///
-/// TODO: 暂时未实现，仅返回 INITPROC 的 pid
-pub fn sys_getpgid(_pid: usize) -> KResult {
-    debug!("get pgid of {_pid}");
-    Ok(INITPROC.pid() as isize)
+/// 错误：
+/// - `EPERM` 调用进程已经是进程组组长，或者已经存在 pgid 与其 pid 相同的进程组
+pub fn sys_setsid() -> KResult {
+    let curr = Arc::clone(&local_hart().curr_process_arc());
+    let pid = curr.pid();
+    if !process::processes_in_group(pid).is_empty() {
+        return Err(errno::EPERM);
+    }
+    curr.lock_inner_with(|inner| {
+        inner.pgid = pid;
+        inner.sid = pid;
+    });
+    debug!("create session {pid}");
+    Ok(pid as isize)
+}
+
+/// 返回进程 `pid` 的会话号，`pid` 为 0 表示调用进程
+///
+/// 错误：
+/// - `ESRCH` 找不到进程 `pid`
+pub fn sys_getsid(pid: usize) -> KResult {
+    let sid = target_process(pid)?.lock_inner_with(|inner| inner.sid);
+    Ok(sid as isize)
+}
+
+/// 返回调用进程的真实用户 ID
+pub fn sys_getuid() -> KResult {
+    let uid = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.uid);
+    Ok(uid as isize)
+}
+
+/// 返回调用进程的有效用户 ID
+pub fn sys_geteuid() -> KResult {
+    let euid = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.euid);
+    Ok(euid as isize)
+}
+
+/// 返回调用进程的真实组 ID
+pub fn sys_getgid() -> KResult {
+    let gid = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.gid);
+    Ok(gid as isize)
+}
+
+/// 返回调用进程的有效组 ID
+pub fn sys_getegid() -> KResult {
+    let egid = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.egid);
+    Ok(egid as isize)
+}
+
+/// 设置调用进程的有效用户 ID。特权进程会同时设置真实用户 ID 和保存的 set-user-ID
+///
+/// 错误：
+/// - `EINVAL` `uid` 为 -1
+/// - `EPERM` 非特权进程，且 `uid` 不是真实用户 ID 或保存的 set-user-ID
+pub fn sys_setuid(uid: u32) -> KResult {
+    if uid == u32::MAX {
+        return Err(errno::EINVAL);
+    }
+    local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.set_uid(uid))?;
+    Ok(0)
+}
+
+/// 设置调用进程的有效组 ID。特权进程会同时设置真实组 ID 和保存的 set-group-ID
+///
+/// 错误：
+/// - `EINVAL` `gid` 为 -1
+/// - `EPERM` 非特权进程，且 `gid` 不是真实组 ID 或保存的 set-group-ID
+pub fn sys_setgid(gid: u32) -> KResult {
+    if gid == u32::MAX {
+        return Err(errno::EINVAL);
+    }
+    local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.set_gid(gid))?;
+    Ok(0)
+}
+
+/// 为 -1 的 ID 表示不改变
+fn optional_id(id: u32) -> Option<u32> {
+    (id != u32::MAX).then_some(id)
+}
+
+/// 设置调用进程的真实、有效用户 ID 和保存的 set-user-ID
+///
+/// 参数：
+/// - `ruid`、`euid`、`suid` 为 -1 时对应的 ID 保持不变
+///
+/// 错误：
+/// - `EPERM` 非特权进程，且某个 ID 不是当前的真实、有效用户 ID 或保存的 set-user-ID 之一
+pub fn sys_setresuid(ruid: u32, euid: u32, suid: u32) -> KResult {
+    local_hart().curr_process().lock_inner_with(|inner| {
+        inner
+            .cred
+            .set_resuid(optional_id(ruid), optional_id(euid), optional_id(suid))
+    })?;
+    Ok(0)
+}
+
+/// 设置调用进程的真实、有效组 ID 和保存的 set-group-ID
+///
+/// 参数：
+/// - `rgid`、`egid`、`sgid` 为 -1 时对应的 ID 保持不变
+///
+/// 错误：
+/// - `EPERM` 非特权进程，且某个 ID 不是当前的真实、有效组 ID 或保存的 set-group-ID 之一
+pub fn sys_setresgid(rgid: u32, egid: u32, sgid: u32) -> KResult {
+    local_hart().curr_process().lock_inner_with(|inner| {
+        inner
+            .cred
+            .set_resgid(optional_id(rgid), optional_id(egid), optional_id(sgid))
+    })?;
+    Ok(0)
+}
+
+/// 获取调用进程的真实、有效用户 ID 和保存的 set-user-ID
+///
+/// 错误：
+/// - `EFAULT` 某个参数指向非法地址
+pub fn sys_getresuid(ruid: UserCheck<u32>, euid: UserCheck<u32>, suid: UserCheck<u32>) -> KResult {
+    let cred = local_hart().curr_process().cred();
+    unsafe { ruid.check_ptr_mut()? }.write(cred.uid);
+    unsafe { euid.check_ptr_mut()? }.write(cred.euid);
+    unsafe { suid.check_ptr_mut()? }.write(cred.suid);
+    Ok(0)
+}
+
+/// 获取调用进程的真实、有效组 ID 和保存的 set-group-ID
+///
+/// 错误：
+/// - `EFAULT` 某个参数指向非法地址
+pub fn sys_getresgid(rgid: UserCheck<u32>, egid: UserCheck<u32>, sgid: UserCheck<u32>) -> KResult {
+    let cred = local_hart().curr_process().cred();
+    unsafe { rgid.check_ptr_mut()? }.write(cred.gid);
+    unsafe { egid.check_ptr_mut()? }.write(cred.egid);
+    unsafe { sgid.check_ptr_mut()? }.write(cred.sgid);
+    Ok(0)
+}
+
+/// 获取调用进程的附加组，返回附加组的数量
+///
+/// 参数：
+/// - `size` 为 0 时只返回附加组的数量，不写入 `list`
+/// - `list` 写入附加组 ID 的数组，长度为 `size`
+///
+/// 错误：
+/// - `EFAULT` `list` 指向非法地址
+/// - `EINVAL` `size` 小于附加组的数量
+pub fn sys_getgroups(size: usize, list: Option<UserCheck<[u32]>>) -> KResult {
+    let groups = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.cred.groups.clone());
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if size < groups.len() {
+        return Err(errno::EINVAL);
+    }
+    let list = list.ok_or(errno::EFAULT)?;
+    let mut list = unsafe { list.check_slice_mut()? };
+    for (gid_ptr, &gid) in list.iter_mut().zip(&groups) {
+        gid_ptr.write(gid);
+    }
+    Ok(groups.len() as isize)
+}
+
+/// 设置调用进程的附加组，需要特权
+///
+/// 参数：
+/// - `size` 附加组的数量，为 0 时清空附加组
+/// - `list` 附加组 ID 的数组，长度为 `size`
+///
+/// 错误：
+/// - `EFAULT` `list` 指向非法地址
+/// - `EINVAL` `size` 超过 `NGROUPS_MAX`
+/// - `EPERM` 非特权进程
+pub fn sys_setgroups(size: usize, list: Option<UserCheck<u32>>) -> KResult {
+    if size > NGROUPS_MAX {
+        return Err(errno::EINVAL);
+    }
+    let mut groups = Vec::with_capacity(size);
+    if size > 0 {
+        let mut gid_ptr = list.ok_or(errno::EFAULT)?;
+        for _ in 0..size {
+            groups.push(gid_ptr.check_ptr()?.read());
+            gid_ptr = gid_ptr.add(1).ok_or(errno::EFAULT)?;
+        }
+    }
+    local_hart().curr_process().lock_inner_with(|inner| {
+        if !inner.cred.is_privileged() {
+            return Err(errno::EPERM);
+        }
+        inner.cred.groups = groups;
+        Ok(())
+    })?;
+    Ok(0)
+}
+
+/// 获取资源限制，同 `sys_prlimit64(0, resource, NULL, rlim)`
+pub fn sys_getrlimit(resource: u32, rlim: UserCheck<RLimit>) -> KResult {
+    sys_prlimit64(0, resource, None, Some(rlim))
+}
+
+/// 设置资源限制，同 `sys_prlimit64(0, resource, rlim, NULL)`
+pub fn sys_setrlimit(resource: u32, rlim: UserCheck<RLimit>) -> KResult {
+    sys_prlimit64(0, resource, Some(rlim), None)
+}
+
+/// 获取并设置进程的资源限制。成功返回 0
+///
+/// 参数：
+/// - `pid` 目标进程，为 0 表示调用进程
+/// - `resource` 资源种类，即 `RLIMIT_*`
+/// - `new_limit` 如果非 NULL，则将资源限制设置为它
+/// - `old_limit` 如果非 NULL，则写入原来的资源限制
+///
+/// 错误：
+/// - `EINVAL` `resource` 非法，或者新的软上限超过了硬上限
+/// - `EFAULT` `new_limit` 或 `old_limit` 指向非法地址
+/// - `ESRCH` 找不到 `pid` 对应的进程
+/// - `EPERM` 非特权进程试图提高硬上限，或者无权操作目标进程
+pub fn sys_prlimit64(
+    pid: usize,
+    resource: u32,
+    new_limit: Option<UserCheck<RLimit>>,
+    old_limit: Option<UserCheck<RLimit>>,
+) -> KResult {
+    if resource as usize >= RLIM_NLIMITS {
+        return Err(errno::EINVAL);
+    }
+    let new_limit = match new_limit {
+        Some(new_limit) => Some(new_limit.check_ptr()?.read()),
+        None => None,
+    };
+    let old_limit = match old_limit {
+        Some(old_limit) => Some(unsafe { old_limit.check_ptr_mut()? }),
+        None => None,
+    };
+
+    let target = target_process(pid)?;
+    let cred = local_hart().curr_process().cred();
+    if target.pid() != local_hart().curr_process().pid() && !cred.can_prlimit(&target.cred()) {
+        return Err(errno::EPERM);
+    }
+    let old = target.lock_inner_with(|inner| {
+        let old = inner.rlimit(resource);
+        if let Some(new) = new_limit {
+            if new.rlim_curr > new.rlim_max {
+                return Err(errno::EINVAL);
+            }
+            if new.rlim_max > old.rlim_max && !cred.is_privileged() {
+                return Err(errno::EPERM);
+            }
+            debug!("set rlimit {resource} to {new:?}");
+            inner.set_rlimit(resource, new);
+        }
+        Ok(old)
+    })?;
+    if let Some(old_limit) = old_limit {
+        old_limit.write(old);
+    }
+    Ok(0)
 }
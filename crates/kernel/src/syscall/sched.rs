@@ -0,0 +1,361 @@
+use alloc::{vec, vec::Vec};
+use core::mem;
+
+use defines::{
+    error::{errno, KResult},
+    resource::{RLIMIT_NICE, RLIMIT_RTPRIO},
+    sched::{
+        SchedParam, MAX_NICE, MAX_RT_PRIO, MIN_NICE, MIN_RT_PRIO, PRIO_PGRP, PRIO_PROCESS,
+        PRIO_USER, SCHED_RESET_ON_FORK,
+    },
+};
+use triomphe::Arc;
+
+use crate::{
+    executor::{self, SchedPolicy, ALL_HARTS},
+    hart::{self, local_hart},
+    memory::UserCheck,
+    process::{self, Credentials},
+    thread::Thread,
+};
+
+/// 根据 `tid` 找到目标线程，`tid` 为 0 表示调用线程
+fn target_thread(tid: usize) -> KResult<Arc<Thread>> {
+    if tid == 0 {
+        Ok(Arc::clone(&local_hart().curr_thread_arc()))
+    } else {
+        process::find_thread(tid).ok_or(errno::ESRCH)
+    }
+}
+
+/// 找到 `sys_setpriority` 和 `sys_getpriority` 的目标线程
+fn priority_targets(which: u32, who: usize) -> KResult<Vec<Arc<Thread>>> {
+    let processes = match which {
+        PRIO_PROCESS => return Ok(vec![target_thread(who)?]),
+        PRIO_PGRP => {
+            let pgid = if who == 0 {
+                local_hart()
+                    .curr_process()
+                    .lock_inner_with(|inner| inner.pgid)
+            } else {
+                who
+            };
+            process::processes_in_group(pgid)
+        }
+        PRIO_USER => {
+            let uid = if who == 0 {
+                local_hart().curr_process().cred().uid
+            } else {
+                who as u32
+            };
+            process::all_processes()
+                .into_iter()
+                .filter(|process| process.cred().uid == uid)
+                .collect()
+        }
+        _ => return Err(errno::EINVAL),
+    };
+    let threads: Vec<_> = processes
+        .iter()
+        .flat_map(|process| {
+            process.lock_inner_with(|inner| inner.threads.values().cloned().collect::<Vec<_>>())
+        })
+        .collect();
+    if threads.is_empty() {
+        return Err(errno::ESRCH);
+    }
+    Ok(threads)
+}
+
+/// 检查调用者是否有权限修改 `target` 的调度参数
+fn check_sched_permission(cred: &Credentials, target: &Thread) -> KResult<()> {
+    if cred.can_set_sched(&target.process.cred()) {
+        Ok(())
+    } else {
+        Err(errno::EPERM)
+    }
+}
+
+/// 设置 nice 值。成功返回 0
+///
+/// 参数：
+/// - `which` 目标的种类，即 `PRIO_*`
+/// - `who` 目标的 id，根据 `which` 分别是 tid、进程组号或用户 ID。为 0 表示调用者自己
+/// - `prio` 新的 nice 值，超出 -20..=19 的会被截断
+///
+/// 错误：
+/// - `EINVAL` `which` 非法
+/// - `ESRCH` 找不到目标
+/// - `EPERM` 无权修改目标的调度参数
+/// - `EACCES` 非特权进程试图将 nice 值降低到 `RLIMIT_NICE` 允许的范围之外
+pub fn sys_setpriority(which: u32, who: usize, prio: i32) -> KResult {
+    let nice = prio.clamp(MIN_NICE as i32, MAX_NICE as i32) as i8;
+    let targets = priority_targets(which, who)?;
+    let cred = local_hart().curr_process().cred();
+    // 和 Linux 一样，对每个目标分别尝试，返回最后一个错误
+    let mut ret = Ok(0);
+    for thread in targets {
+        if let Err(e) = check_sched_permission(&cred, &thread) {
+            ret = Err(e);
+            continue;
+        }
+        if nice < thread.sched.nice() && !cred.is_privileged() {
+            let nice_limit = thread
+                .process
+                .lock_inner_with(|inner| inner.rlimit(RLIMIT_NICE).rlim_curr);
+            if ((20 - nice) as usize) > nice_limit {
+                ret = Err(errno::EACCES);
+                continue;
+            }
+        }
+        thread.sched.set_nice(nice);
+    }
+    ret
+}
+
+/// 获取 nice 值。为了避免返回负数，返回的是 `20 - nice`，即 1..=40，由用户库转换回 nice 值。
+/// 有多个目标时返回其中最高的优先级
+///
+/// 参数：
+/// - `which` 目标的种类，即 `PRIO_*`
+/// - `who` 目标的 id，根据 `which` 分别是 tid、进程组号或用户 ID。为 0 表示调用者自己
+///
+/// 错误：
+/// - `EINVAL` `which` 非法
+/// - `ESRCH` 找不到目标
+pub fn sys_getpriority(which: u32, who: usize) -> KResult {
+    let targets = priority_targets(which, who)?;
+    let min_nice = targets
+        .iter()
+        .map(|thread| thread.sched.nice())
+        .min()
+        .unwrap_or(0);
+    Ok(20 - min_nice as isize)
+}
+
+/// 设置线程的调度策略和实时优先级。成功返回 0
+///
+/// 参数：
+/// - `tid` 目标线程，为 0 表示调用线程
+/// - `policy` 调度策略，即 `SCHED_*`，可以按位或上 `SCHED_RESET_ON_FORK`
+/// - `param` 调度参数。实时策略的优先级为 1..=99，`SCHED_OTHER` 的优先级必须为 0
+///
+/// 错误：
+/// - `EINVAL` 策略非法，或者优先级与策略不匹配
+/// - `EFAULT` `param` 指向非法地址
+/// - `ESRCH` 找不到目标线程
+/// - `EPERM` 无权修改目标的调度参数，或者非特权进程试图超出 `RLIMIT_RTPRIO` 的限制、
+///   清除 `SCHED_RESET_ON_FORK`
+pub fn sys_sched_setscheduler(tid: usize, policy: u32, param: UserCheck<SchedParam>) -> KResult {
+    let reset_on_fork = policy & SCHED_RESET_ON_FORK != 0;
+    let policy = SchedPolicy::from_user(policy & !SCHED_RESET_ON_FORK).ok_or(errno::EINVAL)?;
+    let param = param.check_ptr()?.read();
+    let thread = target_thread(tid)?;
+    set_scheduler(&thread, policy, param.sched_priority, reset_on_fork)?;
+    Ok(0)
+}
+
+/// 获取线程的调度策略。如果设置了 `SCHED_RESET_ON_FORK`，返回值会按位或上它
+///
+/// 参数：
+/// - `tid` 目标线程，为 0 表示调用线程
+///
+/// 错误：
+/// - `ESRCH` 找不到目标线程
+pub fn sys_sched_getscheduler(tid: usize) -> KResult {
+    let thread = target_thread(tid)?;
+    let mut policy = thread.sched.policy().to_user();
+    if thread.sched.reset_on_fork() {
+        policy |= SCHED_RESET_ON_FORK;
+    }
+    Ok(policy as isize)
+}
+
+/// 只设置线程的实时优先级，调度策略不变。成功返回 0
+///
+/// 参数：
+/// - `tid` 目标线程，为 0 表示调用线程
+/// - `param` 调度参数，优先级需要与线程当前的调度策略匹配
+///
+/// 错误：
+/// - `EINVAL` 优先级与调度策略不匹配
+/// - `EFAULT` `param` 指向非法地址
+/// - `ESRCH` 找不到目标线程
+/// - `EPERM` 同 `sys_sched_setscheduler`
+pub fn sys_sched_setparam(tid: usize, param: UserCheck<SchedParam>) -> KResult {
+    let param = param.check_ptr()?.read();
+    let thread = target_thread(tid)?;
+    let policy = thread.sched.policy();
+    let reset_on_fork = thread.sched.reset_on_fork();
+    set_scheduler(&thread, policy, param.sched_priority, reset_on_fork)?;
+    Ok(0)
+}
+
+/// 获取线程的实时优先级。成功返回 0
+///
+/// 参数：
+/// - `tid` 目标线程，为 0 表示调用线程
+/// - `param` 写入调度参数
+///
+/// 错误：
+/// - `EFAULT` `param` 指向非法地址
+/// - `ESRCH` 找不到目标线程
+pub fn sys_sched_getparam(tid: usize, param: UserCheck<SchedParam>) -> KResult {
+    let param = unsafe { param.check_ptr_mut()? };
+    let thread = target_thread(tid)?;
+    param.write(SchedParam {
+        sched_priority: thread.sched.rt_priority() as i32,
+    });
+    Ok(0)
+}
+
+/// 检查权限和参数并设置线程的调度策略
+fn set_scheduler(
+    thread: &Thread,
+    policy: SchedPolicy,
+    priority: i32,
+    reset_on_fork: bool,
+) -> KResult<()> {
+    let valid_priority = if policy.is_realtime() {
+        (MIN_RT_PRIO as i32..=MAX_RT_PRIO as i32).contains(&priority)
+    } else {
+        priority == 0
+    };
+    if !valid_priority {
+        return Err(errno::EINVAL);
+    }
+    let priority = priority as u8;
+
+    let cred = local_hart().curr_process().cred();
+    check_sched_permission(&cred, thread)?;
+    if !cred.is_privileged() {
+        let sched = &thread.sched;
+        // 非特权进程只能在 `RLIMIT_RTPRIO` 的范围内切换到实时策略或提高实时优先级
+        if policy.is_realtime() {
+            let rtprio_limit = thread
+                .process
+                .lock_inner_with(|inner| inner.rlimit(RLIMIT_RTPRIO).rlim_curr);
+            if policy != sched.policy() && rtprio_limit == 0 {
+                return Err(errno::EPERM);
+            }
+            if priority > sched.rt_priority() && priority as usize > rtprio_limit {
+                return Err(errno::EPERM);
+            }
+        }
+        if sched.reset_on_fork() && !reset_on_fork {
+            return Err(errno::EPERM);
+        }
+    }
+    debug!(
+        "set scheduler of thread {} to {policy:?}, priority {priority}",
+        thread.tid()
+    );
+    thread.sched.set_policy(policy, priority, reset_on_fork);
+    Ok(())
+}
+
+/// 获取调度策略的最高优先级
+///
+/// 错误：
+/// - `EINVAL` 策略非法
+pub fn sys_sched_get_priority_max(policy: u32) -> KResult {
+    let policy = SchedPolicy::from_user(policy).ok_or(errno::EINVAL)?;
+    Ok(if policy.is_realtime() {
+        MAX_RT_PRIO as isize
+    } else {
+        0
+    })
+}
+
+/// 获取调度策略的最低优先级
+///
+/// 错误：
+/// - `EINVAL` 策略非法
+pub fn sys_sched_get_priority_min(policy: u32) -> KResult {
+    let policy = SchedPolicy::from_user(policy).ok_or(errno::EINVAL)?;
+    Ok(if policy.is_realtime() {
+        MIN_RT_PRIO as isize
+    } else {
+        0
+    })
+}
+
+/// 设置线程的亲和性掩码，即允许运行在哪些 hart 上。成功返回 0
+///
+/// 参数：
+/// - `tid` 目标线程，为 0 表示调用线程
+/// - `mask` 亲和性掩码，第 i 位为 1 表示允许在 hart i 上运行。超出 hart 数量的位会被忽略
+///
+/// 错误：
+/// - `EINVAL` 掩码中不包含任何已启动的 hart
+/// - `EFAULT` `mask` 指向非法地址
+/// - `ESRCH` 找不到目标线程
+/// - `EPERM` 无权修改目标的调度参数
+pub async fn sys_sched_setaffinity(tid: usize, mask: UserCheck<[u8]>) -> KResult {
+    let affinity = {
+        let mask = mask.check_slice()?;
+        let mut bytes = [0; mem::size_of::<usize>()];
+        let len = mask.len().min(bytes.len());
+        bytes[..len].copy_from_slice(&mask[..len]);
+        usize::from_le_bytes(bytes) & ALL_HARTS & hart::online_harts()
+    };
+    if affinity == 0 {
+        return Err(errno::EINVAL);
+    }
+    let thread = target_thread(tid)?;
+    let cred = local_hart().curr_process().cred();
+    check_sched_permission(&cred, &thread)?;
+    thread.sched.set_affinity(affinity);
+    if thread.tid() == local_hart().curr_thread().tid() {
+        // 当前 hart 不再被允许的话就让出，重新入队时会被放到允许的 hart 上
+        if !thread.sched.allows(local_hart().hart_id()) {
+            executor::yield_now().await;
+        }
+    } else {
+        // 目标线程在排队的话，需要离开不再允许的 hart 的队列。
+        // 正在运行的话，则在下次让出时重新入队到允许的 hart 上
+        executor::migrate(&thread.sched);
+    }
+    Ok(0)
+}
+
+/// 获取线程的亲和性掩码。成功返回写入的字节数
+///
+/// 参数：
+/// - `tid` 目标线程，为 0 表示调用线程
+/// - `mask` 写入亲和性掩码，长度需要是 `usize` 大小的整数倍且不小于它
+///
+/// 错误：
+/// - `EINVAL` `mask` 的长度不合法
+/// - `EFAULT` `mask` 指向非法地址
+/// - `ESRCH` 找不到目标线程
+pub fn sys_sched_getaffinity(tid: usize, mask: UserCheck<[u8]>) -> KResult {
+    const MASK_SIZE: usize = mem::size_of::<usize>();
+    if mask.len() < MASK_SIZE || mask.len() % MASK_SIZE != 0 {
+        return Err(errno::EINVAL);
+    }
+    let mask = mask.slice(0..MASK_SIZE).ok_or(errno::EINVAL)?;
+    let mut mask = unsafe { mask.check_slice_mut()? };
+    let thread = target_thread(tid)?;
+    mask.as_bytes_mut()
+        .copy_from_slice(&thread.sched.affinity().to_le_bytes());
+    Ok(MASK_SIZE as isize)
+}
+
+/// 获取调用线程当前所在的 hart。成功返回 0
+///
+/// 参数：
+/// - `cpu` 如果非 NULL，写入 hart 号
+/// - `node` 如果非 NULL，写入 NUMA 节点号，总是为 0
+///
+/// 错误：
+/// - `EFAULT` `cpu` 或 `node` 指向非法地址
+pub fn sys_getcpu(cpu: Option<UserCheck<u32>>, node: Option<UserCheck<u32>>) -> KResult {
+    if let Some(cpu) = cpu {
+        unsafe { cpu.check_ptr_mut()? }.write(local_hart().hart_id() as u32);
+    }
+    if let Some(node) = node {
+        unsafe { node.check_ptr_mut()? }.write(0);
+    }
+    Ok(0)
+}
@@ -1,13 +1,28 @@
+use alloc::{vec, vec::Vec};
+use core::{pin::pin, time::Duration};
+
+use common::config::SIGNAL_TRAMPOLINE;
 use defines::{
     error::{errno, KResult},
-    signal::{KSignalAction, SignalActionFlags, SIGSET_SIZE_BYTES},
+    fs::OpenFlags,
+    misc::TimeSpec,
+    signal::{
+        KSignalAction, SigInfo, SignalActionFlags, SignalStack, MINSIGSTKSZ, SIGSET_SIZE_BYTES,
+        SI_TKILL, SI_USER, SS_AUTODISARM, SS_DISABLE, SS_ONSTACK,
+    },
 };
+use event_listener::listener;
+use futures::future;
+use triomphe::Arc;
 
 use crate::{
+    fs::{File, FileDescriptor, SignalFd},
     hart::local_hart,
     memory::UserCheck,
-    process::exit_process,
-    signal::{KSignalSet, SigProcMaskHow, Signal, SignalContext},
+    process::{self, exit_process, Process},
+    signal::{self, KSigInfo, KSignalSet, SigProcMaskHow, Signal, SignalFrame, SignalStackExt},
+    thread::Thread,
+    time,
 };
 
 /// 设置当前**进程**在收到特定信号时的行为
@@ -42,12 +57,12 @@ pub fn sys_rt_sigaction(
     }
 
     if let Some(new_act) = new_act {
-        let act = new_act.check_ptr()?.read();
+        let mut act = new_act.check_ptr()?.read();
         if !act.flags.contains(SignalActionFlags::SA_RESTORER) {
             // `SA_RESTORER` 表示传入的 `restore` 字段是有用的
             // 一般而言这个字段由 libc 填写，用于 signal handler 执行结束之后调用 `sys_sigreturn`
-            // 如果没有填写，则 os 需要自己手动做一个 trampoline
-            todo!("[low] sig trampoline does not impl")
+            // 如果没有填写，则使用内核映射的 trampoline
+            act.restorer = SIGNAL_TRAMPOLINE;
         }
         local_hart().curr_process().lock_inner_with(|inner| {
             inner.signal_handlers.action_mut(signal).clone_from(&act);
@@ -90,7 +105,9 @@ pub fn sys_rt_sigprocmask(
 
     if let Some(new_set) = new_set {
         debug!("write signal mask with how = {how:?}");
-        let new_set = KSignalSet::from_user(new_set.check_ptr()?.read());
+        let mut new_set = KSignalSet::from_user(new_set.check_ptr()?.read());
+        // `SIGKILL` 和 `SIGSTOP` 不能被屏蔽，静默地忽略即可
+        new_set.remove(KSignalSet::SIGKILL | KSignalSet::SIGSTOP);
         local_hart()
             .curr_thread()
             .lock_inner_with(|inner| match how {
@@ -103,11 +120,176 @@ pub fn sys_rt_sigprocmask(
     Ok(0)
 }
 
+/// 临时将**线程**的信号掩码替换为 `mask` 并挂起，直到有信号调用了 signal handler 或者终止了进程。
+/// 原来的掩码会在返回用户态前恢复
+///
+/// 参数：
+/// - `mask` 挂起期间使用的信号掩码
+/// - `set_size` 信号集的字节数
+///
+/// 错误：
+/// - `EFAULT` `mask` 指向非法地址
+/// - `EINVAL` 内核不支持 `set_size`
+/// - `EINTR` 被信号打断，这是唯一的返回方式
+pub async fn sys_rt_sigsuspend(mask: UserCheck<u64>, set_size: usize) -> KResult {
+    if set_size > SIGSET_SIZE_BYTES {
+        return Err(errno::EINVAL);
+    }
+    let mut mask = KSignalSet::from_user(mask.check_ptr()?.read());
+    mask.remove(KSignalSet::SIGKILL | KSignalSet::SIGSTOP);
+    local_hart().curr_thread().lock_inner_with(|inner| {
+        let old_mask = core::mem::replace(&mut inner.signal_mask, mask);
+        inner.saved_signal_mask = Some(old_mask);
+    });
+    // 只可能因为信号而返回。没有调用 signal handler 的话（比如信号使进程停止又继续），则重启并继续挂起
+    let _ = signal::interruptible(future::pending::<()>()).await;
+    Err(errno::ERESTARTNOHAND)
+}
+
+/// 获取**线程**被屏蔽而处于待处理状态的信号集
+///
+/// 参数：
+/// - `set` 写入待处理的信号集
+/// - `set_size` 信号集的字节数
+///
+/// 错误：
+/// - `EFAULT` `set` 指向非法地址
+/// - `EINVAL` 内核不支持 `set_size`
+pub fn sys_rt_sigpending(set: UserCheck<u64>, set_size: usize) -> KResult {
+    if set_size > SIGSET_SIZE_BYTES {
+        return Err(errno::EINVAL);
+    }
+    let set_ptr = unsafe { set.check_ptr_mut()? };
+    let pending = local_hart()
+        .curr_thread()
+        .lock_inner_with(|inner| inner.pending_signal.set().intersection(inner.signal_mask));
+    set_ptr.write(pending.to_user());
+    Ok(0)
+}
+
+/// 同步地等待 `set` 中的某个信号，将其从待处理信号中取出，不会调用 signal handler。
+/// 成功时返回信号编号
+///
+/// 参数：
+/// - `set` 要等待的信号集。通常这些信号应当已经被屏蔽
+/// - `info` 如果非 NULL，则写入取出的信号的信息
+/// - `timeout` 如果非 NULL，则最多等待这么长时间。为 0 时只检查而不等待
+/// - `set_size` 信号集的字节数
+///
+/// 错误：
+/// - `EFAULT` `set`、`info` 或 `timeout` 指向非法地址
+/// - `EINVAL` `timeout` 非法或者内核不支持 `set_size`
+/// - `EAGAIN` 超时
+/// - `EINTR` 被不在 `set` 中的信号打断
+pub async fn sys_rt_sigtimedwait(
+    set: UserCheck<u64>,
+    info: Option<UserCheck<SigInfo>>,
+    timeout: Option<UserCheck<TimeSpec>>,
+    set_size: usize,
+) -> KResult {
+    if set_size > SIGSET_SIZE_BYTES {
+        return Err(errno::EINVAL);
+    }
+    let mut set = KSignalSet::from_user(set.check_ptr()?.read());
+    set.remove(KSignalSet::SIGKILL | KSignalSet::SIGSTOP);
+    let deadline = match timeout {
+        Some(timeout) => {
+            let timeout = Duration::try_from(timeout.check_ptr()?.read())?;
+            Some(time::curr_time() + timeout)
+        }
+        None => None,
+    };
+
+    let thread = Arc::clone(&local_hart().curr_thread_arc());
+    let ret = loop {
+        // 先注册监听再检查，以免错过通知
+        listener!(thread.signal_event => listener);
+        let (ret, interrupted) = thread.lock_inner_with(|inner| {
+            let ret = inner.pending_signal.pop(set.complement());
+            // 在 `set` 中的信号即使被屏蔽也会被取出；其余未被屏蔽的信号则会打断等待
+            let interrupted = !inner
+                .pending_signal
+                .set()
+                .difference(inner.signal_mask | set)
+                .is_empty();
+            (ret, interrupted)
+        });
+        if let Some(ret) = ret {
+            break ret;
+        }
+        if interrupted || thread.is_exiting() {
+            return Err(errno::EINTR);
+        }
+        match deadline {
+            Some(deadline) => {
+                if time::curr_time() >= deadline {
+                    return Err(errno::EAGAIN);
+                }
+                let sleep = pin!(time::sleep_until(deadline));
+                // 超时或者收到通知，都回到循环开头重新检查
+                future::select(sleep, listener).await;
+            }
+            None => listener.await,
+        }
+    };
+
+    if let Some(info) = info {
+        unsafe { info.check_ptr_mut()? }.write(ret.to_user());
+    }
+    Ok(ret.signal.to_user() as isize)
+}
+
+/// 创建或修改一个 signalfd，通过读取它可以取出调用线程中匹配 `mask` 的待处理信号。返回其文件描述符
+///
+/// 参数：
+/// - `fd` 为 -1 时创建新的 signalfd，否则修改已有的 signalfd 的 `mask`
+/// - `mask` 要接收的信号集。通常这些信号应当已经被屏蔽，否则可能会先被正常地处理掉
+/// - `set_size` 信号集的字节数
+/// - `flags` 可以包含 `SFD_NONBLOCK` 和 `SFD_CLOEXEC`，它们的值和 [`OpenFlags`] 中对应的标志相同
+///
+/// 错误：
+/// - `EBADF` `fd` 不是有效的文件描述符
+/// - `EFAULT` `mask` 指向非法地址
+/// - `EINVAL` `fd` 不是 signalfd，或者 `flags` 非法，或者内核不支持 `set_size`
+/// - `EMFILE` 文件描述符数量达到上限
+pub fn sys_signalfd4(fd: i32, mask: UserCheck<u64>, set_size: usize, flags: u32) -> KResult {
+    if set_size != SIGSET_SIZE_BYTES {
+        return Err(errno::EINVAL);
+    }
+    let flags = OpenFlags::from_bits(flags)
+        .filter(|flags| (OpenFlags::NONBLOCK | OpenFlags::CLOEXEC).contains(*flags))
+        .ok_or(errno::EINVAL)?;
+    let mut mask = KSignalSet::from_user(mask.check_ptr()?.read());
+    // `SIGKILL` 和 `SIGSTOP` 不能通过 signalfd 接收，静默地忽略即可
+    mask.remove(KSignalSet::SIGKILL | KSignalSet::SIGSTOP);
+
+    let process = local_hart().curr_process();
+    let mut inner = process.lock_inner();
+    if fd == -1 {
+        let signalfd = File::SignalFd(Arc::new(SignalFd::new(mask)));
+        let desc = FileDescriptor::new(signalfd, flags | OpenFlags::RDONLY);
+        let new_fd = inner.fd_table.add(desc).ok_or(errno::EMFILE)?;
+        return Ok(new_fd as isize);
+    }
+    let desc = usize::try_from(fd)
+        .ok()
+        .and_then(|fd| inner.fd_table.get(fd))
+        .ok_or(errno::EBADF)?;
+    let File::SignalFd(signalfd) = &**desc else {
+        return Err(errno::EINVAL);
+    };
+    signalfd.set_mask(mask);
+    Ok(fd as isize)
+}
+
+/// 从 signal handler 返回，恢复用户栈上 [`SignalFrame`] 中保存的上下文和信号掩码。
+///
+/// handler 可能修改了 `ucontext` 中的内容，恢复时以修改后的为准
 pub fn sys_rt_sigreturn() -> KResult {
     debug!("sigreturn called");
     let thread = local_hart().curr_thread();
     let sp = thread.lock_inner_with(|inner| inner.trap_context.sp());
-    let Ok(old_ctx) = UserCheck::new(sp as *mut SignalContext)
+    let Ok(frame) = UserCheck::new(sp as *mut SignalFrame)
         .ok_or(errno::EINVAL)?
         .check_ptr()
     else {
@@ -115,12 +297,293 @@ pub fn sys_rt_sigreturn() -> KResult {
         exit_process(&thread.process, -10);
         return Err(errno::BREAK);
     };
-    let old_ctx = old_ctx.read();
+    let ucontext = &frame.read().ucontext;
+    let mut mask = KSignalSet::from_user(ucontext.uc_sigmask);
+    mask.remove(KSignalSet::SIGKILL | KSignalSet::SIGSTOP);
+    let regs = &ucontext.uc_mcontext.sc_regs;
 
     thread.lock_inner_with(|inner| {
-        inner.signal_mask = old_ctx.old_mask;
-        inner.trap_context = old_ctx.old_trap_context;
+        inner.signal_mask = mask;
+        inner.trap_context.sepc = regs[0];
+        inner.trap_context.user_regs.copy_from_slice(&regs[1..]);
     });
+    // 恢复信号栈，主要是为了 `SS_AUTODISARM`。和 linux 一样，失败了就忽略
+    let _ = set_signal_stack(&thread, &ucontext.uc_stack);
 
+    // 返回值会写入 a0，因此返回恢复的 a0 以免将其覆盖
+    Ok(regs[10] as isize)
+}
+
+/// 设置或获取当前线程的信号栈。signal handler 若指定了 `SA_ONSTACK`，则会在信号栈上执行
+///
+/// 参数：
+/// - `ss` 如果非 NULL，则设置新的信号栈
+///     - `ss_flags` 为 `SS_DISABLE` 时禁用信号栈，为 0 或 `SS_ONSTACK` 时启用，此外还可以附加
+///       `SS_AUTODISARM`
+/// - `old_ss` 如果非 NULL，则写入原来的信号栈。其 `ss_flags` 中的 `SS_ONSTACK` 表示当前正在信号栈上执行
+///
+/// 错误：
+/// - `EFAULT` `ss` 或 `old_ss` 指向非法地址
+/// - `EINVAL` `ss_flags` 非法
+/// - `ENOMEM` 信号栈小于 `MINSIGSTKSZ`
+/// - `EPERM` 当前正在信号栈上执行，不能修改信号栈
+pub fn sys_sigaltstack(
+    ss: Option<UserCheck<SignalStack>>,
+    old_ss: Option<UserCheck<SignalStack>>,
+) -> KResult {
+    let thread = local_hart().curr_thread();
+    if let Some(old_ss) = old_ss {
+        let old_ss_ptr = unsafe { old_ss.check_ptr_mut()? };
+        let old =
+            thread.lock_inner_with(|inner| inner.signal_stack.to_user(inner.trap_context.sp()));
+        old_ss_ptr.write(old);
+    }
+    if let Some(ss) = ss {
+        let ss = ss.check_ptr()?.read();
+        set_signal_stack(&thread, &ss)?;
+    }
+    Ok(0)
+}
+
+/// 检查并设置线程的信号栈
+///
+/// 错误：
+/// - `EINVAL` `ss_flags` 非法
+/// - `ENOMEM` 信号栈小于 `MINSIGSTKSZ`
+/// - `EPERM` 当前正在信号栈上执行
+fn set_signal_stack(thread: &Thread, ss: &SignalStack) -> KResult<()> {
+    let new = match ss.ss_flags & !SS_AUTODISARM {
+        SS_DISABLE => SignalStack::default(),
+        0 | SS_ONSTACK => {
+            if ss.ss_size < MINSIGSTKSZ {
+                return Err(errno::ENOMEM);
+            }
+            SignalStack {
+                ss_sp: ss.ss_sp,
+                ss_flags: ss.ss_flags & SS_AUTODISARM,
+                ss_size: ss.ss_size,
+            }
+        }
+        _ => return Err(errno::EINVAL),
+    };
+    thread.lock_inner_with(|inner| {
+        if inner.signal_stack.contains(inner.trap_context.sp()) {
+            return Err(errno::EPERM);
+        }
+        debug!("set signal stack to {new:x?}");
+        inner.signal_stack = new;
+        Ok(())
+    })
+}
+
+/// 解析用户传入的信号。`signum` 为 0 时返回 `None`，表示只检查目标而不发送信号
+fn parse_signum(signum: usize) -> KResult<Option<Signal>> {
+    if signum == 0 {
+        return Ok(None);
+    }
+    let signal = u8::try_from(signum)
+        .ok()
+        .and_then(Signal::from_user)
+        .ok_or(errno::EINVAL)?;
+    Ok(Some(signal))
+}
+
+/// 向进程或进程组发送信号
+///
+/// 参数：
+/// - `pid` 指定接收信号的进程
+///     - `pid` > 0，则发送给 pid 为 `pid` 的进程
+///     - `pid` == 0，则发送给与调用进程同一进程组的所有进程
+///     - `pid` == -1，则发送给除了 INITPROC 和调用进程之外的所有进程
+///     - `pid` < -1，则发送给 pgid 为 `pid` 绝对值的进程组中的所有进程
+/// - `signum` 要发送的信号。为 0 则不发送信号，但依然会进行错误检查，可用于检查进程是否存在
+///
+/// 错误：
+/// - `EINVAL` `signum` 非法
+/// - `ESRCH` 找不到目标进程或进程组
+/// - `EPERM` 没有权限向任何一个目标进程发送信号
+pub fn sys_kill(pid: isize, signum: usize) -> KResult {
+    let signal = parse_signum(signum)?;
+    let curr_pid = local_hart().curr_process().pid();
+    let targets = match pid {
+        0 => {
+            let pgid = local_hart()
+                .curr_process()
+                .lock_inner_with(|inner| inner.pgid);
+            process::processes_in_group(pgid)
+        }
+        -1 => process::all_processes()
+            .into_iter()
+            .filter(|process| process.pid() != 1 && process.pid() != curr_pid)
+            .collect(),
+        pid if pid < -1 => process::processes_in_group(pid.unsigned_abs()),
+        pid => vec![process::find_process(pid as usize).ok_or(errno::ESRCH)?],
+    };
+    if targets.is_empty() {
+        return Err(errno::ESRCH);
+    }
+    let targets: Vec<_> = targets
+        .into_iter()
+        .filter(|target| can_kill(signal, target))
+        .collect();
+    if targets.is_empty() {
+        return Err(errno::EPERM);
+    }
+    debug!("send {signal:?} to {} process(es)", targets.len());
+    if let Some(signal) = signal {
+        for target in targets {
+            target.receive_signal(KSigInfo::user(signal, SI_USER));
+        }
+    }
+    Ok(0)
+}
+
+/// 向指定线程发送信号。已被 [`sys_tgkill`] 取代，因为 tid 可能被回收复用
+///
+/// 参数：
+/// - `tid` 目标线程的 tid
+/// - `signum` 要发送的信号，为 0 则仅作检查
+///
+/// 错误：
+/// - `EINVAL` `tid` 或 `signum` 非法
+/// - `ESRCH` 找不到目标线程
+/// - `EPERM` 没有权限向目标线程发送信号
+pub fn sys_tkill(tid: isize, signum: usize) -> KResult {
+    if tid <= 0 {
+        return Err(errno::EINVAL);
+    }
+    let signal = parse_signum(signum)?;
+    let thread = process::find_thread(tid as usize).ok_or(errno::ESRCH)?;
+    if !can_kill(signal, &thread.process) {
+        return Err(errno::EPERM);
+    }
+    if let Some(signal) = signal {
+        thread
+            .process
+            .receive_thread_signal(&thread, KSigInfo::user(signal, SI_TKILL));
+    }
+    Ok(0)
+}
+
+/// 向线程组 `tgid` （即 pid 为 `tgid` 的进程）中的线程 `tid` 发送信号
+///
+/// 参数：
+/// - `tgid` 目标线程所属进程的 pid
+/// - `tid` 目标线程的 tid
+/// - `signum` 要发送的信号，为 0 则仅作检查
+///
+/// 错误：
+/// - `EINVAL` `tgid`、`tid` 或 `signum` 非法
+/// - `ESRCH` 找不到目标线程，或目标线程不属于该线程组
+/// - `EPERM` 没有权限向目标线程发送信号
+pub fn sys_tgkill(tgid: isize, tid: isize, signum: usize) -> KResult {
+    if tgid <= 0 || tid <= 0 {
+        return Err(errno::EINVAL);
+    }
+    let signal = parse_signum(signum)?;
+    let process = process::find_process(tgid as usize).ok_or(errno::ESRCH)?;
+    let thread = process
+        .lock_inner_with(|inner| inner.threads.get(&(tid as usize)).cloned())
+        .ok_or(errno::ESRCH)?;
+    if !can_kill(signal, &process) {
+        return Err(errno::EPERM);
+    }
+    if let Some(signal) = signal {
+        process.receive_thread_signal(&thread, KSigInfo::user(signal, SI_TKILL));
+    }
+    Ok(0)
+}
+
+/// 调用进程是否有权限向 `target` 发送信号 `signal`，见 [`process::Credentials::can_signal`]。
+/// 此外，`SIGCONT` 总是可以发送给同一会话中的进程
+fn can_kill(signal: Option<Signal>, target: &Process) -> bool {
+    let (cred, sid) = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| (inner.cred.clone(), inner.sid));
+    target.lock_inner_with(|inner| {
+        cred.can_signal(&inner.cred) || (signal == Some(Signal::SIGCONT) && inner.sid == sid)
+    })
+}
+
+/// 读取并检查用户传入的 `siginfo_t`，用于 [`sys_rt_sigqueueinfo`] 和 [`sys_rt_tgsigqueueinfo`]
+///
+/// 错误：
+/// - `EFAULT` `uinfo` 指向非法地址
+/// - `EPERM` 向其他进程发送时，`si_code` 不能伪装成由内核或 `tkill` 产生
+fn read_user_siginfo(
+    target_pid: usize,
+    signal: Signal,
+    uinfo: UserCheck<SigInfo>,
+) -> KResult<KSigInfo> {
+    let uinfo = uinfo.check_ptr()?.read();
+    let pid = local_hart().curr_process().pid();
+    if (uinfo.si_code >= 0 || uinfo.si_code == SI_TKILL) && target_pid != pid {
+        return Err(errno::EPERM);
+    }
+    let mut info = KSigInfo::user(signal, uinfo.si_code);
+    // 和 linux 一样，信任用户填写的 pid 和 uid
+    info.pid = uinfo.si_pid as usize;
+    info.uid = uinfo.si_uid;
+    info.value = uinfo.si_value;
+    Ok(info)
+}
+
+/// 向进程 `tgid` 发送信号 `signum`，并附带用户指定的 `siginfo_t`。一般用于实现 `sigqueue()`
+///
+/// 参数：
+/// - `tgid` 目标进程的 pid
+/// - `signum` 要发送的信号，为 0 则仅作检查
+/// - `uinfo` 随信号发送的信息，通常 `si_code` 为 `SI_QUEUE`
+///
+/// 错误：
+/// - `EINVAL` `signum` 非法
+/// - `ESRCH` 找不到目标进程
+/// - `EFAULT` `uinfo` 指向非法地址
+/// - `EPERM` 向其他进程发送时，`si_code` 非法，或者没有权限向目标进程发送信号
+pub fn sys_rt_sigqueueinfo(tgid: isize, signum: usize, uinfo: UserCheck<SigInfo>) -> KResult {
+    let signal = parse_signum(signum)?;
+    if tgid <= 0 {
+        return Err(errno::ESRCH);
+    }
+    let process = process::find_process(tgid as usize).ok_or(errno::ESRCH)?;
+    if !can_kill(signal, &process) {
+        return Err(errno::EPERM);
+    }
+    if let Some(signal) = signal {
+        let info = read_user_siginfo(process.pid(), signal, uinfo)?;
+        debug!("queue {signal:?} with code {} to {tgid}", info.code);
+        process.receive_signal(info);
+    }
+    Ok(0)
+}
+
+/// 与 [`sys_rt_sigqueueinfo`] 类似，但发送给进程 `tgid` 中的线程 `tid`
+///
+/// 错误：
+/// - `EINVAL` `tgid`、`tid` 或 `signum` 非法
+/// - `ESRCH` 找不到目标线程，或目标线程不属于该线程组
+/// - `EFAULT` `uinfo` 指向非法地址
+/// - `EPERM` 向其他进程发送时，`si_code` 非法，或者没有权限向目标进程发送信号
+pub fn sys_rt_tgsigqueueinfo(
+    tgid: isize,
+    tid: isize,
+    signum: usize,
+    uinfo: UserCheck<SigInfo>,
+) -> KResult {
+    if tgid <= 0 || tid <= 0 {
+        return Err(errno::EINVAL);
+    }
+    let signal = parse_signum(signum)?;
+    let process = process::find_process(tgid as usize).ok_or(errno::ESRCH)?;
+    let thread = process
+        .lock_inner_with(|inner| inner.threads.get(&(tid as usize)).cloned())
+        .ok_or(errno::ESRCH)?;
+    if !can_kill(signal, &process) {
+        return Err(errno::EPERM);
+    }
+    if let Some(signal) = signal {
+        let info = read_user_siginfo(process.pid(), signal, uinfo)?;
+        process.receive_thread_signal(&thread, info);
+    }
     Ok(0)
 }
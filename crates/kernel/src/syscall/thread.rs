@@ -1,14 +1,122 @@
-use defines::error::KResult;
+use core::{pin::pin, time::Duration};
 
-use crate::hart::local_hart;
+use defines::{
+    error::{errno, KResult},
+    misc::{
+        TimeSpec, FUTEX_BITSET_MATCH_ANY, FUTEX_CLOCK_REALTIME, FUTEX_CMP_REQUEUE,
+        FUTEX_PRIVATE_FLAG, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_WAKE,
+        FUTEX_WAKE_BITSET,
+    },
+};
+use futures::future::{self, Either};
+
+use crate::{hart::local_hart, memory::UserCheck, signal, thread::futex, time};
 
 /// 获取线程 tid。永远成功
 ///
-/// TODO: Linux 手册里说，单线程进程中返回 pid，而多线程进程返回 tid。
-///
-/// 这里一直返回 tid（而且 tid 的定义可能也不同）
+/// tid 与 pid 共用同一个命名空间，主线程的 tid 即为进程的 pid
 ///
 /// <https://man7.org/linux/man-pages/man2/gettid.2.html>
 pub fn sys_gettid() -> KResult {
     Ok(local_hart().curr_thread().tid() as isize)
 }
+
+/// 快速用户空间互斥锁（futex）相关操作。
+///
+/// 参数：
+/// - `uaddr` futex 字的地址，需对齐到 4 字节
+/// - `futex_op` 操作，可以附带 `FUTEX_PRIVATE_FLAG` 和 `FUTEX_CLOCK_REALTIME`
+/// - `val` 含义取决于操作。等待时是期望的值，唤醒时是唤醒的数量
+/// - `timeout` 等待操作中是指向超时时间的指针，为 0 表示不超时；
+///   requeue 操作中则是转移的数量 `val2`
+/// - `uaddr2` requeue 操作中转移到的 futex 字的地址
+/// - `val3` `FUTEX_CMP_REQUEUE` 中期望的值，或 `*_BITSET` 操作中的掩码
+///
+/// 错误：
+/// - `EAGAIN` futex 字的值与期望值不同
+/// - `ETIMEDOUT` 等待超时
+/// - `EINVAL` 地址未对齐、掩码为 0 或超时时间不合法
+/// - `EFAULT` 地址非法
+pub async fn sys_futex(
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> KResult {
+    // futex 以物理地址区分，无论是否私有都一样处理
+    let op = futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+    if futex_op & FUTEX_CLOCK_REALTIME != 0 && op != FUTEX_WAIT && op != FUTEX_WAIT_BITSET {
+        return Err(errno::UNSUPPORTED);
+    }
+    let key = futex::futex_key(uaddr)?;
+    match op {
+        FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+            let bitset = if op == FUTEX_WAIT {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            if bitset == 0 {
+                return Err(errno::EINVAL);
+            }
+            // 等待截止的单调时钟时间
+            let deadline = if let Some(timeout) = UserCheck::new(timeout as *mut TimeSpec) {
+                let timeout = Duration::try_from(timeout.check_ptr()?.read())?;
+                // `FUTEX_WAIT` 的超时是相对时间，`FUTEX_WAIT_BITSET` 则是绝对时间，
+                // 默认基于 `CLOCK_MONOTONIC`，带有 `FUTEX_CLOCK_REALTIME` 时则基于 `CLOCK_REALTIME`
+                if op == FUTEX_WAIT {
+                    Some(time::curr_time().saturating_add(timeout))
+                } else if futex_op & FUTEX_CLOCK_REALTIME != 0 {
+                    Some(time::realtime_to_monotonic(timeout))
+                } else {
+                    Some(timeout)
+                }
+            } else {
+                None
+            };
+
+            let wait = futex::wait(key, val, bitset)?;
+            // 超时或被信号打断后 `FutexWait` 被 drop，会从等待队列中移除
+            if let Some(deadline) = deadline {
+                let sleep = pin!(time::sleep_until(deadline));
+                let wait = future::select(wait, sleep);
+                // 和 nanosleep 一样，有超时的等待在调用了 signal handler 之后不会重启
+                let output = signal::interruptible(wait)
+                    .await
+                    .map_err(|_| errno::ERESTARTNOHAND)?;
+                match output {
+                    Either::Left(((), _)) => Ok(0),
+                    Either::Right(((), _)) => Err(errno::ETIMEDOUT),
+                }
+            } else {
+                signal::interruptible(wait).await?;
+                Ok(0)
+            }
+        }
+        FUTEX_WAKE => Ok(futex::wake(key, val as usize, FUTEX_BITSET_MATCH_ANY) as isize),
+        FUTEX_WAKE_BITSET => {
+            if val3 == 0 {
+                return Err(errno::EINVAL);
+            }
+            Ok(futex::wake(key, val as usize, val3) as isize)
+        }
+        FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+            let new_key = futex::futex_key(uaddr2)?;
+            let expected = (op == FUTEX_CMP_REQUEUE).then_some(val3);
+            let (n_wake, n_requeue) =
+                futex::requeue(key, new_key, val as usize, timeout, expected)?;
+            // `FUTEX_REQUEUE` 只返回唤醒的数量，`FUTEX_CMP_REQUEUE` 则返回唤醒和转移的总数
+            if op == FUTEX_REQUEUE {
+                Ok(n_wake as isize)
+            } else {
+                Ok((n_wake + n_requeue) as isize)
+            }
+        }
+        _ => {
+            error!("unsupported futex op: {futex_op:#x}");
+            Err(errno::UNSUPPORTED)
+        }
+    }
+}
@@ -1,54 +1,159 @@
 use core::time::Duration;
 
-use common::constant::{MICRO_PER_SEC, NANO_PER_SEC};
+use common::constant::MICRO_PER_SEC;
 use defines::{
-    error::KResult,
-    misc::{TimeSpec, TimeVal, Tms},
+    error::{errno, KResult},
+    misc::{
+        Rusage, TimeSpec, TimeVal, Tms, RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD, TIMER_ABSTIME,
+    },
 };
 
-use crate::{memory::UserCheck, time};
+use crate::{hart::local_hart, memory::UserCheck, signal, time};
 
-/// 获取自 Epoch 以来所过的时间（不过目前实现中似乎是自开机或复位以来时间）
+/// 获取自 Epoch 以来所过的时间，即挂钟时间
 ///
 /// 参数：
 /// - `ts` 要设置的时间值
-/// - `tz` 时区结构，但目前已经过时，不考虑
+/// - `tz` 时区结构，已经过时，忽略
 pub fn sys_get_time_of_day(tv: UserCheck<TimeVal>, _tz: usize) -> KResult {
-    // 根据 man 所言，时区参数 `tz` 已经过时了，通常应当是 `NULL`。
-    assert_eq!(_tz, 0);
     let tv = unsafe { tv.check_ptr_mut()? };
-    let us = riscv_time::get_time_us();
-    tv.write(TimeVal {
-        sec: us / MICRO_PER_SEC,
-        usec: us % MICRO_PER_SEC,
-    });
+    tv.write(TimeVal::from(time::realtime()));
+    Ok(0)
+}
+
+/// 设置挂钟时间。成功返回 0
+///
+/// 参数：
+/// - `tv` 新的挂钟时间，为 NULL 时不设置
+/// - `tz` 时区结构，已经过时，忽略
+///
+/// 错误：
+/// - `EFAULT` `tv` 指向非法地址
+/// - `EINVAL` `tv` 非法
+/// - `EPERM` 调用进程没有特权
+pub fn sys_settimeofday(tv: Option<UserCheck<TimeVal>>, _tz: usize) -> KResult {
+    let Some(tv) = tv else {
+        return Ok(0);
+    };
+    let tv = tv.check_ptr()?.read();
+    if tv.usec >= MICRO_PER_SEC {
+        return Err(errno::EINVAL);
+    }
+    if !local_hart().curr_process().cred().is_privileged() {
+        return Err(errno::EPERM);
+    }
+    time::set_realtime(Duration::new(tv.sec as u64, (tv.usec * 1000) as u32));
     Ok(0)
 }
 
 /// 全局时钟，或者说挂钟
-const CLOCK_REALTIME: usize = 0;
+pub(super) const CLOCK_REALTIME: usize = 0;
+/// 单调时钟，自开机以来的时间
+pub(super) const CLOCK_MONOTONIC: usize = 1;
+/// 调用进程的 CPU 时间
+const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+/// 调用线程的 CPU 时间
+const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+const CLOCK_MONOTONIC_RAW: usize = 4;
+const CLOCK_REALTIME_COARSE: usize = 5;
+const CLOCK_MONOTONIC_COARSE: usize = 6;
+pub(super) const CLOCK_BOOTTIME: usize = 7;
 
 /// 同样是获取时间，不过 `TimeSpec` 精度为 ns。
 ///
-/// 可以有不同的时钟，但目前只支持挂钟 (`CLOCK_REALTIME`)。
+/// 没有实现系统挂起，因此 `CLOCK_BOOTTIME` 和 `CLOCK_MONOTONIC` 相同。
+/// 粗粒度的时钟会被截断到调度时钟的精度
 ///
 /// 参数：
-/// - `clock_id` 时钟 id，目前仅为 `CLOCK_READTIME`
+/// - `clock_id` 时钟 id
 /// - `tp` 指向要设置的用户指针
-pub fn sys_clock_gettime(_clock_id: usize, ts: UserCheck<TimeSpec>) -> KResult {
-    // TODO: 目前只考虑挂钟时间
-    assert_eq!(_clock_id, CLOCK_REALTIME);
+///
+/// 错误：
+/// - `EINVAL` 不支持的 `clock_id`
+/// - `EFAULT` `tp` 指向非法地址
+pub fn sys_clock_gettime(clock_id: usize, ts: UserCheck<TimeSpec>) -> KResult {
     let ts = unsafe { ts.check_ptr_mut()? };
-    let ns = riscv_time::get_time_ns();
-    ts.write(TimeSpec {
-        sec: (ns / NANO_PER_SEC) as i64,
-        nsec: (ns % NANO_PER_SEC) as i64,
-    });
+    let time = match clock_id {
+        CLOCK_REALTIME => time::realtime(),
+        CLOCK_REALTIME_COARSE => time::coarse(time::realtime()),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME => time::curr_time(),
+        CLOCK_MONOTONIC_COARSE => time::coarse(time::curr_time()),
+        CLOCK_PROCESS_CPUTIME_ID => {
+            let thread = local_hart().curr_thread();
+            thread.time_stat.account_system();
+            thread
+                .process
+                .lock_inner_with(|inner| inner.cpu_times())
+                .total()
+        }
+        CLOCK_THREAD_CPUTIME_ID => {
+            let thread = local_hart().curr_thread();
+            thread.time_stat.account_system();
+            thread.time_stat.times().total()
+        }
+        _ => return Err(errno::EINVAL),
+    };
+    ts.write(TimeSpec::from(time));
     Ok(0)
 }
 
-// FIXME: `sys_times` 暂时是非正确的实现
-/// 获取进程和子进程运行时间，单位是**时钟 tick 数**
+/// 获取时钟的精度。成功返回 0
+///
+/// 参数：
+/// - `clock_id` 时钟 id
+/// - `res` 如果非 NULL，写入时钟的精度
+///
+/// 错误：
+/// - `EINVAL` 不支持的 `clock_id`
+/// - `EFAULT` `res` 指向非法地址
+pub fn sys_clock_getres(clock_id: usize, res: Option<UserCheck<TimeSpec>>) -> KResult {
+    let resolution = match clock_id {
+        CLOCK_REALTIME_COARSE | CLOCK_MONOTONIC_COARSE => time::COARSE_RESOLUTION,
+        CLOCK_REALTIME
+        | CLOCK_MONOTONIC
+        | CLOCK_PROCESS_CPUTIME_ID
+        | CLOCK_THREAD_CPUTIME_ID
+        | CLOCK_MONOTONIC_RAW
+        | CLOCK_BOOTTIME => time::RESOLUTION,
+        _ => return Err(errno::EINVAL),
+    };
+    if let Some(res) = res {
+        unsafe { res.check_ptr_mut()? }.write(TimeSpec::from(resolution));
+    }
+    Ok(0)
+}
+
+/// 设置时钟。只有 `CLOCK_REALTIME` 可以设置。成功返回 0
+///
+/// 参数：
+/// - `clock_id` 时钟 id
+/// - `tp` 新的时间
+///
+/// 错误：
+/// - `EINVAL` `clock_id` 不支持或不可设置，或者 `tp` 非法
+/// - `EFAULT` `tp` 指向非法地址
+/// - `EPERM` 调用进程没有特权
+pub fn sys_clock_settime(clock_id: usize, tp: UserCheck<TimeSpec>) -> KResult {
+    let tp = Duration::try_from(tp.check_ptr()?.read())?;
+    if clock_id != CLOCK_REALTIME {
+        return Err(errno::EINVAL);
+    }
+    if !local_hart().curr_process().cred().is_privileged() {
+        return Err(errno::EPERM);
+    }
+    time::set_realtime(tp);
+    Ok(0)
+}
+
+/// `clock_t` 的单位，即每秒的时钟 tick 数。对用户态而言，Linux 中它总是 100
+const USER_HZ: u128 = 100;
+
+/// 将时间转换为时钟 tick 数
+fn clock_ticks(time: Duration) -> usize {
+    (time.as_micros() * USER_HZ / MICRO_PER_SEC as u128) as usize
+}
+
+/// 获取进程和已被回收的子进程的 CPU 时间，单位是**时钟 tick 数**。返回自开机以来经过的时钟 tick 数
 ///
 /// 参数：
 /// - `tms` 是一个用户指针，结果被写入其中。
@@ -56,21 +161,111 @@ pub fn sys_clock_gettime(_clock_id: usize, ts: UserCheck<TimeSpec>) -> KResult {
 /// 错误：
 /// - `EFAULT` `tms` 指向非法地址
 pub fn sys_times(tms: UserCheck<Tms>) -> KResult {
-    let ticks = riscv::register::time::read();
     let tms = unsafe { tms.check_ptr_mut()? };
+    let (times, children_times) = {
+        let thread = local_hart().curr_thread();
+        thread.time_stat.account_system();
+        thread
+            .process
+            .lock_inner_with(|inner| (inner.cpu_times(), inner.children_times))
+    };
     tms.write(Tms {
-        tms_utime: ticks / 4,
-        tms_stime: ticks / 4,
-        tms_cutime: ticks / 4,
-        tms_cstime: ticks / 4,
+        tms_utime: clock_ticks(times.user),
+        tms_stime: clock_ticks(times.system),
+        tms_cutime: clock_ticks(children_times.user),
+        tms_cstime: clock_ticks(children_times.system),
     });
+    Ok(clock_ticks(time::curr_time()) as isize)
+}
+
+/// 获取资源使用情况。目前只统计了 CPU 时间。成功返回 0
+///
+/// 参数：
+/// - `who` 统计的对象
+///     - `RUSAGE_SELF` 调用进程，即其所有线程之和
+///     - `RUSAGE_CHILDREN` 调用进程所有已被回收的子进程，以及它们回收了的子进程
+///     - `RUSAGE_THREAD` 调用线程
+/// - `usage` 是一个用户指针，结果被写入其中
+///
+/// 错误：
+/// - `EINVAL` `who` 非法
+/// - `EFAULT` `usage` 指向非法地址
+pub fn sys_getrusage(who: i32, usage: UserCheck<Rusage>) -> KResult {
+    let usage = unsafe { usage.check_ptr_mut()? };
+    let times = {
+        let thread = local_hart().curr_thread();
+        thread.time_stat.account_system();
+        match who {
+            RUSAGE_SELF => thread.process.lock_inner_with(|inner| inner.cpu_times()),
+            RUSAGE_CHILDREN => thread.process.lock_inner_with(|inner| inner.children_times),
+            RUSAGE_THREAD => thread.time_stat.times(),
+            _ => return Err(errno::EINVAL),
+        }
+    };
+    usage.write(Rusage::from(times));
     Ok(0)
 }
 
 /// 挂起调用线程的执行，直到至少经过 `req` 中指定的时间。成功后返回 0
-pub async fn sys_nanosleep(req: UserCheck<TimeSpec>) -> KResult {
-    let req = req.check_ptr()?.read();
-    let req = Duration::try_from(req)?;
-    time::sleep(req).await;
+///
+/// 同 `sys_clock_nanosleep(CLOCK_MONOTONIC, 0, req, rem)`
+pub async fn sys_nanosleep(req: UserCheck<TimeSpec>, rem: Option<UserCheck<TimeSpec>>) -> KResult {
+    sys_clock_nanosleep(CLOCK_MONOTONIC, 0, req, rem).await
+}
+
+/// 挂起调用线程的执行，直到时钟 `clock_id` 至少经过 `req` 中指定的时间，或者到达 `req` 指定的时刻。
+/// 成功后返回 0
+///
+/// 参数：
+/// - `clock_id` 时钟 id，支持 `CLOCK_REALTIME`、`CLOCK_MONOTONIC` 和 `CLOCK_BOOTTIME`
+/// - `flags` 如果包含 `TIMER_ABSTIME`，则 `req` 是绝对时刻，否则是相对时间
+/// - `req` 要睡眠的时间或睡眠到的时刻
+/// - `rem` 如果非 NULL 且 `req` 是相对时间，则在被信号打断时写入剩余的时间
+///
+/// 错误：
+/// - `EFAULT` `req` 或 `rem` 指向非法地址
+/// - `EINVAL` `clock_id` 不支持，或者 `req` 非法
+/// - `EINTR` 被信号打断
+pub async fn sys_clock_nanosleep(
+    clock_id: usize,
+    flags: u32,
+    req: UserCheck<TimeSpec>,
+    rem: Option<UserCheck<TimeSpec>>,
+) -> KResult {
+    let req = Duration::try_from(req.check_ptr()?.read())?;
+    let abs = flags & TIMER_ABSTIME != 0;
+    // 重启的相对时间睡眠只睡眠到原本的时刻
+    let restart_deadline = local_hart()
+        .curr_thread()
+        .lock_inner_with(|inner| inner.restart_deadline.take());
+    // 睡眠到的单调时钟时刻
+    // TODO: [low] 睡眠期间挂钟被修改时，按挂钟时间睡眠的应当随之调整
+    let deadline = match clock_id {
+        CLOCK_REALTIME if abs => time::realtime_to_monotonic(req),
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME if abs => req,
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME => {
+            restart_deadline.unwrap_or_else(|| time::curr_time().saturating_add(req))
+        }
+        _ => return Err(errno::EINVAL),
+    };
+    if signal::interruptible(time::sleep_until(deadline))
+        .await
+        .is_err()
+    {
+        if let Some(rem) = rem {
+            if !abs {
+                let remaining = deadline.saturating_sub(time::curr_time());
+                unsafe { rem.check_ptr_mut()? }.write(TimeSpec::from(remaining));
+            }
+        }
+        // 调用了 signal handler 的话，无论是否有 `SA_RESTART` 都不会重启。
+        // 绝对时刻的睡眠重启后仍然睡眠到同一时刻，相对时间的睡眠重启后则只睡眠剩余的时间
+        if !abs {
+            local_hart()
+                .curr_thread()
+                .lock_inner_with(|inner| inner.restart_deadline = Some(deadline));
+        }
+        return Err(errno::ERESTARTNOHAND);
+    }
     Ok(0)
 }
@@ -0,0 +1,396 @@
+use core::time::Duration;
+
+use defines::{
+    error::{errno, KResult},
+    fs::OpenFlags,
+    misc::{
+        ITimerSpec, ITimerVal, TimeSpec, TimeVal, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL,
+        TIMER_ABSTIME,
+    },
+    signal::{SigEvent, SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD_ID},
+};
+use triomphe::Arc;
+
+use super::time::{CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::{
+    fs::{File, FileDescriptor, TimerFd},
+    hart::local_hart,
+    memory::UserCheck,
+    process::{PosixTimer, RealTimer, TimerNotify, TimerSignal},
+    signal::{KSigInfo, Signal},
+    time,
+};
+
+fn to_itimerval((value, interval): (Duration, Duration)) -> ITimerVal {
+    ITimerVal {
+        it_interval: TimeVal::from(interval),
+        it_value: TimeVal::from(value),
+    }
+}
+
+fn to_itimerspec((value, interval): (Duration, Duration)) -> ITimerSpec {
+    ITimerSpec {
+        it_interval: TimeSpec::from(interval),
+        it_value: TimeSpec::from(value),
+    }
+}
+
+/// 获取间隔定时器的值。成功返回 0
+///
+/// 参数：
+/// - `which` 定时器种类，即 `ITIMER_REAL`、`ITIMER_VIRTUAL` 或 `ITIMER_PROF`
+/// - `curr_value` 写入距离下一次到期的时间和间隔
+///
+/// 错误：
+/// - `EINVAL` `which` 非法
+/// - `EFAULT` `curr_value` 指向非法地址
+pub fn sys_getitimer(which: u32, curr_value: UserCheck<ITimerVal>) -> KResult {
+    let curr_value = unsafe { curr_value.check_ptr_mut()? };
+    let thread = local_hart().curr_thread();
+    thread.time_stat.account_system();
+    let value = thread.process.lock_inner_with(|inner| {
+        let times = inner.cpu_times();
+        match which {
+            ITIMER_REAL => Ok(inner.timers.real.get()),
+            ITIMER_VIRTUAL => Ok(inner.timers.virt.get(times.user)),
+            ITIMER_PROF => Ok(inner.timers.prof.get(times.total())),
+            _ => Err(errno::EINVAL),
+        }
+    })?;
+    curr_value.write(to_itimerval(value));
+    Ok(0)
+}
+
+/// 设置间隔定时器。成功返回 0
+///
+/// 定时器在 `it_value` 后到期，之后每隔 `it_interval` 到期一次。`it_value` 为 0 则停止定时器。
+/// `alarm` 由用户库通过 `ITIMER_REAL` 实现
+///
+/// 参数：
+/// - `which` 定时器种类
+///     - `ITIMER_REAL` 按真实时间计时，到期时发送 `SIGALRM`
+///     - `ITIMER_VIRTUAL` 按进程的用户态 CPU 时间计时，到期时发送 `SIGVTALRM`
+///     - `ITIMER_PROF` 按进程的 CPU 时间计时，到期时发送 `SIGPROF`
+/// - `new_value` 新的值。和 Linux 一样，为 NULL 时视作停止定时器
+/// - `old_value` 如果非 NULL，写入原来的值
+///
+/// 错误：
+/// - `EINVAL` `which` 非法，或者 `new_value` 非法
+/// - `EFAULT` `new_value` 或 `old_value` 指向非法地址
+pub fn sys_setitimer(
+    which: u32,
+    new_value: Option<UserCheck<ITimerVal>>,
+    old_value: Option<UserCheck<ITimerVal>>,
+) -> KResult {
+    let new_value = match new_value {
+        Some(new_value) => new_value.check_ptr()?.read(),
+        None => ITimerVal::default(),
+    };
+    let value = Duration::try_from(new_value.it_value)?;
+    let interval = Duration::try_from(new_value.it_interval)?;
+    let thread = local_hart().curr_thread();
+    thread.time_stat.account_system();
+    let process = &thread.process;
+    let old = process.lock_inner_with(|inner| {
+        let times = inner.cpu_times();
+        let timers = &mut inner.timers;
+        let old = match which {
+            ITIMER_REAL => {
+                let old = timers.real.get();
+                let deadline = (!value.is_zero()).then(|| time::curr_time().saturating_add(value));
+                let process = Arc::clone(process);
+                timers.real.set(deadline, interval, move |_| {
+                    process.receive_signal(KSigInfo::kernel(Signal::SIGALRM));
+                });
+                old
+            }
+            ITIMER_VIRTUAL => {
+                let old = timers.virt.get(times.user);
+                timers.virt.set(times.user, value, interval);
+                old
+            }
+            ITIMER_PROF => {
+                let old = timers.prof.get(times.total());
+                timers.prof.set(times.total(), value, interval);
+                old
+            }
+            _ => return Err(errno::EINVAL),
+        };
+        Ok(old)
+    })?;
+    if let Some(old_value) = old_value {
+        unsafe { old_value.check_ptr_mut()? }.write(to_itimerval(old));
+    }
+    Ok(0)
+}
+
+/// 创建一个 POSIX 定时器，创建后处于停止状态。成功返回 0
+///
+/// 参数：
+/// - `clock_id` 计时所用的时钟，支持 `CLOCK_REALTIME`、`CLOCK_MONOTONIC` 和 `CLOCK_BOOTTIME`
+/// - `sevp` 到期时的通知方式，不支持 `SIGEV_THREAD`。
+///   为 NULL 时相当于发送 `SIGALRM`，并以定时器 id 作为信号携带的值
+/// - `timer_id` 写入新定时器的 id
+///
+/// 错误：
+/// - `EINVAL` `clock_id` 不支持，或者 `sevp` 非法，或者 `SIGEV_THREAD_ID` 指定的线程不在调用进程中
+/// - `EFAULT` `sevp` 或 `timer_id` 指向非法地址
+pub fn sys_timer_create(
+    clock_id: usize,
+    sevp: Option<UserCheck<SigEvent>>,
+    timer_id: UserCheck<i32>,
+) -> KResult {
+    let realtime = match clock_id {
+        CLOCK_REALTIME => true,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => false,
+        _ => return Err(errno::EINVAL),
+    };
+    let sevp = match sevp {
+        Some(sevp) => Some(sevp.check_ptr()?.read()),
+        None => None,
+    };
+    let timer_id = unsafe { timer_id.check_ptr_mut()? };
+    let id = local_hart().curr_process().lock_inner_with(|inner| {
+        let id = inner.timers.alloc_posix_id();
+        let notify = match sevp {
+            None => TimerNotify::Signal {
+                signal: Signal::SIGALRM,
+                value: id,
+                tid: None,
+            },
+            Some(sevp) => match sevp.sigev_notify {
+                SIGEV_NONE => TimerNotify::None,
+                SIGEV_SIGNAL | SIGEV_THREAD_ID => {
+                    let signal = u8::try_from(sevp.sigev_signo)
+                        .ok()
+                        .and_then(Signal::from_user)
+                        .ok_or(errno::EINVAL)?;
+                    let tid = if sevp.sigev_notify == SIGEV_THREAD_ID {
+                        let tid = sevp.sigev_notify_thread_id as usize;
+                        if !inner.threads.contains_key(&tid) {
+                            return Err(errno::EINVAL);
+                        }
+                        Some(tid)
+                    } else {
+                        None
+                    };
+                    TimerNotify::Signal {
+                        signal,
+                        value: sevp.sigev_value,
+                        tid,
+                    }
+                }
+                // SIGEV_THREAD 由用户库借助 SIGEV_THREAD_ID 实现
+                _ => return Err(errno::EINVAL),
+            },
+        };
+        inner.timers.posix.insert(
+            id,
+            PosixTimer {
+                realtime,
+                notify,
+                timer: RealTimer::default(),
+                signal: Arc::new(TimerSignal::default()),
+            },
+        );
+        Ok(id)
+    })?;
+    timer_id.write(id as i32);
+    Ok(0)
+}
+
+/// 设置 POSIX 定时器。成功返回 0
+///
+/// 参数：
+/// - `timer_id` 定时器 id
+/// - `flags` 如果包含 `TIMER_ABSTIME`，则 `it_value` 是定时器所用时钟的绝对时刻，否则是相对时间
+/// - `new_value` 新的值，`it_value` 为 0 则停止定时器
+/// - `old_value` 如果非 NULL，写入原来的值
+///
+/// 错误：
+/// - `EINVAL` `timer_id` 不存在，或者 `new_value` 非法
+/// - `EFAULT` `new_value` 或 `old_value` 指向非法地址
+pub fn sys_timer_settime(
+    timer_id: usize,
+    flags: u32,
+    new_value: UserCheck<ITimerSpec>,
+    old_value: Option<UserCheck<ITimerSpec>>,
+) -> KResult {
+    let new_value = new_value.check_ptr()?.read();
+    let value = Duration::try_from(new_value.it_value)?;
+    let interval = Duration::try_from(new_value.it_interval)?;
+    let process = local_hart().curr_process_arc();
+    let old = process.lock_inner_with(|inner| {
+        let timer = inner.timers.posix.get_mut(&timer_id).ok_or(errno::EINVAL)?;
+        let old = timer.timer.get();
+        // TODO: [low] 按挂钟计时的定时器在挂钟被修改时应当随之调整
+        let deadline = if value.is_zero() {
+            None
+        } else if flags & TIMER_ABSTIME == 0 {
+            Some(time::curr_time().saturating_add(value))
+        } else if timer.realtime {
+            Some(time::realtime_to_monotonic(value))
+        } else {
+            Some(value)
+        };
+        let timer_signal = Arc::clone(&timer.signal);
+        let on_expire = timer
+            .notify
+            .on_expire(Arc::clone(&process), timer_id, timer_signal);
+        timer.timer.set(deadline, interval, on_expire);
+        Ok(old)
+    })?;
+    if let Some(old_value) = old_value {
+        unsafe { old_value.check_ptr_mut()? }.write(to_itimerspec(old));
+    }
+    Ok(0)
+}
+
+/// 获取 POSIX 定时器的值。成功返回 0
+///
+/// 参数：
+/// - `timer_id` 定时器 id
+/// - `curr_value` 写入距离下一次到期的时间和间隔
+///
+/// 错误：
+/// - `EINVAL` `timer_id` 不存在
+/// - `EFAULT` `curr_value` 指向非法地址
+pub fn sys_timer_gettime(timer_id: usize, curr_value: UserCheck<ITimerSpec>) -> KResult {
+    let curr_value = unsafe { curr_value.check_ptr_mut()? };
+    let value = local_hart().curr_process().lock_inner_with(|inner| {
+        inner
+            .timers
+            .posix
+            .get(&timer_id)
+            .map(|timer| timer.timer.get())
+            .ok_or(errno::EINVAL)
+    })?;
+    curr_value.write(to_itimerspec(value));
+    Ok(0)
+}
+
+/// 获取 POSIX 定时器上一次到期时错过的到期次数
+///
+/// 参数：
+/// - `timer_id` 定时器 id
+///
+/// 错误：
+/// - `EINVAL` `timer_id` 不存在
+pub fn sys_timer_getoverrun(timer_id: usize) -> KResult {
+    local_hart().curr_process().lock_inner_with(|inner| {
+        inner
+            .timers
+            .posix
+            .get(&timer_id)
+            .map(|timer| timer.signal.overrun() as isize)
+            .ok_or(errno::EINVAL)
+    })
+}
+
+/// 删除 POSIX 定时器。成功返回 0
+///
+/// 参数：
+/// - `timer_id` 定时器 id
+///
+/// 错误：
+/// - `EINVAL` `timer_id` 不存在
+pub fn sys_timer_delete(timer_id: usize) -> KResult {
+    let timer = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.timers.posix.remove(&timer_id));
+    timer.ok_or(errno::EINVAL)?;
+    Ok(0)
+}
+
+/// 创建一个 timerfd，读取它会取出自上次读取以来定时器到期的次数。创建后定时器处于停止状态。返回其文件描述符
+///
+/// 参数：
+/// - `clock_id` 计时所用的时钟，支持 `CLOCK_REALTIME`、`CLOCK_MONOTONIC` 和 `CLOCK_BOOTTIME`
+/// - `flags` 可以包含 `TFD_NONBLOCK` 和 `TFD_CLOEXEC`，它们的值和 [`OpenFlags`] 中对应的标志相同
+///
+/// 错误：
+/// - `EINVAL` `clock_id` 不支持，或者 `flags` 非法
+/// - `EMFILE` 文件描述符数量达到上限
+pub fn sys_timerfd_create(clock_id: usize, flags: u32) -> KResult {
+    let realtime = match clock_id {
+        CLOCK_REALTIME => true,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => false,
+        _ => return Err(errno::EINVAL),
+    };
+    let flags = OpenFlags::from_bits(flags)
+        .filter(|flags| (OpenFlags::NONBLOCK | OpenFlags::CLOEXEC).contains(*flags))
+        .ok_or(errno::EINVAL)?;
+    let timerfd = File::TimerFd(Arc::new(TimerFd::new(realtime)));
+    let desc = FileDescriptor::new(timerfd, flags | OpenFlags::RDONLY);
+    let fd = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.fd_table.add(desc))
+        .ok_or(errno::EMFILE)?;
+    Ok(fd as isize)
+}
+
+/// 获取文件描述符 `fd` 对应的 timerfd
+///
+/// 错误：
+/// - `EBADF` `fd` 不是有效的文件描述符
+/// - `EINVAL` `fd` 不是 timerfd
+fn get_timerfd(fd: usize) -> KResult<Arc<TimerFd>> {
+    let desc = local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.fd_table.get(fd).cloned())
+        .ok_or(errno::EBADF)?;
+    match &*desc {
+        File::TimerFd(timerfd) => Ok(Arc::clone(timerfd)),
+        _ => Err(errno::EINVAL),
+    }
+}
+
+/// 设置 timerfd 的定时器。成功返回 0
+///
+/// 参数：
+/// - `fd` timerfd 的文件描述符
+/// - `flags` 如果包含 `TFD_TIMER_ABSTIME`，则 `it_value` 是定时器所用时钟的绝对时刻，否则是相对时间
+/// - `new_value` 新的值，`it_value` 为 0 则停止定时器
+/// - `old_value` 如果非 NULL，写入原来的值
+///
+/// 错误：
+/// - `EBADF` `fd` 不是有效的文件描述符
+/// - `EINVAL` `fd` 不是 timerfd，或者 `flags` 或 `new_value` 非法
+/// - `EFAULT` `new_value` 或 `old_value` 指向非法地址
+pub fn sys_timerfd_settime(
+    fd: usize,
+    flags: u32,
+    new_value: UserCheck<ITimerSpec>,
+    old_value: Option<UserCheck<ITimerSpec>>,
+) -> KResult {
+    // `TFD_TIMER_CANCEL_ON_SET` 只用于检测挂钟被修改，不支持也不影响计时
+    const TFD_TIMER_CANCEL_ON_SET: u32 = 2;
+    if flags & !(TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return Err(errno::EINVAL);
+    }
+    let timerfd = get_timerfd(fd)?;
+    let new_value = new_value.check_ptr()?.read();
+    let value = Duration::try_from(new_value.it_value)?;
+    let interval = Duration::try_from(new_value.it_interval)?;
+    let old = timerfd.set(value, interval, flags & TIMER_ABSTIME != 0);
+    if let Some(old_value) = old_value {
+        unsafe { old_value.check_ptr_mut()? }.write(to_itimerspec(old));
+    }
+    Ok(0)
+}
+
+/// 获取 timerfd 的定时器的值。成功返回 0
+///
+/// 参数：
+/// - `fd` timerfd 的文件描述符
+/// - `curr_value` 写入距离下一次到期的时间和间隔
+///
+/// 错误：
+/// - `EBADF` `fd` 不是有效的文件描述符
+/// - `EINVAL` `fd` 不是 timerfd
+/// - `EFAULT` `curr_value` 指向非法地址
+pub fn sys_timerfd_gettime(fd: usize, curr_value: UserCheck<ITimerSpec>) -> KResult {
+    let timerfd = get_timerfd(fd)?;
+    unsafe { curr_value.check_ptr_mut()? }.write(to_itimerspec(timerfd.get()));
+    Ok(0)
+}
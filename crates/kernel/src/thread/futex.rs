@@ -0,0 +1,201 @@
+//! futex 的实现。
+//!
+//! futex 以 futex 字所在的物理地址区分，因此即使是不同进程、不同虚拟地址的共享映射，也能互相等待和唤醒
+
+use alloc::collections::VecDeque;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use defines::error::{errno, KResult};
+use hashbrown::HashMap;
+use klocks::{Lazy, SpinMutex};
+use triomphe::Arc;
+
+use crate::{
+    hart::local_hart,
+    memory::{kernel_pa_to_va, PhysAddr, UserCheck, VirtAddr},
+};
+
+/// 所有 futex 的等待队列，以 futex 字的物理地址为键。队列为空时会被移除
+static FUTEX_QUEUES: Lazy<SpinMutex<HashMap<PhysAddr, VecDeque<Arc<FutexWaiter>>>>> =
+    Lazy::new(|| SpinMutex::new(HashMap::new()));
+
+struct FutexWaiter {
+    bitset: u32,
+    /// 注意锁的顺序，必须先锁 [`FUTEX_QUEUES`] 再锁这里
+    state: SpinMutex<WaiterState>,
+}
+
+struct WaiterState {
+    /// 当前所在的等待队列，可能因 requeue 而改变
+    key: PhysAddr,
+    woken: bool,
+    waker: Option<Waker>,
+}
+
+impl FutexWaiter {
+    fn wake(&self) {
+        let mut state = self.state.lock();
+        state.woken = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// 获取当前进程中用户地址 `uaddr` 处的 futex 对应的物理地址
+///
+/// 错误：
+/// - `EINVAL` `uaddr` 未对齐到 4 字节
+/// - `EFAULT` `uaddr` 指向非法地址
+pub fn futex_key(uaddr: usize) -> KResult<PhysAddr> {
+    if uaddr % core::mem::align_of::<u32>() != 0 {
+        return Err(errno::EINVAL);
+    }
+    // 先检查一遍，确保对应的页已经被分配了
+    UserCheck::new(uaddr as *mut u32)
+        .ok_or(errno::EFAULT)?
+        .check_ptr()?;
+    local_hart()
+        .curr_process()
+        .lock_inner_with(|inner| inner.memory_space.translate(VirtAddr(uaddr)))
+        .ok_or(errno::EFAULT)
+}
+
+/// 读取物理地址 `key` 处的 futex 字
+fn futex_value(key: PhysAddr) -> u32 {
+    // SAFETY: `key` 由 `futex_key()` 得到，是 4 字节对齐且已分配的用户页中的地址
+    let word = unsafe { kernel_pa_to_va(key).as_ref::<AtomicU32>() };
+    word.load(Ordering::SeqCst)
+}
+
+/// 若 futex 字的值仍为 `val`，则加入其等待队列。返回的 [`FutexWait`] 在被唤醒时完成。
+///
+/// 检查值与入队是在同一个锁中完成的，因此不会丢失唤醒
+///
+/// 错误：
+/// - `EAGAIN` futex 字的值不为 `val`
+pub fn wait(key: PhysAddr, val: u32, bitset: u32) -> KResult<FutexWait> {
+    let mut queues = FUTEX_QUEUES.lock();
+    if futex_value(key) != val {
+        return Err(errno::EAGAIN);
+    }
+    let waiter = Arc::new(FutexWaiter {
+        bitset,
+        state: SpinMutex::new(WaiterState {
+            key,
+            woken: false,
+            waker: None,
+        }),
+    });
+    queues
+        .entry(key)
+        .or_default()
+        .push_back(Arc::clone(&waiter));
+    Ok(FutexWait { waiter })
+}
+
+/// 唤醒 `key` 上至多 `n` 个掩码与 `bitset` 有交集的等待者，返回唤醒的数量
+pub fn wake(key: PhysAddr, n: usize, bitset: u32) -> usize {
+    let mut queues = FUTEX_QUEUES.lock();
+    let Some(queue) = queues.get_mut(&key) else {
+        return 0;
+    };
+    let mut count = 0;
+    queue.retain(|waiter| {
+        if count < n && waiter.bitset & bitset != 0 {
+            waiter.wake();
+            count += 1;
+            false
+        } else {
+            true
+        }
+    });
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    count
+}
+
+/// 唤醒 `key` 上至多 `n_wake` 个等待者，再将至多 `n_requeue` 个剩余的等待者转移到 `new_key` 上。
+///
+/// 返回唤醒的数量和转移的数量
+///
+/// 参数：
+/// - `expected` 若为 `Some`，则要求 `key` 处的 futex 字的值与之相等
+///
+/// 错误：
+/// - `EAGAIN` futex 字的值不为 `expected`
+pub fn requeue(
+    key: PhysAddr,
+    new_key: PhysAddr,
+    n_wake: usize,
+    n_requeue: usize,
+    expected: Option<u32>,
+) -> KResult<(usize, usize)> {
+    let mut queues = FUTEX_QUEUES.lock();
+    if expected.is_some_and(|expected| futex_value(key) != expected) {
+        return Err(errno::EAGAIN);
+    }
+    let Some(mut queue) = queues.remove(&key) else {
+        return Ok((0, 0));
+    };
+    let n_wake = n_wake.min(queue.len());
+    for waiter in queue.drain(..n_wake) {
+        waiter.wake();
+    }
+    let n_requeue = n_requeue.min(queue.len());
+    // 转移到自身相当于什么也不做
+    if key != new_key {
+        let moved = queue.drain(..n_requeue).collect::<VecDeque<_>>();
+        for waiter in &moved {
+            waiter.state.lock().key = new_key;
+        }
+        queues.entry(new_key).or_default().extend(moved);
+    }
+    if !queue.is_empty() {
+        queues.insert(key, queue);
+    }
+    Ok((n_wake, n_requeue))
+}
+
+/// 等待 futex 被唤醒。若在唤醒前被 drop（如超时），则会从等待队列中移除自身
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct FutexWait {
+    waiter: Arc<FutexWaiter>,
+}
+
+impl Future for FutexWait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.waiter.state.lock();
+        if state.woken {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for FutexWait {
+    fn drop(&mut self) {
+        let mut queues = FUTEX_QUEUES.lock();
+        let state = self.waiter.state.lock();
+        if state.woken {
+            return;
+        }
+        let key = state.key;
+        if let Some(queue) = queues.get_mut(&key) {
+            queue.retain(|waiter| !Arc::ptr_eq(waiter, &self.waiter));
+            if queue.is_empty() {
+                queues.remove(&key);
+            }
+        }
+    }
+}
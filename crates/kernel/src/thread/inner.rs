@@ -1,16 +1,32 @@
-use crate::{signal::KSignalSet, trap::TrapContext};
+use core::time::Duration;
+
+use defines::signal::SignalStack;
+
+use crate::{
+    signal::{KSignalSet, PendingSignals},
+    trap::{SyscallRestart, TrapContext},
+};
 
 pub struct ThreadInner {
     /// 陷入上下文
     pub trap_context: TrapContext,
 
-    // TODO: [blocked] thread。实现 clear_child_tid。<https://man7.org/linux/man-pages/man2/set_tid_address.2.html>
-    #[allow(unused)]
+    /// 线程退出时，会在该地址写入 0 并唤醒其上的 futex。为 0 表示不设置。
+    ///
+    /// <https://man7.org/linux/man-pages/man2/set_tid_address.2.html>
     pub clear_child_tid: usize,
 
     // 信号
     /// 信号掩码
     pub signal_mask: KSignalSet,
+    /// `rt_sigsuspend` 临时替换信号掩码时保存的原掩码，在返回用户态前恢复
+    pub saved_signal_mask: Option<KSignalSet>,
     /// 待处理信号队列
-    pub pending_signal: KSignalSet,
+    pub pending_signal: PendingSignals,
+    /// 由 `sigaltstack` 设置的信号栈
+    pub signal_stack: SignalStack,
+    /// 被信号打断，可能需要重启的系统调用
+    pub syscall_restart: Option<SyscallRestart>,
+    /// 被打断的睡眠或者 `ppoll` 原本的截止时刻（单调时钟）。被打断时设置，重启后会取出它，只等待剩余的时间
+    pub restart_deadline: Option<Duration>,
 }
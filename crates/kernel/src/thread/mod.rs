@@ -1,31 +1,50 @@
+pub mod futex;
 mod inner;
+mod time_stat;
 mod user;
 
 use core::ops::Range;
 
 use atomic::{Atomic, Ordering};
-use common::config::{LOW_ADDRESS_END, PAGE_SIZE, USER_STACK_SIZE};
+use common::config::{LOW_ADDRESS_END, MAX_USER_STACK_SIZE, PAGE_SIZE};
+use defines::signal::SignalStack;
+use event_listener::Event;
 use klocks::{SpinMutex, SpinMutexGuard};
 use triomphe::Arc;
 
-use self::inner::ThreadInner;
-pub use self::user::spawn_user_thread;
+pub use self::{
+    inner::ThreadInner,
+    time_stat::{CpuTimes, ThreadTimeStat},
+    user::spawn_user_thread,
+};
 use crate::{
-    memory::{self, MapPermission, MemorySpace, VirtAddr, VirtPageNum},
-    process::Process,
-    signal::KSignalSet,
+    executor::SchedEntity,
+    memory::{MapPermission, MemorySpace, UnmappedPages, VirtAddr, VirtPageNum},
+    process::{self, Process},
+    signal::{KSigInfo, KSignalSet, PendingSignals},
     trap::TrapContext,
 };
 
 /// 进程控制块
 pub struct Thread {
+    /// 线程 id，与 pid 共用同一个命名空间。主线程的 tid 即为进程的 pid
     tid: usize,
+    /// 进程内的编号，决定了用户栈的位置
+    stack_id: usize,
+    /// 线程收到信号或所属进程退出时会被通知，用于打断正在等待的系统调用
+    pub signal_event: Event,
     /// 线程状态
     pub status: Atomic<ThreadStatus>,
     /// 线程的退出码，在 `sys_exit` 时被设置。
     ///
     /// 如果它是进程中的最后一个线程，则将进程退出码设置为它。
     pub exit_code: Atomic<i8>,
+    /// 线程是否被同进程中执行 `execve` 的线程终止
+    killed: Atomic<bool>,
+    /// 线程的 CPU 时间统计
+    pub time_stat: ThreadTimeStat,
+    /// 线程的调度信息，也是其任务的元数据
+    pub sched: Arc<SchedEntity>,
     pub process: Arc<Process>,
     inner: SpinMutex<ThreadInner>,
 }
@@ -34,19 +53,30 @@ impl Thread {
     pub fn new(
         process: Arc<Process>,
         tid: usize,
+        stack_id: usize,
         trap_context: TrapContext,
         signal_mask: KSignalSet,
+        sched: SchedEntity,
     ) -> Self {
         Self {
             tid,
+            stack_id,
+            signal_event: Event::new(),
             exit_code: Atomic::new(0),
+            killed: Atomic::new(false),
             status: Atomic::new(ThreadStatus::Ready),
+            time_stat: ThreadTimeStat::default(),
+            sched: Arc::new(sched),
             process,
             inner: SpinMutex::new(ThreadInner {
                 trap_context,
                 clear_child_tid: 0,
                 signal_mask,
-                pending_signal: KSignalSet::empty(),
+                saved_signal_mask: None,
+                pending_signal: PendingSignals::default(),
+                signal_stack: SignalStack::default(),
+                syscall_restart: None,
+                restart_deadline: None,
             }),
         }
     }
@@ -55,6 +85,10 @@ impl Thread {
         self.tid
     }
 
+    pub fn stack_id(&self) -> usize {
+        self.stack_id
+    }
+
     pub fn lock_inner(&self) -> SpinMutexGuard<'_, ThreadInner> {
         self.inner.lock()
     }
@@ -64,20 +98,32 @@ impl Thread {
         f(&mut self.inner.lock())
     }
 
+    /// 令本线程收到信号，并唤醒可能正在等待的本线程
+    pub fn receive_signal(&self, info: KSigInfo) {
+        debug!("thread {} receive signal {:?}", self.tid, info.signal);
+        if self.lock_inner_with(|inner| inner.pending_signal.insert(info)) {
+            self.signal_event.notify(usize::MAX);
+        }
+    }
+
     /// 分配用户栈，一般用于创建新线程。返回用户栈范围
     ///
-    /// 注意 `memory_space` 是本进程的 `MemorySpace`
-    pub fn alloc_user_stack(tid: usize, memory_space: &mut MemorySpace) -> Range<VirtPageNum> {
+    /// 注意 `memory_space` 是本进程的 `MemorySpace`，`stack_size` 是进程的用户栈大小
+    pub fn alloc_user_stack(
+        stack_id: usize,
+        stack_size: usize,
+        memory_space: &mut MemorySpace,
+    ) -> Range<VirtPageNum> {
         // 分配用户栈
-        let ustack_low_vpn = Self::user_stack_low_addr(tid);
-        let ustack_high_vpn = Self::user_stack_high_addr(tid);
+        let ustack_low_vpn = Self::user_stack_low_addr(stack_id, stack_size);
+        let ustack_high_vpn = Self::user_stack_high_addr(stack_id);
         trace!(
             "user stack is {:#x}..{:#x}",
             ustack_low_vpn.page_start().0,
             ustack_high_vpn.page_start().0
         );
 
-        // 栈地址都是根据 tid 确定的，不会冲突
+        // 栈地址都是根据 `stack_id` 确定的，不会冲突
         unsafe {
             memory_space.user_map(
                 ustack_low_vpn..ustack_high_vpn,
@@ -88,29 +134,55 @@ impl Thread {
     }
 
     /// 获取当前线程用户栈的低地址，即高地址减去用户栈大小
-    fn user_stack_low_addr(tid: usize) -> VirtPageNum {
-        Self::user_stack_high_addr(tid) - VirtAddr(USER_STACK_SIZE).vpn_floor().0
+    fn user_stack_low_addr(stack_id: usize, stack_size: usize) -> VirtPageNum {
+        Self::user_stack_high_addr(stack_id) - VirtAddr(stack_size).vpn_floor().0
     }
 
-    /// 获取当前线程用户栈的高地址
-    fn user_stack_high_addr(tid: usize) -> VirtPageNum {
+    /// 获取当前线程用户栈的高地址。
+    ///
+    /// 每个用户栈都按照最大的大小 [`MAX_USER_STACK_SIZE`] 预留位置，实际只映射其中高处的一部分
+    fn user_stack_high_addr(stack_id: usize) -> VirtPageNum {
         // 注意每个用户栈后都会有一个 Guard Page
-        VirtAddr(LOW_ADDRESS_END - tid * (USER_STACK_SIZE + PAGE_SIZE)).vpn_floor()
+        VirtAddr(LOW_ADDRESS_END - stack_id * (MAX_USER_STACK_SIZE + PAGE_SIZE)).vpn_floor()
     }
 
     /// 释放用户栈。一般是单个线程退出时使用。
     ///
-    /// 注意 `memory_space` 是本进程的 `MemorySpace`
-    fn dealloc_user_stack(&self, memory_space: &mut MemorySpace) {
+    /// 注意 `memory_space` 是本进程的 `MemorySpace`，`stack_size` 是进程的用户栈大小。
+    /// 调用者需要在释放进程的锁后对返回值调用 [`UnmappedPages::shootdown()`]
+    fn dealloc_user_stack(
+        &self,
+        stack_size: usize,
+        memory_space: &mut MemorySpace,
+    ) -> UnmappedPages {
         // 手动取消用户栈的映射
-        let user_stack_low_addr = Self::user_stack_low_addr(self.tid);
-        memory_space.remove_area_with_start_vpn(user_stack_low_addr);
-        memory::flush_tlb(None);
+        let user_stack_low_addr = Self::user_stack_low_addr(self.stack_id, stack_size);
+        memory_space.remove_area_with_start_vpn(user_stack_low_addr)
     }
 
     pub fn set_status(&self, status: ThreadStatus) {
         self.status.store(status, Ordering::SeqCst);
     }
+
+    /// 线程是否应当退出，即所属进程已经退出，或者线程被终止了
+    pub fn is_exiting(&self) -> bool {
+        self.killed.load(Ordering::SeqCst) || self.process.is_exited()
+    }
+
+    /// 单独终止本线程，并唤醒可能正在等待的本线程。线程会在回到用户态之前退出，而进程不受影响
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+        self.signal_event.notify(usize::MAX);
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        // 主线程的 tid 即 pid，随进程一起回收
+        if self.tid != self.process.pid() {
+            process::dealloc_tid(self.tid);
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -124,4 +196,4 @@ pub enum ThreadStatus {
 
 unsafe impl bytemuck::NoUninit for ThreadStatus {}
 
-const _: () = assert!(USER_STACK_SIZE % PAGE_SIZE == 0 && LOW_ADDRESS_END % PAGE_SIZE == 0);
+const _: () = assert!(MAX_USER_STACK_SIZE % PAGE_SIZE == 0 && LOW_ADDRESS_END % PAGE_SIZE == 0);
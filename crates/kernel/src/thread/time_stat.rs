@@ -0,0 +1,93 @@
+use core::{
+    ops::{Add, AddAssign},
+    time::Duration,
+};
+
+use atomic::{Atomic, Ordering};
+use defines::misc::{Rusage, TimeVal};
+
+/// CPU 时间，分为用户态时间和内核态时间
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuTimes {
+    /// 用户态时间
+    pub user: Duration,
+    /// 内核态时间
+    pub system: Duration,
+}
+
+impl CpuTimes {
+    /// 用户态与内核态时间之和
+    pub fn total(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+impl Add for CpuTimes {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            user: self.user + rhs.user,
+            system: self.system + rhs.system,
+        }
+    }
+}
+
+impl AddAssign for CpuTimes {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl From<CpuTimes> for Rusage {
+    fn from(times: CpuTimes) -> Self {
+        Self {
+            ru_utime: TimeVal::from(times.user),
+            ru_stime: TimeVal::from(times.system),
+            ..Default::default()
+        }
+    }
+}
+
+/// 线程的 CPU 时间统计，单位为纳秒。
+///
+/// 线程在 hart 上运行的时间中，从返回用户态到 trap 回内核的部分算作用户态时间，其余算作内核态时间。
+/// 只有正在运行该线程的 hart 会更新它，但其他 hart 也可能读取，所以都用原子变量
+#[derive(Default)]
+pub struct ThreadTimeStat {
+    user_ns: Atomic<u64>,
+    system_ns: Atomic<u64>,
+    /// 上一次结算的时刻
+    last_ns: Atomic<u64>,
+}
+
+impl ThreadTimeStat {
+    /// 线程被调度到 hart 上，从此刻开始计时
+    pub fn start(&self) {
+        self.last_ns
+            .store(riscv_time::get_time_ns() as u64, Ordering::Relaxed);
+    }
+
+    /// 将自上次结算以来的时间计入内核态时间。在返回用户态前、让出 hart 前调用
+    pub fn account_system(&self) {
+        self.system_ns.fetch_add(self.lap(), Ordering::Relaxed);
+    }
+
+    /// 将自上次结算以来的时间计入用户态时间。在从用户态 trap 回内核后调用
+    pub fn account_user(&self) {
+        self.user_ns.fetch_add(self.lap(), Ordering::Relaxed);
+    }
+
+    /// 已经结算的 CPU 时间。注意正在运行的线程还有一段时间未结算
+    pub fn times(&self) -> CpuTimes {
+        CpuTimes {
+            user: Duration::from_nanos(self.user_ns.load(Ordering::Relaxed)),
+            system: Duration::from_nanos(self.system_ns.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn lap(&self) -> u64 {
+        let now = riscv_time::get_time_ns() as u64;
+        now.saturating_sub(self.last_ns.swap(now, Ordering::Relaxed))
+    }
+}
@@ -6,16 +6,23 @@ use core::{
     task::{Context, Poll},
 };
 
+use defines::{
+    error::{errno, KResult},
+    misc::FUTEX_BITSET_MATCH_ANY,
+    signal::CLD_EXITED,
+};
+use event_listener::listener;
 use hashbrown::HashMap;
 use triomphe::Arc;
 
-use super::Thread;
+use super::{futex, Thread};
 use crate::{
     executor,
     fs::VFS,
     hart::local_hart,
-    memory::KERNEL_SPACE,
+    memory::{UserCheck, KERNEL_SPACE},
     process::{ProcessStatus, INITPROC},
+    signal::{KSigInfo, Signal},
     thread::ThreadStatus,
     trap, SHUTDOWN,
 };
@@ -23,6 +30,7 @@ use crate::{
 pub fn spawn_user_thread(thread: Arc<Thread>) {
     let (runnable, task) = executor::spawn_with(
         UserThreadWrapperFuture::new(Arc::clone(&thread), user_thread_loop()),
+        Arc::clone(&thread.sched),
         move || thread.set_status(ThreadStatus::Ready),
     );
     runnable.schedule();
@@ -33,36 +41,84 @@ type UserThreadFuture = impl Future<Output = ()> + Send;
 
 fn user_thread_loop() -> UserThreadFuture {
     async {
+        let thread = Arc::clone(&local_hart().curr_thread_arc());
         loop {
+            // 处理信号，进程可能因此退出或者停止
+            if trap::check_signal(&thread) || thread.is_exiting() {
+                break;
+            }
+            if thread.process.is_stopped() {
+                wait_for_continue(&thread).await;
+                continue;
+            }
+            trap::restart_syscall(&thread);
+
             // 返回用户态
             // 注意切换了控制流，但是之后回到内核态还是在这里
             let trap_context = local_hart()
                 .curr_thread()
                 .lock_inner_with(|inner| &mut inner.trap_context as _);
             trace!("enter user mode");
+            thread.time_stat.account_system();
             trap::trap_return(trap_context);
+            thread.time_stat.account_user();
 
             trace!("enter kernel mode");
             // 在内核态处理 trap。注意这里也可能切换控制流，让出 Hart 给其他线程
             let next_op = trap::user_trap_handler().await;
 
-            if next_op.is_break() || local_hart().curr_process().is_exited() {
+            if next_op.is_break() || thread.is_exiting() {
                 break;
             }
         }
     }
 }
 
+/// 等待进程从停止状态中恢复。进程退出或者线程收到 `SIGKILL` 时也会返回
+async fn wait_for_continue(thread: &Thread) {
+    debug!("thread stopped");
+    loop {
+        listener!(thread.signal_event => listener);
+        let killed = thread.lock_inner_with(|inner| inner.pending_signal.contains(Signal::SIGKILL));
+        if killed || !thread.process.is_stopped() || thread.is_exiting() {
+            break;
+        }
+        listener.await;
+    }
+    debug!("thread continued");
+}
+
 fn exit_thread(thread: &Thread) {
     debug!("thread exits");
+    let clear_child_tid = thread.lock_inner_with(|inner| inner.clear_child_tid);
+    if clear_child_tid != 0 {
+        // 地址非法的话就忽略，和 Linux 一致
+        if let Err(e) = clear_tid_and_wake(clear_child_tid) {
+            warn!("failed to clear child tid at {clear_child_tid:#x}: {e:?}");
+        }
+    }
+
     let process = &thread.process;
+    // 同进程的其他线程可能在其他 hart 上缓存了用户栈的映射，需要在不持有锁时刷新 TLB
+    process
+        .lock_inner_with(|inner| {
+            thread.dealloc_user_stack(inner.user_stack_size, &mut inner.memory_space)
+        })
+        .shootdown();
     let mut process_inner = process.lock_inner();
     process_inner
         .threads
         .remove(&thread.tid)
         .expect("remove thread here");
-    process_inner.tid_allocator.dealloc(thread.tid);
-    thread.dealloc_user_stack(&mut process_inner.memory_space);
+    // 只剩一个线程的话，它可能正在 `execve` 中等待其他线程退出
+    if process_inner.threads.len() == 1 {
+        let last = process_inner.threads.values().next().unwrap();
+        last.signal_event.notify(usize::MAX);
+    }
+    // 线程的 CPU 时间并入进程
+    thread.time_stat.account_system();
+    process_inner.exited_times += thread.time_stat.times();
+    process_inner.stack_id_allocator.dealloc(thread.stack_id);
     thread.set_status(ThreadStatus::Terminated);
 
     // 如果是最后一个线程，则该进程成为僵尸进程，等待父进程 wait
@@ -74,10 +130,14 @@ fn exit_thread(thread: &Thread) {
         process_inner.cwd = Arc::clone(VFS.root_dir());
         process_inner.memory_space.recycle_user_pages();
         process_inner.threads = HashMap::new();
-        process_inner.tid_allocator.release();
+        process_inner.stack_id_allocator.release();
         let children = mem::take(&mut process_inner.children);
         let parent = process_inner.parent.take();
+        let uid = process_inner.cred.uid;
+        // 定时器的内核任务持有进程的引用，需要取消掉
+        let timers = mem::take(&mut process_inner.timers);
         drop(process_inner);
+        drop(timers);
 
         // 如果进程已标记为退出（即已调用 `exit_process()`），则标记为僵尸并使用已有的退出码
         // 否则使用线程的退出码
@@ -94,6 +154,7 @@ fn exit_thread(thread: &Thread) {
         if process.pid() == 1 {
             assert_eq!(children.len(), 0);
             SHUTDOWN.store(true, Ordering::SeqCst);
+            executor::wake_idle_harts();
         } else {
             INITPROC.lock_inner_with(|initproc_inner| {
                 for child in children {
@@ -108,7 +169,14 @@ fn exit_thread(thread: &Thread) {
         // 通知父进程自己退出了
         if let Some(parent) = parent {
             if let Some(exit_signal) = process.exit_signal {
-                parent.lock_inner_with(|inner| inner.receive_signal(exit_signal));
+                let status = i32::from(process.exit_code().unwrap_or(exit_code) as u8);
+                parent.receive_signal(KSigInfo::child(
+                    exit_signal,
+                    CLD_EXITED,
+                    process.pid(),
+                    uid,
+                    status,
+                ));
             }
 
             parent.wait4_event.notify(1);
@@ -116,6 +184,21 @@ fn exit_thread(thread: &Thread) {
     }
 }
 
+/// 在 `clear_child_tid` 处写入 0 并唤醒其上的一个等待者，`pthread_join()` 依赖于此。
+///
+/// 注意此时仍需处于该线程的地址空间中
+fn clear_tid_and_wake(clear_child_tid: usize) -> KResult<()> {
+    let key = futex::futex_key(clear_child_tid)?;
+    let tid_ptr = unsafe {
+        UserCheck::new(clear_child_tid as *mut u32)
+            .ok_or(errno::EFAULT)?
+            .check_ptr_mut()?
+    };
+    tid_ptr.write(0);
+    futex::wake(key, 1, FUTEX_BITSET_MATCH_ANY);
+    Ok(())
+}
+
 /// `UserThreadWrapperFuture` 用来处理用户线程获取控制权以及让出控制权时的上下文切换。如页表切换等
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 #[pin_project::pin_project]
@@ -150,6 +233,7 @@ impl Future for UserThreadWrapperFuture {
         if prev_status != ThreadStatus::Ready {
             panic!("Run unready({prev_status:?}) task")
         }
+        self.thread.time_stat.start();
 
         let project = self.project();
         let ret = project.future.poll(cx);
@@ -160,6 +244,7 @@ impl Future for UserThreadWrapperFuture {
             project.thread.set_status(ThreadStatus::Blocking);
         }
 
+        project.thread.time_stat.account_system();
         // NOTE: 一定要切换页表。否则进程页表被回收立刻导致内核异常
         unsafe {
             KERNEL_SPACE.activate_no_tlb();
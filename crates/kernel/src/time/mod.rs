@@ -1,17 +1,70 @@
 mod timer;
 
-use core::time::Duration;
+use core::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
 
+use common::{
+    config::{CLOCK_FREQ, TICKS_PER_SEC},
+    constant::NANO_PER_SEC,
+};
 use defines::misc::TimeSpec;
 
-pub use self::timer::{check_timer, sleep};
+pub use self::timer::{check_timer, program_timer, sleep, sleep_until};
+use crate::{drivers::goldfish_rtc, memory};
 
-/// 目前是返回自开机以来的 [`Duration`]
+/// 高精度时钟的精度，即硬件时钟的周期
+pub const RESOLUTION: Duration = Duration::from_nanos((NANO_PER_SEC / CLOCK_FREQ) as u64);
+/// 粗粒度时钟的精度，即调度时钟的间隔
+pub const COARSE_RESOLUTION: Duration = Duration::from_nanos((NANO_PER_SEC / TICKS_PER_SEC) as u64);
+
+/// 挂钟时间与单调时钟之差（纳秒），即开机时刻的挂钟时间。
+/// 如果挂钟被设置到了比开机时长更早的时刻，则为负数
+static REALTIME_OFFSET_NS: AtomicI64 = AtomicI64::new(0);
+
+/// 根据 RTC 初始化挂钟时间
+pub fn init() {
+    let rtc_ns = goldfish_rtc::read_ns();
+    set_realtime(Duration::from_nanos(rtc_ns));
+    info!("RTC time: {}s since epoch", rtc_ns / NANO_PER_SEC as u64);
+}
+
+/// 返回自开机以来的 [`Duration`]，即单调时钟
 pub fn curr_time() -> Duration {
     let curr_ns = riscv_time::get_time_ns();
     Duration::from_nanos(curr_ns as u64)
 }
 
+/// 挂钟时间，即自 Epoch 以来的时间
+pub fn realtime() -> Duration {
+    let ns = (riscv_time::get_time_ns() as i64)
+        .saturating_add(REALTIME_OFFSET_NS.load(Ordering::Relaxed));
+    Duration::from_nanos(ns.max(0) as u64)
+}
+
+/// 设置挂钟时间。单调时钟不受影响
+pub fn set_realtime(time: Duration) {
+    let ns = i64::try_from(time.as_nanos()).unwrap_or(i64::MAX);
+    let offset = ns.saturating_sub(riscv_time::get_time_ns() as i64);
+    REALTIME_OFFSET_NS.store(offset, Ordering::Relaxed);
+    memory::set_realtime_offset(offset);
+}
+
+/// 将挂钟时间转换为对应时刻的单调时钟时间。早于开机的时刻会被截断为 0，过远的时刻则截断为最大值
+pub fn realtime_to_monotonic(time: Duration) -> Duration {
+    // 用户给出的时间可能远超 `i64` 的范围，因此用 `i128` 计算
+    let ns = time.as_nanos() as i128 - i128::from(REALTIME_OFFSET_NS.load(Ordering::Relaxed));
+    Duration::from_nanos(ns.clamp(0, i128::from(u64::MAX)) as u64)
+}
+
+/// 将时间向下截断到粗粒度时钟的精度
+pub fn coarse(time: Duration) -> Duration {
+    let resolution = COARSE_RESOLUTION.as_nanos();
+    Duration::from_nanos((time.as_nanos() / resolution * resolution) as u64)
+}
+
+/// 当前的挂钟时间，用于文件时间戳等
 pub fn curr_time_spec() -> TimeSpec {
-    TimeSpec::from(curr_time())
+    TimeSpec::from(realtime())
 }
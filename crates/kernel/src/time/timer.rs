@@ -1,84 +1,164 @@
-use alloc::collections::BinaryHeap;
+use alloc::collections::BTreeMap;
 use core::{
-    cmp::{Ordering, Reverse},
     future::Future,
     pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
     task::{Context, Poll, Waker},
     time::Duration,
 };
 
+use common::config::MAX_HART_NUM;
+use crossbeam_utils::CachePadded;
 use klocks::SpinNoIrqMutex;
+use riscv_guard::NoIrqGuard;
 
+use crate::hart::local_hart;
+
+/// 每个 hart 的定时器队列。定时器注册在注册时所在的 hart 上，由该 hart 负责在到期时唤醒，
+/// 这样各个 hart 之间不会争抢同一把锁
+static TIMER_QUEUES: [CachePadded<TimerQueue>; MAX_HART_NUM] =
+    [const { CachePadded::new(TimerQueue::new()) }; MAX_HART_NUM];
+
+/// 用于区分到期时刻相同的定时器
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(0);
+
+struct TimerQueue {
+    /// 按 (到期时刻（纳秒）, 定时器 id) 排序
+    timers: SpinNoIrqMutex<BTreeMap<(u64, u64), Waker>>,
+    /// 本 hart 的定时器下一次触发的时刻（时钟次数），`u64::MAX` 表示没有设置
+    deadline: AtomicU64,
+}
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self {
+            timers: SpinNoIrqMutex::new(BTreeMap::new()),
+            deadline: AtomicU64::new(u64::MAX),
+        }
+    }
+}
+
+fn local_queue() -> &'static TimerQueue {
+    &TIMER_QUEUES[local_hart().hart_id()]
+}
+
+/// 设置本 hart 的定时器，`u64::MAX` 即不会触发
+fn set_deadline(deadline: u64) {
+    local_queue().deadline.store(deadline, Ordering::Relaxed);
+    riscv_time::set_timer(deadline);
+}
+
+fn ns_to_ticks(ns: u64) -> u64 {
+    riscv_time::ns_to_ticks(ns as usize) as u64
+}
+
+/// 在到期时刻完成的 Future。被 drop 时会从定时器队列中移除
 struct TimerFuture {
-    expire_ms: usize,
-    timer_activated: bool,
+    /// 到期时刻，即自开机以来的纳秒数
+    expire_ns: u64,
+    /// 已注册的定时器所在的 hart 及其在队列中的键
+    registered: Option<(usize, (u64, u64))>,
 }
 
 impl Future for TimerFuture {
     type Output = ();
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if self.expire_ms > riscv_time::get_time_ms() {
-            if !self.timer_activated {
-                TIMERS.lock().push(Reverse(Timer {
-                    expire_ms: self.expire_ms,
-                    waker: cx.waker().clone(),
-                }));
-                self.timer_activated = true;
+        if riscv_time::get_time_ns() as u64 >= self.expire_ns {
+            self.cancel();
+            return Poll::Ready(());
+        }
+        match self.registered {
+            // 任务可能迁移到了别的 hart 上，但定时器仍然留在原来的 hart 上
+            Some((hart_id, key)) => {
+                let mut timers = TIMER_QUEUES[hart_id].timers.lock();
+                match timers.get_mut(&key) {
+                    Some(waker) => waker.clone_from(cx.waker()),
+                    // 刚刚到期被移出了队列，唤醒已经在路上了
+                    None => cx.waker().wake_by_ref(),
+                }
+            }
+            None => {
+                let key = (
+                    self.expire_ns,
+                    NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed),
+                );
+                let queue = local_queue();
+                queue.timers.lock().insert(key, cx.waker().clone());
+                self.registered = Some((local_hart().hart_id(), key));
+                // 本 hart 的定时器可能设置得更晚，甚至没有设置
+                let _guard = NoIrqGuard::new();
+                let deadline = ns_to_ticks(self.expire_ns);
+                if deadline < queue.deadline.load(Ordering::Relaxed) {
+                    set_deadline(deadline);
+                }
             }
-            Poll::Pending
-        } else {
-            Poll::Ready(())
         }
+        Poll::Pending
     }
 }
 
-struct Timer {
-    expire_ms: usize,
-    waker: Waker,
-}
-
-impl PartialEq for Timer {
-    fn eq(&self, other: &Self) -> bool {
-        self.expire_ms == other.expire_ms
+impl TimerFuture {
+    fn cancel(&mut self) {
+        if let Some((hart_id, key)) = self.registered.take() {
+            TIMER_QUEUES[hart_id].timers.lock().remove(&key);
+        }
     }
 }
 
-impl Eq for Timer {}
-
-impl PartialOrd for Timer {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl Drop for TimerFuture {
+    fn drop(&mut self) {
+        self.cancel();
     }
 }
 
-impl Ord for Timer {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.expire_ms.cmp(&other.expire_ms)
+/// 唤醒本 hart 上所有已到期的定时器
+pub fn check_timer() {
+    let timers = &local_queue().timers;
+    let curr_ns = riscv_time::get_time_ns() as u64;
+    loop {
+        // 在锁外唤醒，唤醒时会操作任务队列
+        let waker = {
+            let mut timers = timers.lock();
+            match timers.first_entry() {
+                Some(entry) if entry.key().0 <= curr_ns => entry.remove(),
+                _ => break,
+            }
+        };
+        waker.wake();
     }
 }
 
-static TIMERS: SpinNoIrqMutex<BinaryHeap<Reverse<Timer>>> =
-    SpinNoIrqMutex::new(BinaryHeap::<Reverse<Timer>>::new());
-
-pub fn check_timer() {
-    let mut timers = TIMERS.lock();
-    let curr_ms = riscv_time::get_time_ms();
-    while let Some(timer) = timers.peek() {
-        if curr_ms >= timer.0.expire_ms {
-            let timer = timers.pop().unwrap();
-            timer.0.waker.wake();
-        } else {
-            break;
-        }
-    }
+/// 根据本 hart 上最早到期的定时器重新设置定时器。
+///
+/// `busy` 为真表示 hart 上有任务在运行，此时定时器最晚在一个调度时间片后触发，以便抢占。
+/// 空闲的 hart 则只在有定时器到期时才会被唤醒，没有定时器时不设置
+pub fn program_timer(busy: bool) {
+    let _guard = NoIrqGuard::new();
+    let earliest = local_queue()
+        .timers
+        .lock()
+        .first_key_value()
+        .map(|(&(expire_ns, _), _)| ns_to_ticks(expire_ns));
+    let quantum = busy.then(|| (riscv_time::get_time() + riscv_time::quantum_ticks()) as u64);
+    set_deadline(
+        earliest
+            .into_iter()
+            .chain(quantum)
+            .min()
+            .unwrap_or(u64::MAX),
+    );
 }
 
+/// 睡眠 `time` 长的时间
 pub fn sleep(time: Duration) -> impl Future<Output = ()> {
-    let curr_ms = riscv_time::get_time_ms();
-    let expire_ms = curr_ms + time.as_millis() as usize;
+    sleep_until(super::curr_time().saturating_add(time))
+}
+
+/// 睡眠直到 `deadline`，即自开机以来的时间
+pub fn sleep_until(deadline: Duration) -> impl Future<Output = ()> {
     TimerFuture {
-        expire_ms,
-        timer_activated: false,
+        expire_ns: u64::try_from(deadline.as_nanos()).unwrap_or(u64::MAX),
+        registered: None,
     }
 }
@@ -31,6 +31,10 @@ impl TrapContext {
         &mut self.user_regs[1]
     }
 
+    pub fn tp_mut(&mut self) -> &mut usize {
+        &mut self.user_regs[3]
+    }
+
     pub fn a0_mut(&mut self) -> &mut usize {
         &mut self.user_regs[9]
     }
@@ -39,6 +43,10 @@ impl TrapContext {
         &mut self.user_regs[10]
     }
 
+    pub fn a2_mut(&mut self) -> &mut usize {
+        &mut self.user_regs[11]
+    }
+
     pub fn ra_mut(&mut self) -> &mut usize {
         &mut self.user_regs[0]
     }
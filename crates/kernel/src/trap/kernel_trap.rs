@@ -5,7 +5,7 @@ use riscv::register::{
     stvec::{self, TrapMode},
 };
 
-use crate::time;
+use crate::{executor, hart, time};
 
 pub fn set_kernel_trap_entry() {
     extern "C" {
@@ -24,12 +24,17 @@ pub extern "C" fn kernel_trap_handler() {
             let _enter = debug_span!("timer_irq").entered();
             // TODO: 想办法通知线程让出 hart
             time::check_timer();
-            riscv_time::set_next_trigger();
+            // 空闲的 hart 在挂起时也会在这里处理定时器中断
+            time::program_timer(!executor::is_idle());
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
             let _enter = debug_span!("external_irq").entered();
             super::interrupt_handler();
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            let _enter = debug_span!("ipi").entered();
+            hart::handle_ipi();
+        }
         other => {
             panic!(
                 "Trap from kernel! Cause = {:?}, bad addr = {:#x}, bad instruction = {:#x}",
@@ -1,10 +1,13 @@
 mod context;
 mod kernel_trap;
 
-use core::ops::ControlFlow;
+use core::{ops::ControlFlow, time::Duration};
 
 pub use context::TrapContext;
-use defines::{error::errno, signal::SignalActionFlags};
+use defines::{
+    error::errno,
+    signal::{MContext, SignalActionFlags, SignalStack, UContext, SS_AUTODISARM},
+};
 use kernel_tracer::Instrument;
 use riscv::register::{
     scause::{self, Exception, Interrupt, Trap},
@@ -15,14 +18,15 @@ use riscv::register::{
 use crate::{
     drivers::{qemu_plic::Plic, qemu_uart::UART0, InterruptSource},
     executor,
-    hart::local_hart,
+    hart::{self, local_hart},
     memory::UserCheck,
     process::{self, exit_process},
     signal::{
-        DefaultHandler, KSignalActionExt, KSignalSet, SignalContext, SIG_DFL, SIG_ERR, SIG_IGN,
+        DefaultHandler, KSignalActionExt, KSignalSet, SignalFrame, SignalStackExt, SIG_DFL,
+        SIG_ERR, SIG_IGN,
     },
     syscall,
-    thread::Thread,
+    thread::{Thread, ThreadInner},
     time,
 };
 
@@ -71,7 +75,22 @@ pub async fn user_trap_handler() -> ControlFlow<(), ()> {
                 ControlFlow::Break(())
             } else {
                 let thread = local_hart().curr_thread();
-                thread.lock_inner_with(|inner| inner.trap_context.user_regs[9] = result as usize);
+                thread.lock_inner_with(|inner| {
+                    // 被信号打断的系统调用先按返回 `EINTR` 处理，处理信号时再决定是否要重启
+                    let result = if result == errno::ERESTARTSYS.as_isize()
+                        || result == errno::ERESTARTNOHAND.as_isize()
+                    {
+                        inner.syscall_restart = Some(SyscallRestart {
+                            only_without_handler: result == errno::ERESTARTNOHAND.as_isize(),
+                            a0: syscall_args[0],
+                            deadline: inner.restart_deadline.take(),
+                        });
+                        errno::EINTR.as_isize()
+                    } else {
+                        result
+                    };
+                    inner.trap_context.user_regs[9] = result as usize;
+                });
                 ControlFlow::Continue(())
             }
         }
@@ -124,9 +143,23 @@ pub async fn user_trap_handler() -> ControlFlow<(), ()> {
             {
                 let _enter = debug_span!("timer_irq").entered();
                 time::check_timer();
-                riscv_time::set_next_trigger();
+                time::program_timer(true);
+            }
+            {
+                let process = local_hart().curr_process();
+                process.check_cpu_limit();
+                process.check_cpu_timers();
+            }
+            preempt_if_needed().await;
+            ControlFlow::Continue(())
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            {
+                let _enter = debug_span!("ipi").entered();
+                hart::handle_ipi();
             }
-            executor::yield_now().await;
+            // IPI 可能是因为有新任务加入了本 hart 的队列
+            preempt_if_needed().await;
             ControlFlow::Continue(())
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
@@ -144,6 +177,14 @@ pub async fn user_trap_handler() -> ControlFlow<(), ()> {
     }
 }
 
+/// 如果队列中有更应该运行的任务，则让出 hart
+async fn preempt_if_needed() {
+    let preempt = executor::should_preempt(&local_hart().curr_thread().sched);
+    if preempt {
+        executor::yield_now().await;
+    }
+}
+
 /// 从用户任务的内核态返回到用户态。
 ///
 /// 注意：会切换控制流和栈
@@ -155,8 +196,6 @@ pub fn trap_return(trap_context: *mut TrapContext) {
     trace!("enter user mode");
     set_user_trap_entry();
 
-    check_signal(&local_hart().curr_thread());
-
     extern "C" {
         fn __return_to_user(cx: *mut TrapContext);
     }
@@ -169,22 +208,59 @@ pub fn trap_return(trap_context: *mut TrapContext) {
     }
 }
 
-/// 如果进程因为信号被终止了，则返回 true
+/// 被信号打断的系统调用，需要在处理信号时决定是重启它还是返回 `EINTR`。
+///
+/// - 若调用了 signal handler，则只有系统调用返回 `ERESTARTSYS` 且 handler 设置了 `SA_RESTART`
+///   时才重启
+/// - 否则（信号被忽略、停止进程等）总是重启
+#[derive(Clone, Copy, Debug)]
+pub struct SyscallRestart {
+    /// 系统调用返回的是 `ERESTARTNOHAND`，调用了 signal handler 时就不重启
+    pub only_without_handler: bool,
+    /// 系统调用原本的第一个参数，因为 a0 已经被返回值覆盖了
+    pub a0: usize,
+    /// 被打断的睡眠原本要睡眠到的时刻，见 [`ThreadInner::restart_deadline`]
+    pub deadline: Option<Duration>,
+}
+
+impl SyscallRestart {
+    /// 回退 `sepc` 到 `ecall` 指令并恢复 a0，这样返回用户态后会重新执行该系统调用
+    fn restart(self, inner: &mut ThreadInner) {
+        inner.trap_context.sepc -= 4;
+        *inner.trap_context.a0_mut() = self.a0;
+        inner.restart_deadline = self.deadline;
+    }
+}
+
+/// 若被打断的系统调用还没有因为 signal handler 而返回 `EINTR`，则重启它。应当在返回用户态之前调用
+///
+/// 同时会恢复 `rt_sigsuspend` 临时替换的信号掩码
+pub fn restart_syscall(thread: &Thread) {
+    thread.lock_inner_with(|inner| {
+        if let Some(mask) = inner.saved_signal_mask.take() {
+            inner.signal_mask = mask;
+        }
+        if let Some(restart) = inner.syscall_restart.take() {
+            debug!("restart syscall");
+            restart.restart(inner);
+        }
+    });
+}
+
+/// 处理线程的一个待处理信号。应当在返回用户态之前调用
+///
+/// 如果进程因为信号被终止了，则返回 true。进程也可能因此被停止，需要调用者检查
 pub fn check_signal(thread: &Thread) -> bool {
-    let first_pending = {
-        let mut inner = thread.lock_inner();
-        let pendings = inner.pending_signal.intersection(!inner.signal_mask);
-        let Some(first_pending) = pendings.first_pending() else {
-            return false;
-        };
-        inner.pending_signal.remove(KSignalSet::from(first_pending));
-        first_pending
+    let Some(info) = thread.lock_inner_with(|inner| inner.pending_signal.pop(inner.signal_mask))
+    else {
+        return false;
     };
+    let signal = info.signal;
 
-    debug!("handle signal {first_pending:?}");
+    debug!("handle signal {signal:?}");
     let action = thread
         .process
-        .lock_inner_with(|inner| inner.signal_handlers.action(first_pending).clone());
+        .lock_inner_with(|inner| inner.signal_handlers.action(signal).clone());
     trace!(
         "handler: {:#x}, mask: {:?}, flags: {:?}, restorer: {:#x}",
         action.handler,
@@ -195,72 +271,102 @@ pub fn check_signal(thread: &Thread) -> bool {
 
     let handler = match action.handler {
         SIG_ERR => todo!("[low] maybe there is no `SIG_ERR`"),
-        SIG_DFL => match DefaultHandler::new(first_pending) {
+        SIG_DFL => match DefaultHandler::new(signal) {
             DefaultHandler::Terminate | DefaultHandler::CoreDump => {
-                exit_process(
-                    &thread.process,
-                    (first_pending as i8).wrapping_add_unsigned(128),
-                );
+                exit_process(&thread.process, (signal as i8).wrapping_add_unsigned(128));
                 // TODO:[low] 要处理 CoreDump
                 return true;
             }
-            DefaultHandler::Ignore => return false,
-            DefaultHandler::Stop | DefaultHandler::Continue => {
-                // 被信号 stop 或者 continue 都要通知 `sys_wait4()`
-                todo!("[low] default handler Stop and Continue")
+            // 恢复进程在 `SIGCONT` 产生时就已经处理了
+            DefaultHandler::Ignore | DefaultHandler::Continue => return false,
+            DefaultHandler::Stop => {
+                thread.process.stop(signal);
+                return false;
             }
         },
         SIG_IGN => return false,
         handler => handler,
     };
 
-    let (old_mask, old_trap_context) = thread.lock_inner_with(|inner| {
-        let old_mask = inner.signal_mask;
-        let old_trap_context = inner.trap_context.clone();
+    // 在用户栈上构造 `SignalFrame`，保存当前上下文，按 16 字节对齐。
+    // 若指定了 `SA_ONSTACK` 且信号栈可用，则换到信号栈上（已经在信号栈上的话就继续用当前的栈）
+    let (frame, frame_addr) = thread.lock_inner_with(|inner| {
+        if let Some(restart) = inner.syscall_restart.take() {
+            if !restart.only_without_handler && action.flags.contains(SignalActionFlags::SA_RESTART)
+            {
+                restart.restart(inner);
+            }
+        }
+        // `rt_sigsuspend` 期间的掩码是临时的，handler 返回后应当恢复为原来的掩码
+        let old_mask = inner.saved_signal_mask.take().unwrap_or(inner.signal_mask);
         inner.signal_mask.insert(action.kmask());
         if !action.flags.contains(SignalActionFlags::SA_NODEFER) {
-            inner.signal_mask.set(KSignalSet::from(first_pending), true);
+            inner.signal_mask.insert(KSignalSet::from(signal));
         }
+
         let trap_context = &mut inner.trap_context;
+        let old_sp = trap_context.sp();
+        let signal_stack = inner.signal_stack;
+        let sp = if action.flags.contains(SignalActionFlags::SA_ONSTACK)
+            && signal_stack.is_enabled()
+            && !signal_stack.contains(old_sp)
+        {
+            signal_stack.ss_sp + signal_stack.ss_size
+        } else {
+            old_sp
+        };
+        if signal_stack.ss_flags & SS_AUTODISARM != 0 {
+            inner.signal_stack = SignalStack::default();
+        }
+
+        let mut regs = [0; 32];
+        regs[0] = trap_context.sepc;
+        regs[1..].copy_from_slice(&trap_context.user_regs);
+        let frame = SignalFrame {
+            info: info.to_user(),
+            ucontext: UContext::new(
+                signal_stack.to_user(old_sp),
+                old_mask.to_user(),
+                MContext::new(regs),
+            ),
+        };
+        let frame_addr = (sp - core::mem::size_of::<SignalFrame>()) & !0xf;
+
         trap_context.sepc = handler;
-        *trap_context.sp_mut() = trap_context.sp() - core::mem::size_of::<SignalContext>();
+        *trap_context.sp_mut() = frame_addr;
         *trap_context.ra_mut() = action.restorer;
-        *trap_context.a0_mut() = first_pending.to_user() as usize;
+        *trap_context.a0_mut() = signal.to_user() as usize;
+        // 没有 `SA_SIGINFO` 的 handler 只使用 a0，因此总是传入也无妨
+        *trap_context.a1_mut() = frame_addr + core::mem::offset_of!(SignalFrame, info);
+        *trap_context.a2_mut() = frame_addr + core::mem::offset_of!(SignalFrame, ucontext);
 
-        (old_mask, old_trap_context)
+        (frame, frame_addr)
     });
 
-    let signal_context = SignalContext {
-        old_mask,
-        old_trap_context,
-    };
-
-    let sp = signal_context.old_trap_context.sp() - core::mem::size_of::<SignalContext>();
     let user_ptr = (|| unsafe {
-        UserCheck::new(sp as *mut SignalContext)
+        UserCheck::new(frame_addr as *mut SignalFrame)
             .ok_or(errno::EINVAL)?
             .check_ptr_mut()
     })();
     if let Ok(user_ptr) = user_ptr {
-        user_ptr.write(signal_context);
+        user_ptr.write(frame);
         false
     } else {
-        exit_process(
-            &thread.process,
-            (first_pending as i8).wrapping_add_unsigned(128),
-        );
+        exit_process(&thread.process, (signal as i8).wrapping_add_unsigned(128));
         true
     }
 }
 
 pub fn init() {
     kernel_trap::set_kernel_trap_entry();
+    riscv_time::init();
     unsafe {
         sie::set_sext();
         sie::set_stimer();
+        sie::set_ssoft();
         sstatus::set_sie();
     }
-    riscv_time::set_next_trigger();
+    time::program_timer(true);
 }
 
 fn set_user_trap_entry() {
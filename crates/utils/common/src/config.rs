@@ -26,22 +26,31 @@ pub const PTE_PER_PAGE: usize = PAGE_SIZE / PTR_SIZE;
 /// 地址空间的最后一个字节
 pub const ADDR_END: usize = usize::MAX;
 
-/// 用户栈的大小
+/// 用户栈的默认大小，即 `RLIMIT_STACK` 的默认软上限
 pub const USER_STACK_SIZE: usize = 8 * MiB;
+/// 用户栈的最大大小。每个线程的用户栈在地址空间中都占据这么大的位置，因此 `RLIMIT_STACK` 超过它也没有意义
+pub const MAX_USER_STACK_SIZE: usize = 64 * MiB;
 
 /// mmap 开始寻找可映射段的起点，即低地址的 128GiB 处
 pub const MMAP_START: usize = 0x20_0000_0000;
 /// 低地址的末端，即 256GiB 处
 pub const LOW_ADDRESS_END: usize = 0x40_0000_0000;
+/// 信号 trampoline 所在页的地址，紧邻 mmap 区域之下
+pub const SIGNAL_TRAMPOLINE: usize = MMAP_START - PAGE_SIZE;
+/// vDSO 映像所在页的地址，紧邻信号 trampoline 之下
+pub const VDSO_BASE: usize = SIGNAL_TRAMPOLINE - PAGE_SIZE;
+/// vDSO 数据页的地址，紧邻 vDSO 映像之下。vDSO 中的代码通过 PC 相对寻址访问它
+pub const VDSO_DATA: usize = VDSO_BASE - PAGE_SIZE;
 
 /// 时钟频率。似乎由 qemu 中的 `RISCV_ACLINT_DEFAULT_TIMEBASE_FREQ` 宏定义
 pub const CLOCK_FREQ: usize = 10_000_000;
 
-/// 每秒的 Tick 数。即理想状况下每秒触发定时器中断的次数
+/// 每秒的 Tick 数。即忙碌的 hart 理想状况下每秒触发调度时钟中断的次数，空闲的 hart 只在有定时器到期时才触发
 pub const TICKS_PER_SEC: usize = 100;
 
 /// I/O 映射的起始地址和长度
 pub const MMIO: &[(usize, usize)] = &[
+    (QEMU_RTC_ADDR, 0x1000),    // RTC
     (QEMU_UART_ADDR, 0x1000),   // UART
     (QEMU_VIRTIO0, 0x1000),     // VIRTIO
     (0x0200_0000, 0x10000),     // CLINT
@@ -49,6 +58,8 @@ pub const MMIO: &[(usize, usize)] = &[
 ];
 
 pub const QEMU_UART_ADDR: usize = 0x1000_0000;
+/// Goldfish RTC
+pub const QEMU_RTC_ADDR: usize = 0x10_1000;
 pub const QEMU_PLIC_ADDR: usize = 0xc00_0000;
 pub const QEMU_VIRTIO0: usize = 0x1000_1000;
 
@@ -57,8 +68,8 @@ pub const MAX_HART_NUM: usize = 8;
 /// Hart 启动时的地址
 pub const HART_START_ADDR: usize = 0x8020_0000;
 
-/// 内核并发任务上限
-pub const TASK_LIMIT: usize = 256;
+/// 每个 hart 本地任务队列的容量，放不下的任务会进入全局队列
+pub const LOCAL_TASK_QUEUE_CAPACITY: usize = 256;
 
 /// 用户路径名最大长度
 pub const MAX_PATHNAME_LEN: usize = 4 * PAGE_SIZE;
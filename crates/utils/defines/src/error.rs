@@ -32,6 +32,8 @@ pub mod errno {
     declare_errno!(
         UNSUPPORTED, -1024, "Do not support",
         BREAK,       -1023, "Thread should exit",
+        ERESTARTSYS,    -512,   "Interrupted syscall, restart it if SA_RESTART is set",
+        ERESTARTNOHAND, -514,   "Interrupted syscall, restart it if no handler is called",
         
         EPERM,          -1,     "Operation not permitted.",
         ENOENT,         -2,     "No such file or directory.",
@@ -59,5 +61,6 @@ pub mod errno {
         ERANGE,         -34,    "Exceed range.",
         EOVERFLOW,      -75,    "Value too large for data type",
         ENAMETOOLONG,   -78,    "Filename too long",
+        ETIMEDOUT,      -110,   "Connection timed out.",
     );
 }
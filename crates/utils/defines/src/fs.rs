@@ -12,6 +12,9 @@ pub const SEEK_SET: usize = 0;
 pub const SEEK_CUR: usize = 1;
 pub const SEEK_END: usize = 2;
 
+/// eventfd 以信号量的方式读取，即每次读取只减 1
+pub const EFD_SEMAPHORE: u32 = 1;
+
 bitflags! {
     /// 一个 inode 的 mode。如文件类型、用户权限等
     #[derive(Clone, Copy, Debug, Default)]
@@ -32,25 +35,33 @@ bitflags! {
         /// 是 FIFO
         const FIFO          = 1 << 12;
 
-        // /// 是否设置 uid/gid/sticky
-        // // const S_ISUID = 1 << 11;
-        // // const S_ISGID = 1 << 10;
-        // // const S_ISVTX = 1 << 9;
-        // /// 所有者权限
-        // const S_IRWXU = Self::S_IRUSR.bits() | Self::S_IWUSR.bits() | Self::S_IXUSR.bits();
-        // const S_IRUSR = 1 << 8;
-        // const S_IWUSR = 1 << 7;
-        // const S_IXUSR = 1 << 6;
-        // /// 用户组权限
-        // const S_IRWXG = Self::S_IRGRP.bits() | Self::S_IWGRP.bits() | Self::S_IXGRP.bits();
-        // const S_IRGRP = 1 << 5;
-        // const S_IWGRP = 1 << 4;
-        // const S_IXGRP = 1 << 3;
-        // /// 其他用户权限
-        // const S_IRWXO = Self::S_IROTH.bits() | Self::S_IWOTH.bits() | Self::S_IXOTH.bits();
-        // const S_IROTH = 1 << 2;
-        // const S_IWOTH = 1 << 1;
-        // const S_IXOTH = 1 << 0;
+        /// 是否设置 uid/gid/sticky
+        const S_ISUID = 1 << 11;
+        const S_ISGID = 1 << 10;
+        const S_ISVTX = 1 << 9;
+        /// 所有者权限
+        const S_IRWXU = Self::S_IRUSR.bits() | Self::S_IWUSR.bits() | Self::S_IXUSR.bits();
+        const S_IRUSR = 1 << 8;
+        const S_IWUSR = 1 << 7;
+        const S_IXUSR = 1 << 6;
+        /// 用户组权限
+        const S_IRWXG = Self::S_IRGRP.bits() | Self::S_IWGRP.bits() | Self::S_IXGRP.bits();
+        const S_IRGRP = 1 << 5;
+        const S_IWGRP = 1 << 4;
+        const S_IXGRP = 1 << 3;
+        /// 其他用户权限
+        const S_IRWXO = Self::S_IROTH.bits() | Self::S_IWOTH.bits() | Self::S_IXOTH.bits();
+        const S_IROTH = 1 << 2;
+        const S_IWOTH = 1 << 1;
+        const S_IXOTH = 1 << 0;
+    }
+
+    /// 访问文件所需的权限，即 `R_OK`、`W_OK`、`X_OK`
+    #[derive(Clone, Copy, Debug)]
+    pub struct AccessFlags: u32 {
+        const READ      = 1 << 2;
+        const WRITE     = 1 << 1;
+        const EXECUTE   = 1 << 0;
     }
 
     #[derive(Debug)]
@@ -154,6 +165,13 @@ bitflags! {
     }
 }
 
+impl StatMode {
+    /// 取出 `mode` 中的权限位，包括 set-user-ID、set-group-ID 和 sticky 位
+    pub const fn permission(mode: u32) -> Self {
+        Self::from_bits_truncate(mode & 0o7777)
+    }
+}
+
 impl OpenFlags {
     pub fn with_read_only(self) -> Self {
         self.difference(Self::WRONLY | Self::RDWR)
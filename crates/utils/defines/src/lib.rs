@@ -11,5 +11,6 @@ pub mod fs;
 pub mod ioctl;
 pub mod misc;
 pub mod resource;
+pub mod sched;
 pub mod signal;
 pub mod syscall;
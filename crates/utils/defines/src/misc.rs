@@ -3,7 +3,7 @@
 use core::time::Duration;
 
 use bitflags::bitflags;
-use common::constant::NANO_PER_SEC;
+use common::constant::{MICRO_PER_SEC, NANO_PER_SEC};
 
 use crate::error::{errno, Error};
 
@@ -81,12 +81,32 @@ impl TryFrom<TimeSpec> for Duration {
 }
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
 
+impl From<Duration> for TimeVal {
+    fn from(value: Duration) -> Self {
+        Self {
+            sec: value.as_secs() as usize,
+            usec: value.subsec_micros() as usize,
+        }
+    }
+}
+
+impl TryFrom<TimeVal> for Duration {
+    type Error = Error;
+
+    fn try_from(value: TimeVal) -> Result<Self, Self::Error> {
+        if value.usec >= MICRO_PER_SEC {
+            return Err(errno::EINVAL);
+        }
+        Ok(Duration::new(value.sec as u64, (value.usec * 1000) as u32))
+    }
+}
+
 #[repr(C)]
 pub struct Tms {
     /// 当前进程的用户态时间
@@ -99,6 +119,55 @@ pub struct Tms {
     pub tms_cstime: usize,
 }
 
+/// `sys_clock_nanosleep`、`sys_timer_settime` 和 `sys_timerfd_settime` 的参数是绝对时间而不是相对时间
+pub const TIMER_ABSTIME: u32 = 1;
+
+/// 间隔定时器按真实时间计时，到期时发送 `SIGALRM`
+pub const ITIMER_REAL: u32 = 0;
+/// 间隔定时器按进程的用户态 CPU 时间计时，到期时发送 `SIGVTALRM`
+pub const ITIMER_VIRTUAL: u32 = 1;
+/// 间隔定时器按进程的 CPU 时间计时，到期时发送 `SIGPROF`
+pub const ITIMER_PROF: u32 = 2;
+
+/// 间隔定时器的值，用于 `sys_setitimer` 和 `sys_getitimer`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ITimerVal {
+    /// 到期后重新计时的间隔，为 0 表示只触发一次
+    pub it_interval: TimeVal,
+    /// 距离下一次到期的时间，为 0 表示停止
+    pub it_value: TimeVal,
+}
+
+/// POSIX 定时器的值，用于 `sys_timer_settime` 等
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ITimerSpec {
+    /// 到期后重新计时的间隔，为 0 表示只触发一次
+    pub it_interval: TimeSpec,
+    /// 距离下一次到期的时间（设置时也可以是绝对时刻），为 0 表示停止
+    pub it_value: TimeSpec,
+}
+
+/// `sys_getrusage` 统计调用进程自身
+pub const RUSAGE_SELF: i32 = 0;
+/// `sys_getrusage` 统计调用进程已回收的子进程
+pub const RUSAGE_CHILDREN: i32 = -1;
+/// `sys_getrusage` 统计调用线程自身
+pub const RUSAGE_THREAD: i32 = 1;
+
+/// 资源使用情况，用于 `sys_getrusage` 和 `sys_wait4`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rusage {
+    /// 用户态时间
+    pub ru_utime: TimeVal,
+    /// 内核态时间
+    pub ru_stime: TimeVal,
+    /// 之后的 `ru_maxrss`、`ru_minflt`、`ru_nvcsw` 等字段，目前均不统计，为 0
+    pub _rest: [isize; 14],
+}
+
 bitflags! {
     #[derive(Clone,Copy,Debug)]
     /// `sys_wait4` 的选项，描述等待方式
@@ -106,7 +175,7 @@ bitflags! {
         /// 如果没有符合条件的子进程，则立刻返回
         const WNOHANG = 1 << 0;
         /// 如果子线程被信号暂停，则也返回
-        const WIMTRACED = 1 << 1;
+        const WUNTRACED = 1 << 1;
         /// 如果子线程被信号恢复 (`SIGCONT`)，则也返回
         const WCONTINUED = 1 << 3;
     }
@@ -182,7 +251,31 @@ bitflags! {
         const CLONE_DETACHED = 1 << 22;
         // /// 与 sys_ptrace 相关，目前未用到
         // const CLONE_UNTRACED = 1 << 23;
-        // /// 要求在子任务的一个地址写入子任务的 tid
-        // const CLONE_CHILD_SETTID = 1 << 24;
+        /// 要求在子任务的一个地址写入子任务的 tid
+        const CLONE_CHILD_SETTID = 1 << 24;
     }
 }
+
+// `sys_futex` 的操作，即 `futex_op` 去掉 `FUTEX_PRIVATE_FLAG` 和 `FUTEX_CLOCK_REALTIME` 后的部分
+/// 若 futex 字的值仍为 `val`，则等待直到被唤醒
+pub const FUTEX_WAIT: u32 = 0;
+/// 唤醒至多 `val` 个等待者
+pub const FUTEX_WAKE: u32 = 1;
+/// 唤醒至多 `val` 个等待者，并将至多 `val2` 个剩余的等待者转移到 `uaddr2` 上
+pub const FUTEX_REQUEUE: u32 = 3;
+/// 同 `FUTEX_REQUEUE`，但会先检查 futex 字的值是否为 `val3`
+pub const FUTEX_CMP_REQUEUE: u32 = 4;
+/// 同 `FUTEX_WAIT`，但超时为绝对时间，且可以用 `val3` 指定掩码
+pub const FUTEX_WAIT_BITSET: u32 = 9;
+/// 同 `FUTEX_WAKE`，但只唤醒掩码与 `val3` 有交集的等待者
+pub const FUTEX_WAKE_BITSET: u32 = 10;
+
+/// 表示 futex 只在进程内使用。内核以物理地址区分 futex，因此可以忽略该标志
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// 表示 `FUTEX_WAIT_BITSET` 的超时以 `CLOCK_REALTIME` 计算，而非 `CLOCK_MONOTONIC`
+pub const FUTEX_CLOCK_REALTIME: u32 = 256;
+/// 匹配任意等待者的掩码
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// 附加组数量的上限
+pub const NGROUPS_MAX: usize = 65536;
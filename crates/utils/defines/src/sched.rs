@@ -0,0 +1,34 @@
+//! 调度相关的常量和结构体
+
+/// 普通的分时调度策略，即 `SCHED_NORMAL`
+pub const SCHED_OTHER: u32 = 0;
+/// 先进先出的实时调度策略
+pub const SCHED_FIFO: u32 = 1;
+/// 时间片轮转的实时调度策略
+pub const SCHED_RR: u32 = 2;
+/// 与调度策略按位或，表示 fork 出的子进程不继承实时策略和负的 nice 值
+pub const SCHED_RESET_ON_FORK: u32 = 0x4000_0000;
+
+/// 实时调度策略的最低优先级
+pub const MIN_RT_PRIO: u8 = 1;
+/// 实时调度策略的最高优先级
+pub const MAX_RT_PRIO: u8 = 99;
+
+/// 最小的 nice 值，对应最高的优先级
+pub const MIN_NICE: i8 = -20;
+/// 最大的 nice 值，对应最低的优先级
+pub const MAX_NICE: i8 = 19;
+
+/// `sys_setpriority` 和 `sys_getpriority` 的目标是进程
+pub const PRIO_PROCESS: u32 = 0;
+/// `sys_setpriority` 和 `sys_getpriority` 的目标是进程组
+pub const PRIO_PGRP: u32 = 1;
+/// `sys_setpriority` 和 `sys_getpriority` 的目标是某个用户的所有进程
+pub const PRIO_USER: u32 = 2;
+
+/// `sys_sched_setscheduler` 等使用的调度参数
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedParam {
+    pub sched_priority: i32,
+}
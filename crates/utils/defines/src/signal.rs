@@ -35,12 +35,16 @@ impl Default for KSignalAction {
 bitflags! {
     #[derive(Clone, Copy, Debug)]
     pub struct SignalActionFlags: u32 {
-        // const SA_NOCLDSTOP = 1;
+        /// 子进程停止或恢复时不发送 `SIGCHLD`
+        const SA_NOCLDSTOP = 1;
         // const SA_NOCLDWAIT = 2;
-        // const SA_SIGINFO = 4;
+        /// signal handler 接收三个参数，额外的两个分别是 `siginfo_t` 和 `ucontext_t` 的指针
+        const SA_SIGINFO = 4;
         const SA_RESTORER = 0x04_000_000;
-        // const SA_ONSTACK = 0x08_000_000;
-        // const SA_RESTART = 0x10_000_000;
+        /// 在 `sigaltstack` 设置的信号栈上执行 signal handler
+        const SA_ONSTACK = 0x08_000_000;
+        /// 被信号打断的系统调用在 signal handler 返回后自动重启，而不是返回 `EINTR`
+        const SA_RESTART = 0x10_000_000;
         /// 一般而言。执行一个 signal handler 时，会屏蔽自己这个信号。
         ///
         /// 若指定以下这个 flag 则不会。sigaction 中的 mask 仍有效
@@ -80,3 +84,205 @@ pub const SIGWINCH: u8 = 28;
 pub const SIGIO: u8 = 29;
 pub const SIGPWR: u8 = 30;
 pub const SIGSYS: u8 = 31;
+/// 实时信号的范围是 `SIGRTMIN..=SIGRTMAX`。注意 libc 会保留前几个实时信号自用
+pub const SIGRTMIN: u8 = 32;
+pub const SIGRTMAX: u8 = 64;
+
+// `si_code` 的取值。为正数时表示由内核产生，否则由用户产生
+
+/// 由 `kill` 等发送
+pub const SI_USER: i32 = 0;
+/// 由内核发送
+pub const SI_KERNEL: i32 = 0x80;
+/// 由 `sigqueue` 发送
+pub const SI_QUEUE: i32 = -1;
+/// 由 POSIX 定时器到期发送
+pub const SI_TIMER: i32 = -2;
+/// 由 `tkill` 或 `tgkill` 发送
+pub const SI_TKILL: i32 = -6;
+
+// `SIGCHLD` 的 `si_code`
+
+/// 子进程正常退出
+pub const CLD_EXITED: i32 = 1;
+/// 子进程被信号杀死
+pub const CLD_KILLED: i32 = 2;
+/// 子进程被信号杀死，并产生了 core dump
+pub const CLD_DUMPED: i32 = 3;
+/// 子进程被信号停止
+pub const CLD_STOPPED: i32 = 5;
+/// 子进程被 `SIGCONT` 恢复
+pub const CLD_CONTINUED: i32 = 6;
+
+/// 即 `siginfo_t`，大小固定为 128 字节。
+///
+/// 其中 `si_code` 之后是一个 union，这里只列出了常用的部分，具体含义取决于信号和 `si_code`：
+/// - `kill` 等：`si_pid`、`si_uid`
+/// - `sigqueue`：`si_pid`、`si_uid`、`si_value`
+/// - POSIX 定时器：`si_pid` 处为 `si_tid`，`si_uid` 处为 `si_overrun`，以及 `si_value`
+/// - `SIGCHLD`：`si_pid`、`si_uid`，`si_value` 处为 `si_status`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    pub si_pid: i32,
+    pub si_uid: u32,
+    pub si_value: usize,
+    _rest: [u64; 12],
+}
+
+impl SigInfo {
+    pub const fn new(si_signo: i32, si_code: i32) -> Self {
+        Self {
+            si_signo,
+            si_errno: 0,
+            si_code,
+            _pad: 0,
+            si_pid: 0,
+            si_uid: 0,
+            si_value: 0,
+            _rest: [0; 12],
+        }
+    }
+}
+
+// `sigev_notify` 的取值，即定时器等到期时如何通知
+/// 发送信号 `sigev_signo` 给进程
+pub const SIGEV_SIGNAL: i32 = 0;
+/// 不通知
+pub const SIGEV_NONE: i32 = 1;
+/// 创建线程执行回调，由用户库实现，内核不支持
+pub const SIGEV_THREAD: i32 = 2;
+/// 发送信号 `sigev_signo` 给线程 `sigev_notify_thread_id`
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// 即 `sigevent`，用于 `sys_timer_create`，大小固定为 64 字节
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SigEvent {
+    /// 随信号传递的值
+    pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    /// 仅用于 `SIGEV_THREAD_ID`
+    pub sigev_notify_thread_id: i32,
+    _pad: [i32; 11],
+}
+
+/// 即 `signalfd_siginfo`，是从 signalfd 中读出的记录，大小固定为 128 字节
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SignalFdSigInfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    pub ssi_pid: u32,
+    pub ssi_uid: u32,
+    pub ssi_fd: i32,
+    pub ssi_tid: u32,
+    pub ssi_band: u32,
+    pub ssi_overrun: u32,
+    pub ssi_trapno: u32,
+    /// `SIGCHLD` 的退出码或者信号
+    pub ssi_status: i32,
+    /// `sigqueue` 发送的值
+    pub ssi_int: i32,
+    /// `sigqueue` 发送的值
+    pub ssi_ptr: u64,
+    pub ssi_utime: u64,
+    pub ssi_stime: u64,
+    pub ssi_addr: u64,
+    _rest: [u8; 48],
+}
+
+impl SignalFdSigInfo {
+    pub const fn new(ssi_signo: u32, ssi_code: i32) -> Self {
+        Self {
+            ssi_signo,
+            ssi_errno: 0,
+            ssi_code,
+            ssi_pid: 0,
+            ssi_uid: 0,
+            ssi_fd: 0,
+            ssi_tid: 0,
+            ssi_band: 0,
+            ssi_overrun: 0,
+            ssi_trapno: 0,
+            ssi_status: 0,
+            ssi_int: 0,
+            ssi_ptr: 0,
+            ssi_utime: 0,
+            ssi_stime: 0,
+            ssi_addr: 0,
+            _rest: [0; 48],
+        }
+    }
+}
+
+// 信号栈的 `ss_flags`
+
+/// 当前正在信号栈上执行
+pub const SS_ONSTACK: i32 = 1;
+/// 信号栈被禁用
+pub const SS_DISABLE: i32 = 2;
+/// 进入 signal handler 时自动禁用信号栈，从 handler 返回时恢复
+pub const SS_AUTODISARM: i32 = 1 << 31;
+/// 信号栈的最小大小
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// 即 `stack_t`，描述信号栈
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SignalStack {
+    pub ss_sp: usize,
+    pub ss_flags: i32,
+    pub ss_size: usize,
+}
+
+/// 即 riscv64 的 `ucontext_t`，信号处理时保存在用户栈上
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct UContext {
+    pub uc_flags: usize,
+    pub uc_link: usize,
+    pub uc_stack: SignalStack,
+    pub uc_sigmask: u64,
+    /// libc 的 `sigset_t` 为 128 字节，剩下的部分不使用
+    _unused: [u8; 120],
+    pub uc_mcontext: MContext,
+}
+
+impl UContext {
+    pub const fn new(uc_stack: SignalStack, uc_sigmask: u64, uc_mcontext: MContext) -> Self {
+        Self {
+            uc_flags: 0,
+            uc_link: 0,
+            uc_stack,
+            uc_sigmask,
+            _unused: [0; 120],
+            uc_mcontext,
+        }
+    }
+}
+
+/// 即 riscv64 的 `mcontext_t`
+#[repr(C, align(16))]
+#[derive(Clone, Debug)]
+pub struct MContext {
+    /// 依次为 pc 以及 x1~x31
+    pub sc_regs: [usize; 32],
+    /// 浮点寄存器，目前内核不保存浮点上下文，因此不使用
+    pub sc_fpregs: [u64; 66],
+}
+
+impl MContext {
+    pub const fn new(sc_regs: [usize; 32]) -> Self {
+        Self {
+            sc_regs,
+            sc_fpregs: [0; 66],
+        }
+    }
+}
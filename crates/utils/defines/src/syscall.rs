@@ -13,6 +13,7 @@ macro_rules! declare_syscall_id {
 #[rustfmt::skip]
 declare_syscall_id!(
     GETCWD,             17,
+    EVENTFD2,           19,
     DUP,                23,
     DUP3,               24,
     FCNTL64,            25,
@@ -33,24 +34,70 @@ declare_syscall_id!(
     READV,              65,
     WRITEV,             66,
     PPOLL,              73,
+    SIGNALFD4,          74,
     NEWFSTATAT,         79,
     NEWFSTAT,           80,
+    TIMERFD_CREATE,     85,
+    TIMERFD_SETTIME,    86,
+    TIMERFD_GETTIME,    87,
     EXIT,               93,
     EXIT_GROUP,         94,
     SET_TID_ADDRESS,    96,
+    FUTEX,              98,
     NANOSLEEP,          101,
+    GETITIMER,          102,
+    SETITIMER,          103,
+    TIMER_CREATE,       107,
+    TIMER_GETTIME,      108,
+    TIMER_GETOVERRUN,   109,
+    TIMER_SETTIME,      110,
+    TIMER_DELETE,       111,
+    CLOCK_SETTIME,      112,
     CLOCK_GETTIME,      113,
+    CLOCK_GETRES,       114,
+    CLOCK_NANOSLEEP,    115,
+    SCHED_SETPARAM,     118,
+    SCHED_SETSCHEDULER, 119,
+    SCHED_GETSCHEDULER, 120,
+    SCHED_GETPARAM,     121,
+    SCHED_SETAFFINITY,  122,
+    SCHED_GETAFFINITY,  123,
     SCHED_YIELD,        124,
-    // KILL,               129,
+    SCHED_GET_PRIORITY_MAX, 125,
+    SCHED_GET_PRIORITY_MIN, 126,
+    KILL,               129,
+    TKILL,              130,
+    TGKILL,             131,
+    SIGALTSTACK,        132,
+    RT_SIGSUSPEND,      133,
     RT_SIGACTION,       134,
     RT_SIGPROCMASK,     135,
+    RT_SIGPENDING,      136,
+    RT_SIGTIMEDWAIT,    137,
+    RT_SIGQUEUEINFO,    138,
     RT_SIGRETURN,       139,
     SETPRIORITY,        140,
+    GETPRIORITY,        141,
+    SETGID,             144,
+    SETUID,             146,
+    SETRESUID,          147,
+    GETRESUID,          148,
+    SETRESGID,          149,
+    GETRESGID,          150,
     TIMES,              153,
     SETPGID,            154,
     GETPGID,            155,
+    GETSID,             156,
+    SETSID,             157,
+    GETGROUPS,          158,
+    SETGROUPS,          159,
     UNAME,              160,
+    GETRLIMIT,          163,
+    SETRLIMIT,          164,
+    GETRUSAGE,          165,
+    GETCPU,             168,
     GET_TIME_OF_DAY,    169,
+    SETTIMEOFDAY,       170,
     GETPID,             172,
     GETPPID,            173,
     GETUID,             174,
@@ -63,5 +110,7 @@ declare_syscall_id!(
     CLONE,              220,
     EXECVE,             221,
     MMAP,               222,
+    RT_TGSIGQUEUEINFO,  240,
     WAIT4,              260,
+    PRLIMIT64,          261,
 );